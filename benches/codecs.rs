@@ -0,0 +1,161 @@
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, pin::Pin};
+use tokio_serde::{formats::*, Deserializer, Serializer};
+
+/// A heartbeat-sized message made of a few scalars.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Small {
+    id: u64,
+    seq: u32,
+    ok: bool,
+}
+
+/// A typical request with strings, a list and a map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Medium {
+    id: u64,
+    method: String,
+    path: String,
+    tags: Vec<String>,
+    headers: HashMap<String, String>,
+    scores: Vec<f64>,
+}
+
+/// A batch of records carrying binary payloads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Large {
+    records: Vec<Record>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Record {
+    key: String,
+    #[serde(with = "serde_bytes_vec")]
+    payload: Vec<u8>,
+    offsets: Vec<u32>,
+}
+
+/// Writes `Vec<u8>` as a byte string instead of a sequence of integers.
+mod serde_bytes_vec {
+    use serde::{de, Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> de::Visitor<'de> for BytesVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte array")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(v)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+fn small() -> Small {
+    Small {
+        id: 0x0123_4567_89ab_cdef,
+        seq: 42,
+        ok: true,
+    }
+}
+
+fn medium() -> Medium {
+    Medium {
+        id: 7,
+        method: "POST".to_owned(),
+        path: "/api/v1/orders/12345/items".to_owned(),
+        tags: (0..16).map(|i| format!("tag-{}", i)).collect(),
+        headers: (0..8)
+            .map(|i| (format!("x-header-{}", i), format!("value-{}", i)))
+            .collect(),
+        scores: (0..32).map(|i| f64::from(i) * 0.5).collect(),
+    }
+}
+
+fn large() -> Large {
+    Large {
+        records: (0..64)
+            .map(|i| Record {
+                key: format!("record-{:04}", i),
+                payload: vec![i as u8; 4096],
+                offsets: (0..64).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Serializes `value` and decodes it back, as a `Framed` would on both ends
+/// of a connection.
+fn round_trip<C, T>(codec: &mut C, value: &T) -> T
+where
+    C: Serializer<T> + Deserializer<T> + Unpin,
+    <C as Serializer<T>>::Error: std::fmt::Debug,
+    <C as Deserializer<T>>::Error: std::fmt::Debug,
+{
+    let buf = Pin::new(&mut *codec).serialize(value).unwrap();
+    Pin::new(codec)
+        .deserialize(&BytesMut::from(&buf[..]))
+        .unwrap()
+}
+
+fn bench_shape<T>(c: &mut Criterion, shape: &str, value: T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug + Unpin,
+{
+    let mut group = c.benchmark_group(shape);
+
+    macro_rules! bench_codec {
+        ($name:expr, $codec:expr) => {{
+            let mut codec = $codec;
+            assert_eq!(round_trip(&mut codec, &value), value);
+
+            let len = Pin::new(&mut codec).serialize(&value).unwrap().len();
+            group.throughput(Throughput::Bytes(len as u64));
+            group.bench_with_input(BenchmarkId::new("round_trip", $name), &value, |b, value| {
+                b.iter(|| round_trip(&mut codec, value))
+            });
+        }};
+    }
+
+    bench_codec!("json", SymmetricalJson::<T>::default());
+    bench_codec!("bincode", SymmetricalBincode::<T>::default());
+    bench_codec!("cbor", SymmetricalCbor::<T>::default());
+    bench_codec!("messagepack", SymmetricalMessagePack::<T>::default());
+
+    group.finish();
+}
+
+fn codecs(c: &mut Criterion) {
+    bench_shape(c, "small", small());
+    bench_shape(c, "medium", medium());
+    bench_shape(c, "large", large());
+}
+
+criterion_group!(benches, codecs);
+criterion_main!(benches);
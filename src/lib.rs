@@ -48,16 +48,24 @@
 
 extern crate core;
 
-use bytes::{Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_channel::mpsc;
 use futures_core::{ready, Stream, TryStream};
 use futures_sink::Sink;
 use pin_project::pin_project;
 use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fmt, io,
     marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
 };
 
+/// A `Result` whose error defaults to [`io::Error`], the error type used by
+/// the built-in codecs.
+pub type Result<T, E = io::Error> = std::result::Result<T, E>;
+
 /// Serializes a value into a destination buffer
 ///
 /// Implementations of `Serializer` are able to take values of type `T` and
@@ -75,7 +83,6 @@ use std::{
 /// use tokio_serde::Serializer;
 /// use bytes::{Buf, Bytes, BytesMut, BufMut};
 /// use std::pin::Pin;
-/// use bincode_crate::Options;
 ///
 /// struct IntSerializer {
 ///     width: usize,
@@ -124,6 +131,76 @@ pub trait Serializer<T> {
     ///
     /// See the trait level docs for more detail.
     fn serialize(self: Pin<&mut Self>, item: &T) -> Result<Bytes, Self::Error>;
+
+    /// Serializes `item` into a new mutable buffer, after `headroom` bytes
+    ///
+    /// The returned buffer starts with `headroom` zeroed bytes, followed by
+    /// the encoding. This lets callers fill in a header, e.g. a length
+    /// prefix, in front of the serialized bytes without moving them. The
+    /// default implementation copies the output of
+    /// [`serialize`](Self::serialize). Implementations able to write to a
+    /// `BytesMut` directly should override this to avoid the copy.
+    fn serialize_mut(
+        self: Pin<&mut Self>,
+        item: &T,
+        headroom: usize,
+    ) -> Result<BytesMut, Self::Error> {
+        let bytes = self.serialize(item)?;
+
+        let mut frame = with_headroom(headroom, bytes.len());
+        frame.extend_from_slice(&bytes);
+        Ok(frame)
+    }
+
+    /// Serializes `item` onto the end of `buf`
+    ///
+    /// This lets callers bring their own buffer, e.g. one taken from a pool,
+    /// instead of getting a freshly allocated one. The default implementation
+    /// copies the output of [`serialize`](Self::serialize) into `buf`.
+    /// Implementations able to write to any [`BufMut`] directly should
+    /// override this to avoid the copy. If `buf` can't hold the encoding, an
+    /// [`io::ErrorKind::WriteZero`] error is returned, so the codec's error
+    /// must be convertible from [`io::Error`]. If an error is returned, `buf`
+    /// may hold part of the encoding.
+    ///
+    /// The `From<io::Error>` bound means this method isn't available on codecs
+    /// reporting a foreign error type, such as `Json` without the
+    /// `unified-error` feature, whose error is `serde_json::Error`. Use
+    /// [`serialize_mut`](Self::serialize_mut) with those instead.
+    fn serialize_to_buf<B: BufMut>(
+        self: Pin<&mut Self>,
+        item: &T,
+        buf: &mut B,
+    ) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+        Self::Error: From<io::Error>,
+    {
+        let bytes = self.serialize(item)?;
+        put_frame(buf, &bytes)?;
+        Ok(())
+    }
+}
+
+/// Returns a buffer holding `headroom` zeroed bytes, with room for `len` more.
+pub(crate) fn with_headroom(headroom: usize, len: usize) -> BytesMut {
+    let mut frame = BytesMut::with_capacity(headroom + len);
+    frame.resize(headroom, 0);
+    frame
+}
+
+/// Copies `frame` onto the end of `buf`, failing with
+/// [`io::ErrorKind::WriteZero`] if it doesn't fit.
+pub(crate) fn put_frame<B: BufMut>(buf: &mut B, frame: &[u8]) -> io::Result<()> {
+    if buf.remaining_mut() < frame.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "buffer is too small for the serialized value",
+        ));
+    }
+
+    buf.put_slice(frame);
+    Ok(())
 }
 
 /// Deserializes a value from a source buffer
@@ -148,7 +225,6 @@ pub trait Serializer<T> {
 /// use tokio_serde::Deserializer;
 /// use bytes::{BytesMut, Buf};
 /// use std::pin::Pin;
-/// use bincode_crate::Options;
 ///
 /// struct IntDeserializer {
 ///     width: usize,
@@ -195,6 +271,157 @@ pub trait Deserializer<T> {
     ///
     /// See the trait level docs for more detail.
     fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<T, Self::Error>;
+
+    /// Deserializes a value from an owned `buf`
+    ///
+    /// [`Framed`] hands every frame read from the transport to this function.
+    /// The default implementation calls [`deserialize`](Self::deserialize).
+    /// Implementations able to borrow from the frame instead of copying out
+    /// of it can override this to take ownership of the buffer.
+    fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<T, Self::Error> {
+        self.deserialize(&src)
+    }
+
+    /// Deserializes a value from `buf` into an existing `place`
+    ///
+    /// This lets hot loops reuse the allocations of a previously decoded
+    /// value, e.g. the buffer of a large `Vec`. The default implementation
+    /// replaces `place` with the output of [`deserialize`](Self::deserialize).
+    /// Implementations backed by serde can override this to decode in place.
+    /// If an error is returned, `place` may be left partially updated.
+    fn deserialize_in_place(
+        self: Pin<&mut Self>,
+        src: &BytesMut,
+        place: &mut T,
+    ) -> Result<(), Self::Error> {
+        *place = self.deserialize(src)?;
+        Ok(())
+    }
+}
+
+/// A codec able to both decode `Item`s and encode `SinkItem`s, reporting the
+/// same error type for both.
+///
+/// This is a shorthand for
+/// `Serializer<SinkItem> + Deserializer<Item, Error = ...>` in generic code.
+/// It is implemented for every type implementing both halves with a shared
+/// error type, and is not meant to be implemented by hand.
+///
+/// # Examples
+///
+/// ```
+/// use bytes::BytesMut;
+/// use std::pin::Pin;
+/// use tokio_serde::{Codec, Ping, Serializer, Deserializer};
+///
+/// fn round_trip<C, T>(codec: &mut C, item: &T) -> Result<T, <C as Serializer<T>>::Error>
+/// where
+///     C: Codec<T, T> + Unpin,
+/// {
+///     let buf = Pin::new(&mut *codec).serialize(item)?;
+///     Pin::new(codec).deserialize(&BytesMut::from(&buf[..]))
+/// }
+///
+/// round_trip(&mut Ping, &()).unwrap();
+/// ```
+pub trait Codec<Item, SinkItem>:
+    Serializer<SinkItem> + Deserializer<Item, Error = <Self as Serializer<SinkItem>>::Error>
+{
+}
+
+impl<C, Item, SinkItem> Codec<Item, SinkItem> for C where
+    C: Serializer<SinkItem> + Deserializer<Item, Error = <C as Serializer<SinkItem>>::Error>
+{
+}
+
+/// Codec for empty ping frames.
+///
+/// Serializing `()` yields an empty buffer and deserializing an empty buffer
+/// yields `()`, whatever format the rest of the connection uses. Non-empty
+/// frames are rejected with [`io::ErrorKind::InvalidData`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ping;
+
+impl Serializer<()> for Ping {
+    type Error = io::Error;
+
+    fn serialize(self: Pin<&mut Self>, _item: &()) -> Result<Bytes, Self::Error> {
+        Ok(Bytes::new())
+    }
+}
+
+impl Deserializer<()> for Ping {
+    type Error = io::Error;
+
+    fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<(), Self::Error> {
+        if src.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ping frame is not empty",
+            ))
+        }
+    }
+}
+
+/// Codec passing frames through as [`Bytes`], for formats parsed by hand.
+///
+/// Deserializing yields the frame's contents with the transport's framing
+/// already stripped, and serializing sends a `Bytes` value as a frame
+/// unchanged. Frames handed over by [`Framed`] are frozen in place rather
+/// than copied, so the returned `Bytes` is a cheap, cloneable slice of the
+/// read buffer.
+///
+/// For payloads that are sometimes borrowed and sometimes owned, `Slice` also
+/// sends `Cow<[u8]>` as is and decodes frames into `Cow::Owned`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Slice;
+
+impl Serializer<Bytes> for Slice {
+    type Error = io::Error;
+
+    fn serialize(self: Pin<&mut Self>, item: &Bytes) -> Result<Bytes, Self::Error> {
+        Ok(item.clone())
+    }
+}
+
+impl Deserializer<Bytes> for Slice {
+    type Error = io::Error;
+
+    fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Bytes, Self::Error> {
+        Ok(Bytes::copy_from_slice(src))
+    }
+
+    fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Bytes, Self::Error> {
+        Ok(src.freeze())
+    }
+}
+
+impl Serializer<Cow<'_, [u8]>> for Slice {
+    type Error = io::Error;
+
+    fn serialize(self: Pin<&mut Self>, item: &Cow<'_, [u8]>) -> Result<Bytes, Self::Error> {
+        Ok(Bytes::copy_from_slice(item))
+    }
+}
+
+impl Deserializer<Cow<'static, [u8]>> for Slice {
+    type Error = io::Error;
+
+    fn deserialize(
+        self: Pin<&mut Self>,
+        src: &BytesMut,
+    ) -> Result<Cow<'static, [u8]>, Self::Error> {
+        Ok(Cow::Owned(src.to_vec()))
+    }
+
+    fn deserialize_owned(
+        self: Pin<&mut Self>,
+        src: BytesMut,
+    ) -> Result<Cow<'static, [u8]>, Self::Error> {
+        Ok(Cow::Owned(Vec::from(src)))
+    }
 }
 
 /// Adapts a transport to a value sink by serializing the values and to a stream of values by deserializing them.
@@ -222,6 +449,8 @@ pub struct Framed<Transport, Item, SinkItem, Codec> {
     #[pin]
     codec: Codec,
     item: PhantomData<(Item, SinkItem)>,
+    pending: bool,
+    on_serialize_error: SkipOrFail,
 }
 
 impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec> {
@@ -231,9 +460,37 @@ impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
             inner,
             codec,
             item: PhantomData,
+            pending: false,
+            on_serialize_error: SkipOrFail::Fail,
         }
     }
 
+    /// Returns `true` if a frame was sent since the last successful flush.
+    ///
+    /// This is a best-effort signal that the transport likely holds buffered
+    /// data, useful for adaptive flushing. It does not account for buffering
+    /// done by the transport on its own, e.g. after writes through
+    /// [`get_mut`](Self::get_mut).
+    ///
+    /// Flushing the `Framed` as a [`Sink`] while this is `false` returns
+    /// right away without reaching the transport, so a select loop can flush
+    /// on every iteration for free. Use
+    /// [`poll_transport_flush`](Self::poll_transport_flush) to flush the
+    /// transport regardless.
+    pub fn has_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Sets what `start_send` does when the codec fails to serialize a value.
+    ///
+    /// With [`SkipOrFail::Skip`], the value is dropped, `start_send` returns
+    /// `Ok(())` and the connection stays usable. With the `tracing` feature,
+    /// each dropped value is logged at the `warn` level. Defaults to
+    /// [`SkipOrFail::Fail`].
+    pub fn on_serialize_error(&mut self, policy: SkipOrFail) {
+        self.on_serialize_error = policy;
+    }
+
     /// Returns a reference to the underlying transport wrapped by `Framed`.
     ///
     /// Note that care should be taken to not tamper with the underlying transport as
@@ -258,6 +515,384 @@ impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
     pub fn into_inner(self) -> Transport {
         self.inner
     }
+
+    /// Swaps in `new` as the underlying transport, returning the old one.
+    ///
+    /// The codec and the options of the `Framed` are kept, which makes this
+    /// the building block of reconnect logic: once a connection fails, wrap a
+    /// fresh one the same way and carry on with the same `Framed`.
+    ///
+    /// Whatever the old transport still buffers, in either direction, stays
+    /// with it and is lost unless the caller drains the returned transport.
+    /// [`has_pending`](Self::has_pending) starts over as `false`.
+    /// A stateful codec, e.g. one tracking the last value it sent, is not
+    /// reset and may need the peer to start over as well.
+    pub fn replace_transport(&mut self, new: Transport) -> Transport {
+        self.pending = false;
+        std::mem::replace(&mut self.inner, new)
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+{
+    /// Calls [`Sink::poll_ready`] on the underlying transport, bypassing the
+    /// codec.
+    ///
+    /// This is an escape hatch for coordinating with other users of the
+    /// transport. Writing to the transport directly once it is ready may
+    /// interleave foreign bytes with the frames sent through `Framed` and
+    /// desync the peer.
+    pub fn poll_transport_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Transport::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    /// Calls [`Sink::poll_flush`] on the underlying transport, bypassing the
+    /// codec.
+    ///
+    /// Frames already handed to the transport are flushed along with anything
+    /// written to it directly, so [`has_pending`](Self::has_pending) is
+    /// cleared on success.
+    pub fn poll_transport_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Transport::Error>> {
+        let this = self.project();
+
+        ready!(this.inner.poll_flush(cx))?;
+        *this.pending = false;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Calls [`Sink::poll_close`] on the underlying transport, bypassing the
+    /// codec.
+    ///
+    /// Unlike closing the `Framed` itself, this does not flush first, so any
+    /// buffering the transport does not handle on close is lost.
+    pub fn poll_transport_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Transport::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+
+    /// Sends an already serialized frame, bypassing the codec.
+    ///
+    /// This is meant for forwarding, e.g. a proxy relaying frames it read
+    /// from another `Framed` with the same codec, where decoding and
+    /// re-encoding them would be wasted work. Like [`Sink::start_send`], it
+    /// must be preceded by a successful `poll_ready`, and the frame goes out
+    /// on the next flush.
+    ///
+    /// Nothing checks that `bytes` is something the codec could have
+    /// produced. Frames the peer's codec can't decode, or that a stateful
+    /// codec didn't see on the way out, e.g. one tracking the last value it
+    /// sent, can make the peer fail or drift out of sync.
+    pub fn send_raw(self: Pin<&mut Self>, bytes: Bytes) -> Result<(), Transport::Error> {
+        let this = self.project();
+        this.inner.start_send(bytes)?;
+        *this.pending = true;
+
+        Ok(())
+    }
+
+    /// Flushes and closes the transport, giving up after `timeout`.
+    ///
+    /// Frames already sent through `Framed` are flushed before the transport
+    /// is closed, so a clean shutdown delivers everything that was queued. If
+    /// the peer stalls and the two steps don't finish within `timeout`, the
+    /// transport is dropped and [`io::ErrorKind::TimedOut`] is returned.
+    ///
+    /// The timer runs on the Tokio runtime, so this must be awaited from
+    /// within one.
+    #[cfg(feature = "time")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+    pub async fn shutdown_with_timeout(
+        self,
+        timeout: std::time::Duration,
+    ) -> Result<(), Transport::Error>
+    where
+        Transport::Error: From<io::Error>,
+    {
+        let framed = self;
+        tokio::pin!(framed);
+
+        let shutdown = std::future::poll_fn(|cx| {
+            ready!(framed.as_mut().poll_transport_flush(cx))?;
+            framed.as_mut().poll_transport_close(cx)
+        });
+
+        match tokio::time::timeout(timeout, shutdown).await {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "transport did not shut down before the deadline",
+            )
+            .into()),
+        }
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes> + Unpin,
+    Codec: Serializer<SinkItem> + Unpin,
+    Codec::Error: Into<Transport::Error>,
+{
+    /// Polls whether the sink can accept another item, without pinning.
+    ///
+    /// A convenience for calling [`Sink::poll_ready`] on an unpinned `Framed`
+    /// from hand-written state machines.
+    pub fn poll_ready_unpin(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Transport::Error>> {
+        Pin::new(self).poll_ready(cx)
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
+where
+    Transport:
+        TryStream<Ok = BytesMut> + Sink<Bytes, Error = <Transport as TryStream>::Error> + Unpin,
+    <Transport as TryStream>::Error: From<io::Error>,
+{
+    /// Checks that the peer uses the same message type before any value is
+    /// exchanged.
+    ///
+    /// Both sides send `fingerprint` in a frame of its own, bypassing the
+    /// codec, and read the peer's. Fingerprints identify the message type,
+    /// e.g. one computed with [`fingerprint::of`] or a constant bumped on
+    /// every incompatible change. If they differ, an
+    /// [`io::ErrorKind::InvalidData`] error wrapping [`FingerprintMismatch`]
+    /// is returned, which is easier to act on than the decode errors version
+    /// skew causes otherwise. Both peers must call this first thing after
+    /// connecting.
+    ///
+    /// [`fingerprint::of`]: crate::fingerprint::of
+    pub async fn negotiate(
+        &mut self,
+        fingerprint: u64,
+    ) -> Result<(), <Transport as TryStream>::Error> {
+        let mut transport = Pin::new(&mut self.inner);
+
+        std::future::poll_fn(|cx| transport.as_mut().poll_ready(cx)).await?;
+        transport
+            .as_mut()
+            .start_send(Bytes::copy_from_slice(&fingerprint.to_be_bytes()))?;
+        std::future::poll_fn(|cx| transport.as_mut().poll_flush(cx)).await?;
+
+        let frame = std::future::poll_fn(|cx| transport.as_mut().try_poll_next(cx))
+            .await
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "transport closed before the peer's fingerprint arrived",
+                )
+            })??;
+
+        if frame.len() != 8 {
+            let err = io::Error::new(io::ErrorKind::InvalidData, "malformed fingerprint frame");
+            return Err(err.into());
+        }
+
+        let remote = (&frame[..]).get_u64();
+        if remote != fingerprint {
+            let err = FingerprintMismatch {
+                local: fingerprint,
+                remote,
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    Codec: Deserializer<Item>,
+{
+    /// Decodes every frame the transport has ready into `out`, returning how
+    /// many were added.
+    ///
+    /// Frames are read until the transport returns `Poll::Pending` or ends,
+    /// which saves a round trip through the caller per value on bursty
+    /// inputs. `Poll::Pending` is only returned if no frame was ready at all;
+    /// `Poll::Ready(Ok(0))` means the transport has ended.
+    ///
+    /// If reading or decoding a frame fails, the values decoded before it stay
+    /// in `out` and the returned [`DrainError`] tells how many there were.
+    pub fn poll_drain(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut Vec<Item>,
+    ) -> Poll<Result<usize, DrainError<Transport::Error>>> {
+        let mut decoded = 0;
+
+        loop {
+            match self.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(item))) => {
+                    out.push(item);
+                    decoded += 1;
+                }
+                Poll::Ready(Some(Err(error))) => {
+                    return Poll::Ready(Err(DrainError { decoded, error }))
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(decoded)),
+                Poll::Pending if decoded == 0 => return Poll::Pending,
+                Poll::Pending => return Poll::Ready(Ok(decoded)),
+            }
+        }
+    }
+}
+
+/// An error that interrupted [`Framed::poll_drain`].
+#[derive(Debug)]
+pub struct DrainError<E> {
+    decoded: usize,
+    error: E,
+}
+
+impl<E> DrainError<E> {
+    /// Returns how many values were decoded into the output before the error.
+    ///
+    /// This is also the index of the failed frame among the drained ones.
+    pub fn decoded(&self) -> usize {
+        self.decoded
+    }
+
+    /// Returns a reference to the underlying error.
+    pub fn get_ref(&self) -> &E {
+        &self.error
+    }
+
+    /// Consumes the `DrainError`, returning the underlying error.
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for DrainError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to drain frame {}: {}", self.decoded, self.error)
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for DrainError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// A run of frames that failed to decode, reported by a [`CheckedFramed`]
+/// with [`coalesce_decode_errors`](CheckedFramed::coalesce_decode_errors)
+/// enabled.
+///
+/// It is wrapped in an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData) and can be recovered with
+/// [`io::Error::get_ref`] and a downcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeErrors {
+    count: usize,
+}
+
+impl DecodeErrors {
+    /// Returns how many consecutive frames were skipped.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    fn into_io_error(count: usize) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, DecodeErrors { count })
+    }
+}
+
+impl fmt::Display for DecodeErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped {} frames that failed to decode", self.count)
+    }
+}
+
+impl std::error::Error for DecodeErrors {}
+
+/// The fingerprints exchanged by [`Framed::negotiate`] didn't match.
+///
+/// It is wrapped in an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData) and can be recovered with
+/// [`io::Error::get_ref`] and a downcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FingerprintMismatch {
+    local: u64,
+    remote: u64,
+}
+
+impl FingerprintMismatch {
+    /// Returns the fingerprint sent by this side.
+    pub fn local(&self) -> u64 {
+        self.local
+    }
+
+    /// Returns the fingerprint sent by the peer.
+    pub fn remote(&self) -> u64 {
+        self.remote
+    }
+}
+
+impl fmt::Display for FingerprintMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "peer uses a different message type: local fingerprint {:016x}, remote {:016x}",
+            self.local, self.remote
+        )
+    }
+}
+
+impl std::error::Error for FingerprintMismatch {}
+
+/// What a [`Framed`] sink does with a value its codec fails to serialize, set
+/// with [`Framed::on_serialize_error`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SkipOrFail {
+    /// Drop the value and carry on.
+    Skip,
+    /// Return the codec's error from `start_send`.
+    #[default]
+    Fail,
+}
+
+/// The next frame decoded by [`Framed::poll_decode`], or the codec's error
+/// for it, or the transport's error.
+type PollDecoded<Item, CodecError, TransportError> =
+    Poll<Option<Result<Result<Item, CodecError>, TransportError>>>;
+
+impl<Transport, Item, SinkItem, Codec> Framed<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    /// Reads the next frame, runs `check` on it and decodes it, keeping the
+    /// codec's errors apart from the transport's.
+    fn poll_decode(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        check: impl FnOnce(&BytesMut) -> Result<(), Transport::Error>,
+    ) -> PollDecoded<Item, Codec::Error, Transport::Error> {
+        let this = self.project();
+
+        let bytes = match ready!(this.inner.try_poll_next(cx)) {
+            Some(bytes) => bytes?,
+            None => return Poll::Ready(None),
+        };
+        check(&bytes)?;
+        Poll::Ready(Some(Ok(this.codec.deserialize_owned(bytes))))
+    }
 }
 
 impl<Transport, Item, SinkItem, Codec> Stream for Framed<Transport, Item, SinkItem, Codec>
@@ -269,15 +904,9 @@ where
 {
     type Item = Result<Item, Transport::Error>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match ready!(self.as_mut().project().inner.try_poll_next(cx)) {
-            Some(bytes) => Poll::Ready(Some(Ok(self
-                .as_mut()
-                .project()
-                .codec
-                .deserialize(&bytes?)?))),
-            None => Poll::Ready(None),
-        }
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let res = ready!(self.poll_decode(cx, |_| Ok(())));
+        Poll::Ready(res.map(|res| Ok(res??)))
     }
 }
 
@@ -295,15 +924,34 @@ where
 
     fn start_send(mut self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
         let res = self.as_mut().project().codec.serialize(&item);
-        let bytes = res.map_err(Into::into)?;
+        let bytes = match res {
+            Ok(bytes) => bytes,
+            Err(_) if self.on_serialize_error == SkipOrFail::Skip => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("dropping a value that failed to serialize");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
 
-        self.as_mut().project().inner.start_send(bytes)?;
+        let this = self.as_mut().project();
+        this.inner.start_send(bytes)?;
+        *this.pending = true;
 
         Ok(())
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().inner.poll_flush(cx)
+        if !self.pending {
+            return Poll::Ready(Ok(()));
+        }
+
+        let this = self.project();
+
+        ready!(this.inner.poll_flush(cx))?;
+        *this.pending = false;
+
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -314,439 +962,14932 @@ where
 
 pub type SymmetricallyFramed<Transport, Value, Codec> = Framed<Transport, Value, Value, Codec>;
 
-#[cfg(any(
-    feature = "json",
-    feature = "bincode",
-    feature = "messagepack",
-    feature = "cbor",
-    feature = "encrypted_bincode"
-))]
-pub mod formats {
-    #[cfg(feature = "bincode")]
-    pub use self::bincode::*;
-    #[cfg(feature = "cbor")]
-    pub use self::cbor::*;
-    #[cfg(feature = "encrypted_bincode")]
-    pub use self::encrypted_bincode::*;
-    #[cfg(feature = "json")]
-    pub use self::json::*;
-    #[cfg(feature = "messagepack")]
-    pub use self::messagepack::*;
+/// Configures and builds a [`Framed`] used for both reading and writing.
+///
+/// `Framed::new` accepts any transport and codec, and mismatched error types
+/// only show up once the `Stream` or `Sink` half is used. [`build`](Self::build)
+/// requires every bound of both halves up front, so a codec whose errors
+/// can't be converted into the transport's is reported where the `Framed` is
+/// created. The builder also gathers the options of [`Framed`] in one place,
+/// and the checks of [`CheckedFramed`] can be added on top of the result.
+///
+/// ```
+/// # #[cfg(feature = "json")] {
+/// use tokio_serde::{
+///     formats::SymmetricalJson, CheckedFramed, FramedBuilder, SkipOrFail, SymmetricallyFramed,
+/// };
+/// use tokio_util::codec::{Framed, LengthDelimitedCodec};
+///
+/// let (socket, _peer) = tokio::io::duplex(1024);
+/// let transport = Framed::new(socket, LengthDelimitedCodec::new());
+///
+/// let framed: SymmetricallyFramed<_, String, _> =
+///     FramedBuilder::new(transport, SymmetricalJson::<String>::default())
+///         .on_serialize_error(SkipOrFail::Skip)
+///         .build();
+///
+/// let mut framed = CheckedFramed::new(framed);
+/// framed.max_frame_length(Some(64 * 1024));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FramedBuilder<Transport, Item, SinkItem, Codec> {
+    framed: Framed<Transport, Item, SinkItem, Codec>,
+}
 
-    use super::{Deserializer, Serializer};
-    use bytes::{Bytes, BytesMut};
-    use educe::Educe;
-    pub(crate) use serde::{Deserialize, Serialize};
-    use std::{marker::PhantomData, pin::Pin};
+impl<Transport, Item, SinkItem, Codec> FramedBuilder<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `FramedBuilder` with the given transport and codec, and
+    /// the default options of [`Framed::new`].
+    pub fn new(inner: Transport, codec: Codec) -> Self {
+        Self {
+            framed: Framed::new(inner, codec),
+        }
+    }
 
-    #[cfg(feature = "encrypted_bincode")]
-    mod encrypted_bincode {
-        use super::*;
+    /// Sets what happens to values that fail to serialize.
+    ///
+    /// See [`Framed::on_serialize_error`].
+    pub fn on_serialize_error(mut self, policy: SkipOrFail) -> Self {
+        self.framed.on_serialize_error(policy);
+        self
+    }
+
+    /// Builds the `Framed`, checking that it can be used as both a `Stream`
+    /// and a `Sink`.
+    pub fn build(self) -> Framed<Transport, Item, SinkItem, Codec>
+    where
+        Transport: TryStream<Ok = BytesMut> + Sink<Bytes>,
+        <Transport as TryStream>::Error: From<<Codec as Deserializer<Item>>::Error>,
+        Codec: Deserializer<Item> + Serializer<SinkItem>,
+        <Codec as Serializer<SinkItem>>::Error: Into<<Transport as Sink<Bytes>>::Error>,
+    {
+        self.framed
+    }
+}
+
+/// A [`Framed`] with optional checks on the frames it reads.
+///
+/// - [`error_on_unexpected_close`](Self::error_on_unexpected_close) makes the
+///   stream fail instead of ending if the transport closes while more frames
+///   are expected.
+/// - [`coalesce_decode_errors`](Self::coalesce_decode_errors) reports a run
+///   of frames failing to decode as a single error.
+/// - [`max_frame_length`](Self::max_frame_length) rejects long frames before
+///   they reach the codec.
+///
+/// These errors are created by `CheckedFramed` itself, so its `Stream` half
+/// requires the transport's error to be convertible from [`io::Error`] on
+/// top of the bounds of `Framed`. The `Sink` half is forwarded to the
+/// `Framed` unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct CheckedFramed<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    error_on_unexpected_close: bool,
+    expecting_more: bool,
+    coalesce_decode_errors: bool,
+    max_frame_length: Option<usize>,
+    skipped: usize,
+    ready: Option<Item>,
+    terminated: bool,
+}
+
+impl<Transport, Item, SinkItem, Codec> CheckedFramed<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `CheckedFramed` wrapping `inner`, with every check
+    /// disabled.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>) -> Self {
+        Self {
+            inner,
+            error_on_unexpected_close: false,
+            expecting_more: false,
+            coalesce_decode_errors: false,
+            max_frame_length: None,
+            skipped: 0,
+            ready: None,
+            terminated: false,
+        }
+    }
+
+    /// Sets whether the stream fails with [`io::ErrorKind::UnexpectedEof`]
+    /// instead of ending if the transport closes while more frames are
+    /// expected.
+    ///
+    /// `Framed` only ever sees whole frames, so it can't tell a clean close
+    /// from a reset on its own. Together with
+    /// [`set_expecting_more`](Self::set_expecting_more), which the caller
+    /// keeps up to date with the protocol's state, this turns a premature end
+    /// of the transport into an error. Disabled by default.
+    pub fn error_on_unexpected_close(&mut self, enabled: bool) {
+        self.error_on_unexpected_close = enabled;
+    }
+
+    /// Marks whether the protocol expects more frames from the peer, e.g.
+    /// while a multi-frame response is in progress.
+    ///
+    /// Only has an effect if
+    /// [`error_on_unexpected_close`](Self::error_on_unexpected_close) is
+    /// enabled.
+    pub fn set_expecting_more(&mut self, expecting_more: bool) {
+        self.expecting_more = expecting_more;
+    }
+
+    /// Returns `true` if the caller marked more frames as expected.
+    pub fn is_expecting_more(&self) -> bool {
+        self.expecting_more
+    }
+
+    /// Sets whether a run of frames failing to decode is reported as a
+    /// single error.
+    ///
+    /// When enabled, frames the codec rejects are skipped and counted. The
+    /// count is reported as one [`io::ErrorKind::InvalidData`] error wrapping
+    /// [`DecodeErrors`] as soon as a frame decodes again, right before its
+    /// value, or when the transport ends. The codec's own errors are
+    /// dropped. Transport errors are yielded as usual and don't end a run.
+    /// Disabled by default.
+    pub fn coalesce_decode_errors(&mut self, enabled: bool) {
+        self.coalesce_decode_errors = enabled;
+    }
+
+    /// Sets the largest frame the stream accepts, or `None` for no limit.
+    ///
+    /// Longer frames are reported as [`io::ErrorKind::InvalidData`] without
+    /// being handed to the codec, and the stream carries on with the next
+    /// frame. The transport has already read the whole frame by then, so this
+    /// guards the codec rather than memory; limit the transport itself, e.g.
+    /// with `LengthDelimitedCodec::max_frame_length`, to bound reads. No limit
+    /// is set by default.
+    pub fn max_frame_length(&mut self, max: Option<usize>) {
+        self.max_frame_length = max;
+    }
+
+    /// Swaps in `new` as the transport of the underlying `Framed`, returning
+    /// the old one.
+    ///
+    /// See [`Framed::replace_transport`].
+    /// [`set_expecting_more`](Self::set_expecting_more) starts over as
+    /// `false`.
+    pub fn replace_transport(&mut self, new: Transport) -> Transport {
+        self.expecting_more = false;
+        self.terminated = false;
+        self.inner.replace_transport(new)
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `CheckedFramed`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for CheckedFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error> + From<io::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if let Some(item) = this.ready.take() {
+            return Poll::Ready(Some(Ok(item)));
+        }
+
+        // The transport may not be fused, so it isn't polled again once it
+        // has ended. The errors owed for how it ended are yielded one by one.
+        let max_frame_length = *this.max_frame_length;
+        while !*this.terminated {
+            let res = ready!(this.inner.as_mut().poll_decode(cx, |bytes| {
+                match max_frame_length {
+                    Some(max) if bytes.len() > max => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame exceeds the maximum length",
+                    )
+                    .into()),
+                    _ => Ok(()),
+                }
+            }));
+
+            match res {
+                Some(Ok(Ok(item))) if *this.skipped > 0 => {
+                    *this.ready = Some(item);
+                    let err = DecodeErrors::into_io_error(std::mem::take(this.skipped));
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                Some(Ok(Ok(item))) => return Poll::Ready(Some(Ok(item))),
+                Some(Ok(Err(_))) if *this.coalesce_decode_errors => *this.skipped += 1,
+                Some(Ok(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => *this.terminated = true,
+            }
+        }
+
+        if *this.skipped > 0 {
+            let err = DecodeErrors::into_io_error(std::mem::take(this.skipped));
+            return Poll::Ready(Some(Err(err.into())));
+        }
+
+        if *this.error_on_unexpected_close && *this.expecting_more {
+            *this.expecting_more = false;
+            let err = io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "transport closed while more frames were expected",
+            );
+            return Poll::Ready(Some(Err(err.into())));
+        }
+
+        Poll::Ready(None)
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem>
+    for CheckedFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Like [`Framed`], but with separate codecs for each direction.
+///
+/// The `Stream` half decodes frames with `DecCodec`, which must implement
+/// [`Deserializer<Item>`], while the `Sink` half encodes values with
+/// `EncCodec`, which must implement [`Serializer<SinkItem>`]. This allows
+/// asymmetric protocols where the inbound and outbound formats differ.
+#[pin_project]
+#[derive(Debug)]
+pub struct BiFramed<Transport, Item, SinkItem, DecCodec, EncCodec> {
+    #[pin]
+    inner: Transport,
+    #[pin]
+    decoder: DecCodec,
+    #[pin]
+    encoder: EncCodec,
+    item: PhantomData<(Item, SinkItem)>,
+}
+
+impl<Transport, Item, SinkItem, DecCodec, EncCodec>
+    BiFramed<Transport, Item, SinkItem, DecCodec, EncCodec>
+{
+    /// Creates a new `BiFramed` with the given transport, decoder and encoder.
+    pub fn new(inner: Transport, decoder: DecCodec, encoder: EncCodec) -> Self {
+        Self {
+            inner,
+            decoder,
+            encoder,
+            item: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying transport wrapped by `BiFramed`.
+    ///
+    /// Note that care should be taken to not tamper with the underlying transport as
+    /// it may corrupt the sequence of frames otherwise being worked with.
+    pub fn get_ref(&self) -> &Transport {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying transport wrapped by
+    /// `BiFramed`.
+    ///
+    /// Note that care should be taken to not tamper with the underlying transport as
+    /// it may corrupt the sequence of frames otherwise being worked with.
+    pub fn get_mut(&mut self) -> &mut Transport {
+        &mut self.inner
+    }
+
+    /// Consumes the `BiFramed`, returning its underlying transport.
+    ///
+    /// Note that care should be taken to not tamper with the underlying transport as
+    /// it may corrupt the sequence of frames otherwise being worked with.
+    pub fn into_inner(self) -> Transport {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, DecCodec, EncCodec> Stream
+    for BiFramed<Transport, Item, SinkItem, DecCodec, EncCodec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<DecCodec::Error>,
+    BytesMut: From<Transport::Ok>,
+    DecCodec: Deserializer<Item>,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match ready!(self.as_mut().project().inner.try_poll_next(cx)) {
+            Some(bytes) => Poll::Ready(Some(Ok(self
+                .as_mut()
+                .project()
+                .decoder
+                .deserialize_owned(bytes?)?))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl<Transport, Item, SinkItem, DecCodec, EncCodec> Sink<SinkItem>
+    for BiFramed<Transport, Item, SinkItem, DecCodec, EncCodec>
+where
+    Transport: Sink<Bytes>,
+    EncCodec: Serializer<SinkItem>,
+    EncCodec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        let res = self.as_mut().project().encoder.serialize(&item);
+        let bytes = res.map_err(Into::into)?;
+
+        self.as_mut().project().inner.start_send(bytes)?;
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Bounds the number of bytes handed to a transport sink but not yet flushed.
+///
+/// `MaxInFlight` sits between [`Framed`] and the transport. It counts the
+/// bytes of every frame passed to `start_send` and, once more than
+/// `max_in_flight_bytes` are waiting to be flushed, `poll_ready` flushes the
+/// transport and returns `Poll::Pending` until that flush completes. This keeps
+/// a fast producer from queueing frames without bound inside a slow writer.
+///
+/// Reads are passed through to the transport untouched.
+#[pin_project]
+#[derive(Debug)]
+pub struct MaxInFlight<Transport> {
+    #[pin]
+    inner: Transport,
+    max_in_flight_bytes: usize,
+    in_flight: usize,
+}
+
+impl<Transport> MaxInFlight<Transport> {
+    /// Creates a new `MaxInFlight` allowing up to `max_in_flight_bytes`
+    /// unflushed bytes in `inner`.
+    pub fn new(inner: Transport, max_in_flight_bytes: usize) -> Self {
+        Self {
+            inner,
+            max_in_flight_bytes,
+            in_flight: 0,
+        }
+    }
+
+    /// Returns the number of bytes sent to the transport since the last
+    /// completed flush.
+    pub fn in_flight_bytes(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub fn get_ref(&self) -> &Transport {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying transport.
+    pub fn get_mut(&mut self) -> &mut Transport {
+        &mut self.inner
+    }
+
+    /// Consumes the `MaxInFlight`, returning its underlying transport.
+    pub fn into_inner(self) -> Transport {
+        self.inner
+    }
+}
+
+impl<Transport> Stream for MaxInFlight<Transport>
+where
+    Transport: Stream,
+{
+    type Item = Transport::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+impl<Transport> Sink<Bytes> for MaxInFlight<Transport>
+where
+    Transport: Sink<Bytes>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        if *this.in_flight > *this.max_in_flight_bytes {
+            ready!(this.inner.as_mut().poll_flush(cx))?;
+            *this.in_flight = 0;
+        }
+
+        this.inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        let len = item.len();
+
+        this.inner.start_send(item)?;
+        *this.in_flight += len;
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        ready!(this.inner.poll_flush(cx))?;
+        *this.in_flight = 0;
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        ready!(this.inner.poll_close(cx))?;
+        *this.in_flight = 0;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Merges the frames sent between two flushes into a single write.
+///
+/// `CoalescedWrite` sits between [`Framed`] and a byte-oriented transport,
+/// e.g. a `FramedWrite` with `BytesCodec`. Frames passed to `start_send` are
+/// appended to a buffer, and the buffer is handed to the transport in one
+/// `start_send` when the sink is flushed or closed. A burst of small frames
+/// then costs one write instead of one per frame.
+///
+/// Frame boundaries are lost on the way, so the frames must already carry
+/// their own framing, e.g. a length prefix added by the codec. Nothing is
+/// written before the next flush, and the buffer grows without bound until
+/// then.
+///
+/// Reads are passed through to the transport untouched.
+#[pin_project]
+#[derive(Debug)]
+pub struct CoalescedWrite<Transport> {
+    #[pin]
+    inner: Transport,
+    buf: BytesMut,
+}
+
+impl<Transport> CoalescedWrite<Transport> {
+    /// Creates a new `CoalescedWrite` in front of `inner`.
+    pub fn new(inner: Transport) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// Returns the number of bytes waiting for the next flush.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub fn get_ref(&self) -> &Transport {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying transport.
+    pub fn get_mut(&mut self) -> &mut Transport {
+        &mut self.inner
+    }
+
+    /// Consumes the `CoalescedWrite`, returning its underlying transport.
+    ///
+    /// Frames that weren't flushed yet are lost.
+    pub fn into_inner(self) -> Transport {
+        self.inner
+    }
+}
+
+impl<Transport> CoalescedWrite<Transport>
+where
+    Transport: Sink<Bytes>,
+{
+    fn poll_write_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Transport::Error>> {
+        let mut this = self.project();
+
+        if !this.buf.is_empty() {
+            ready!(this.inner.as_mut().poll_ready(cx))?;
+            this.inner.start_send(this.buf.split().freeze())?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Transport> Stream for CoalescedWrite<Transport>
+where
+    Transport: Stream,
+{
+    type Item = Transport::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+impl<Transport> Sink<Bytes> for CoalescedWrite<Transport>
+where
+    Transport: Sink<Bytes>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        self.project().buf.extend_from_slice(&item);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_buf(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_buf(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Limits how fast frames are handed to a transport, using a token bucket.
+///
+/// The bucket holds up to `burst` tokens and is refilled with `rate` tokens
+/// every `interval`. Sending a frame takes one token, or, for a limiter
+/// created with [`bytes`](Self::bytes), one token per byte. While the bucket
+/// is empty, `poll_ready` returns `Poll::Pending` and wakes the task once a
+/// token is available again. Byte-based limiting lets a frame overdraw the
+/// bucket, so a large frame delays the ones after it rather than being stuck
+/// forever.
+///
+/// The timer runs on the Tokio runtime, so the sink must be polled from
+/// within one. Reads are passed through to the transport untouched.
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+#[pin_project]
+#[derive(Debug)]
+pub struct RateLimited<Transport> {
+    #[pin]
+    inner: Transport,
+    rate: u64,
+    interval: std::time::Duration,
+    burst: i64,
+    per_byte: bool,
+    tokens: i64,
+    refilled: tokio::time::Instant,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+#[cfg(feature = "time")]
+impl<Transport> RateLimited<Transport> {
+    /// Creates a new `RateLimited` letting `rate` frames through every
+    /// `interval`, with bursts of up to `burst` frames.
+    ///
+    /// The bucket starts out full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate`, `interval` or `burst` is zero.
+    pub fn new(inner: Transport, rate: u64, interval: std::time::Duration, burst: u64) -> Self {
+        assert!(rate > 0, "rate must be non-zero");
+        assert!(!interval.is_zero(), "interval must be non-zero");
+        assert!(burst > 0, "burst must be non-zero");
+
+        let burst = burst.min(i64::MAX as u64) as i64;
+
+        Self {
+            inner,
+            rate,
+            interval,
+            burst,
+            per_byte: false,
+            tokens: burst,
+            refilled: tokio::time::Instant::now(),
+            sleep: None,
+        }
+    }
+
+    /// Creates a new `RateLimited` letting `rate` bytes through every
+    /// `interval`, with bursts of up to `burst` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate`, `interval` or `burst` is zero.
+    pub fn bytes(inner: Transport, rate: u64, interval: std::time::Duration, burst: u64) -> Self {
+        Self {
+            per_byte: true,
+            ..Self::new(inner, rate, interval, burst)
+        }
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub fn get_ref(&self) -> &Transport {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying transport.
+    pub fn get_mut(&mut self) -> &mut Transport {
+        &mut self.inner
+    }
+
+    /// Consumes the `RateLimited`, returning its underlying transport.
+    pub fn into_inner(self) -> Transport {
+        self.inner
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport> Stream for RateLimited<Transport>
+where
+    Transport: Stream,
+{
+    type Item = Transport::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport> Sink<Bytes> for RateLimited<Transport>
+where
+    Transport: Sink<Bytes>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        use std::future::Future;
+
+        let this = self.project();
+        let interval = this.interval.as_nanos();
+        let rate = u128::from(*this.rate);
+
+        loop {
+            let now = tokio::time::Instant::now();
+            let elapsed = now.saturating_duration_since(*this.refilled).as_nanos();
+            let earned = elapsed * rate / interval;
+
+            if earned > 0 {
+                let earned = earned.min(i64::MAX as u128) as i64;
+                *this.tokens = this.tokens.saturating_add(earned).min(*this.burst);
+
+                if *this.tokens == *this.burst {
+                    *this.refilled = now;
+                } else {
+                    // Keep the time spent towards the next token.
+                    let spent = earned as u128 * interval / rate;
+                    *this.refilled += std::time::Duration::from_nanos(spent as u64);
+                }
+            }
+
+            if *this.tokens >= 1 {
+                *this.sleep = None;
+                break;
+            }
+
+            let missing = (1 - *this.tokens) as u128;
+            let wait = (missing * interval).div_ceil(rate);
+            let deadline = *this.refilled + std::time::Duration::from_nanos(wait as u64);
+
+            let sleep = this
+                .sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            sleep.as_mut().reset(deadline);
+            ready!(sleep.as_mut().poll(cx));
+        }
+
+        this.inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        let cost = if *this.per_byte { item.len() as i64 } else { 1 };
+
+        this.inner.start_send(item)?;
+        *this.tokens = this.tokens.saturating_sub(cost);
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Stops reading from a stream until a downstream permit is free.
+///
+/// Each item is handed out together with an
+/// [`OwnedSemaphorePermit`](tokio::sync::OwnedSemaphorePermit) taken from the
+/// semaphore. The permit is acquired before the inner stream is polled, so
+/// while all permits are out `poll_next` returns `Poll::Pending` without
+/// pulling anything from the transport, and back-pressure reaches the peer.
+/// Dropping the permit once the item is processed, e.g. at the end of the
+/// worker task handling it, lets the next item through. This ties the read
+/// rate of a [`Framed`] to the capacity of a bounded worker pool.
+///
+/// An error from the inner stream is yielded without a permit; the permit
+/// already acquired is kept for the next item. The stream ends when the inner
+/// one does or when the semaphore is closed.
+#[cfg(feature = "throttle")]
+#[cfg_attr(docsrs, doc(cfg(feature = "throttle")))]
+#[pin_project]
+#[derive(Debug)]
+pub struct Throttled<S> {
+    #[pin]
+    inner: S,
+    semaphore: tokio_util::sync::PollSemaphore,
+    permit: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
+#[cfg(feature = "throttle")]
+impl<S> Throttled<S> {
+    /// Creates a new `Throttled` handing out the items of `inner` along with
+    /// permits of `semaphore`.
+    pub fn new(inner: S, semaphore: std::sync::Arc<tokio::sync::Semaphore>) -> Self {
+        Self {
+            inner,
+            semaphore: tokio_util::sync::PollSemaphore::new(semaphore),
+            permit: None,
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes the `Throttled`, returning its underlying stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(feature = "throttle")]
+impl<S> Stream for Throttled<S>
+where
+    S: TryStream,
+{
+    type Item = Result<(S::Ok, tokio::sync::OwnedSemaphorePermit), S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if this.permit.is_none() {
+            match ready!(this.semaphore.poll_acquire(cx)) {
+                Some(permit) => *this.permit = Some(permit),
+                None => return Poll::Ready(None),
+            }
+        }
+
+        match ready!(this.inner.try_poll_next(cx)) {
+            Some(Ok(item)) => {
+                let permit = this.permit.take().expect("permit acquired above");
+                Poll::Ready(Some(Ok((item, permit))))
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// A transport splitting a byte stream into frames on a delimiter byte.
+///
+/// This supports legacy protocols that terminate records with a sentinel,
+/// e.g. `\0`, rather than prefixing them with their length. Chunks read from
+/// the inner transport are buffered and split on the delimiter, so a record
+/// may span several chunks and a chunk may hold several records. Each record
+/// is yielded without its delimiter. Bytes left over without a delimiter when
+/// the inner transport ends are reported as [`io::ErrorKind::UnexpectedEof`].
+///
+/// A record longer than [`max_record_length`](Self::max_record_length) is
+/// reported as [`io::ErrorKind::InvalidData`] as soon as the limit is
+/// exceeded. The rest of it is discarded up to the next delimiter, after
+/// which records are yielded again.
+///
+/// Frames written to the `Sink` half get the delimiter appended. There is no
+/// escaping, so frames containing the delimiter are rejected with
+/// [`io::ErrorKind::InvalidInput`] instead of desyncing the peer. This makes
+/// it a good fit for text formats such as JSON, which never contain a `\0`
+/// byte, but not for binary ones.
+#[pin_project]
+#[derive(Debug)]
+pub struct Delimited<Transport> {
+    #[pin]
+    inner: Transport,
+    delimiter: u8,
+    buf: BytesMut,
+    scanned: usize,
+    discarding: bool,
+    max_record_length: usize,
+}
+
+impl<Transport> Delimited<Transport> {
+    /// Creates a new `Delimited` splitting the bytes of `inner` on
+    /// `delimiter` into records of up to 8 MiB.
+    pub fn new(inner: Transport, delimiter: u8) -> Self {
+        Self {
+            inner,
+            delimiter,
+            buf: BytesMut::new(),
+            scanned: 0,
+            discarding: false,
+            max_record_length: 8 * 1024 * 1024,
+        }
+    }
+
+    /// Returns the byte records are terminated with.
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    /// Returns the longest record accepted, not counting the delimiter.
+    pub fn max_record_length(&self) -> usize {
+        self.max_record_length
+    }
+
+    /// Sets the longest record accepted, not counting the delimiter.
+    pub fn set_max_record_length(&mut self, max: usize) {
+        self.max_record_length = max;
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub fn get_ref(&self) -> &Transport {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying transport.
+    pub fn get_mut(&mut self) -> &mut Transport {
+        &mut self.inner
+    }
+
+    /// Consumes the `Delimited`, returning its underlying transport.
+    ///
+    /// Bytes read from the transport that don't form a complete record yet
+    /// are lost.
+    pub fn into_inner(self) -> Transport {
+        self.inner
+    }
+
+    fn too_long() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "record exceeds the maximum length",
+        )
+    }
+}
+
+impl<Transport> Stream for Delimited<Transport>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<io::Error>,
+{
+    type Item = Result<BytesMut, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            // Bytes before `scanned` are known not to hold a delimiter.
+            let found = this.buf[*this.scanned..]
+                .iter()
+                .position(|b| b == this.delimiter)
+                .map(|pos| *this.scanned + pos);
+
+            match found {
+                Some(pos) if *this.discarding || pos > *this.max_record_length => {
+                    this.buf.advance(pos + 1);
+                    *this.scanned = 0;
+
+                    if !std::mem::take(this.discarding) {
+                        return Poll::Ready(Some(Err(Self::too_long().into())));
+                    }
+                    continue;
+                }
+                Some(pos) => {
+                    let frame = this.buf.split_to(pos);
+                    this.buf.advance(1);
+                    *this.scanned = 0;
+                    return Poll::Ready(Some(Ok(frame)));
+                }
+                None if *this.discarding => {
+                    this.buf.clear();
+                    *this.scanned = 0;
+                }
+                None if this.buf.len() > *this.max_record_length => {
+                    this.buf.clear();
+                    *this.scanned = 0;
+                    *this.discarding = true;
+                    return Poll::Ready(Some(Err(Self::too_long().into())));
+                }
+                None => *this.scanned = this.buf.len(),
+            }
+
+            match ready!(this.inner.as_mut().try_poll_next(cx)) {
+                Some(chunk) => this.buf.extend_from_slice(&chunk?),
+                None if this.buf.is_empty() => return Poll::Ready(None),
+                None => {
+                    this.buf.clear();
+                    *this.scanned = 0;
+                    let err = io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "transport ended in the middle of a record",
+                    );
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            }
+        }
+    }
+}
+
+impl<Transport> Sink<Bytes> for Delimited<Transport>
+where
+    Transport: Sink<Bytes>,
+    Transport::Error: From<io::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+
+        if item.contains(this.delimiter) {
+            let err = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame contains the record delimiter",
+            );
+            return Err(err.into());
+        }
+
+        let mut frame = BytesMut::with_capacity(item.len() + 1);
+        frame.extend_from_slice(&item);
+        frame.extend_from_slice(&[*this.delimiter]);
+        this.inner.start_send(frame.freeze())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// A transport splitting a byte stream into records prefixed with their
+/// length in ASCII decimal and a newline, i.e. `<length>\n<payload>`.
+///
+/// This is the framing used by some log shippers. Chunks read from the inner
+/// transport are buffered, so a record may span several chunks and a chunk
+/// may hold several records. Each record is yielded without its prefix, ready
+/// to be decoded by the codec of a [`Framed`]. A prefix that isn't a decimal
+/// number, or announces a record longer than
+/// [`max_frame_length`](Self::max_frame_length), is reported as
+/// [`io::ErrorKind::InvalidData`], after which the stream can't be
+/// resynchronized. Bytes left over when the inner transport ends are
+/// reported as [`io::ErrorKind::UnexpectedEof`].
+///
+/// Frames written to the `Sink` half get their length and a newline
+/// prepended.
+#[pin_project]
+#[derive(Debug)]
+pub struct LengthNewline<Transport> {
+    #[pin]
+    inner: Transport,
+    buf: BytesMut,
+    len: Option<usize>,
+    max_frame_length: usize,
+}
+
+impl<Transport> LengthNewline<Transport> {
+    /// The most digits accepted in a length prefix, enough for any `u64`.
+    const MAX_DIGITS: usize = 20;
+
+    /// Creates a new `LengthNewline` splitting the bytes of `inner` into
+    /// records of up to 8 MiB.
+    pub fn new(inner: Transport) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::new(),
+            len: None,
+            max_frame_length: 8 * 1024 * 1024,
+        }
+    }
+
+    /// Returns the longest record accepted.
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    /// Sets the longest record accepted.
+    ///
+    /// The length prefix is checked as soon as it is read, so a peer can't
+    /// make the stream buffer more than this for a single record.
+    pub fn set_max_frame_length(&mut self, max: usize) {
+        self.max_frame_length = max;
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub fn get_ref(&self) -> &Transport {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying transport.
+    pub fn get_mut(&mut self) -> &mut Transport {
+        &mut self.inner
+    }
+
+    /// Consumes the `LengthNewline`, returning its underlying transport.
+    ///
+    /// Bytes read from the transport that don't form a complete record yet
+    /// are lost.
+    pub fn into_inner(self) -> Transport {
+        self.inner
+    }
+}
+
+/// Parses the ASCII decimal length prefix of a [`LengthNewline`] record.
+fn parse_length_prefix(prefix: &[u8]) -> io::Result<usize> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid record length prefix");
+
+    if prefix.is_empty() || !prefix.iter().all(u8::is_ascii_digit) {
+        return Err(invalid());
+    }
+
+    std::str::from_utf8(prefix)
+        .ok()
+        .and_then(|prefix| prefix.parse().ok())
+        .ok_or_else(invalid)
+}
+
+impl<Transport> Stream for LengthNewline<Transport>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<io::Error>,
+{
+    type Item = Result<BytesMut, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match *this.len {
+                Some(len) if this.buf.len() >= len => {
+                    *this.len = None;
+                    return Poll::Ready(Some(Ok(this.buf.split_to(len))));
+                }
+                Some(_) => {}
+                None => match this
+                    .buf
+                    .iter()
+                    .take(Self::MAX_DIGITS + 1)
+                    .position(|&b| b == b'\n')
+                {
+                    Some(pos) => {
+                        let len = parse_length_prefix(&this.buf[..pos]).and_then(|len| {
+                            if len > *this.max_frame_length {
+                                return Err(io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    "record exceeds the maximum length",
+                                ));
+                            }
+                            Ok(len)
+                        });
+                        this.buf.advance(pos + 1);
+
+                        match len {
+                            Ok(len) => *this.len = Some(len),
+                            Err(err) => {
+                                this.buf.clear();
+                                return Poll::Ready(Some(Err(err.into())));
+                            }
+                        }
+                        continue;
+                    }
+                    None if this.buf.len() > Self::MAX_DIGITS => {
+                        this.buf.clear();
+                        let err = io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "record length prefix is too long",
+                        );
+                        return Poll::Ready(Some(Err(err.into())));
+                    }
+                    None => {}
+                },
+            }
+
+            match ready!(this.inner.as_mut().try_poll_next(cx)) {
+                Some(chunk) => this.buf.extend_from_slice(&chunk?),
+                None if this.buf.is_empty() && this.len.is_none() => return Poll::Ready(None),
+                None => {
+                    this.buf.clear();
+                    *this.len = None;
+                    let err = io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "transport ended in the middle of a record",
+                    );
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            }
+        }
+    }
+}
+
+impl<Transport> Sink<Bytes> for LengthNewline<Transport>
+where
+    Transport: Sink<Bytes>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let prefix = format!("{}\n", item.len());
+
+        let mut frame = BytesMut::with_capacity(prefix.len() + item.len());
+        frame.extend_from_slice(prefix.as_bytes());
+        frame.extend_from_slice(&item);
+        self.project().inner.start_send(frame.freeze())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Pairs every value decoded by a [`Framed`] with the size of its frame.
+///
+/// The `Stream` half yields `(Item, usize)`, where the size is the length of
+/// the raw frame read from the transport, before it was decoded. This is
+/// handy for accounting, e.g. billing per message. The `Sink` half is
+/// forwarded to the `Framed` unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct WithSizes<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+}
+
+impl<Transport, Item, SinkItem, Codec> WithSizes<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `WithSizes` reporting the frame sizes of `inner`.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `WithSizes`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for WithSizes<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<(Item, usize), Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project().inner.project();
+
+        match ready!(this.inner.try_poll_next(cx)) {
+            Some(bytes) => {
+                let bytes = bytes?;
+                let len = bytes.len();
+                Poll::Ready(Some(Ok((this.codec.deserialize_owned(bytes)?, len))))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem>
+    for WithSizes<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// What [`OnEmpty`] does with a zero-length frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmptyFrame<T> {
+    /// Yield an [`io::ErrorKind::InvalidData`] error.
+    Error,
+    /// Skip the frame and read the next one.
+    Skip,
+    /// Yield a clone of the given value.
+    Value(T),
+}
+
+/// Applies a uniform [`EmptyFrame`] policy to the frames read by a
+/// [`Framed`].
+///
+/// Codecs disagree on empty frames: bincode decodes them as `()` while JSON
+/// fails to parse them. `OnEmpty` handles zero-length frames itself, before
+/// they reach the codec, so the behavior no longer depends on the format.
+/// Non-empty frames and the `Sink` half are forwarded to the `Framed`
+/// unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct OnEmpty<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    policy: EmptyFrame<Item>,
+}
+
+impl<Transport, Item, SinkItem, Codec> OnEmpty<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `OnEmpty` applying `policy` to the empty frames of
+    /// `inner`.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>, policy: EmptyFrame<Item>) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Returns the policy applied to empty frames.
+    pub fn policy(&self) -> &EmptyFrame<Item> {
+        &self.policy
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `OnEmpty`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for OnEmpty<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error> + From<io::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+    Item: Clone,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut framed = this.inner.project();
+
+        loop {
+            let bytes = match ready!(framed.inner.as_mut().try_poll_next(cx)) {
+                Some(bytes) => bytes?,
+                None => return Poll::Ready(None),
+            };
+
+            if !bytes.is_empty() {
+                return Poll::Ready(Some(Ok(framed.codec.as_mut().deserialize_owned(bytes)?)));
+            }
+
+            match this.policy {
+                EmptyFrame::Error => {
+                    let err = io::Error::new(io::ErrorKind::InvalidData, "empty frame");
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                EmptyFrame::Skip => continue,
+                EmptyFrame::Value(value) => return Poll::Ready(Some(Ok(value.clone()))),
+            }
+        }
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem> for OnEmpty<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Diverts the decode errors of a [`Framed`] to a separate channel.
+///
+/// Frames the codec fails to decode are skipped, and the codec's error is
+/// sent to the [`mpsc::Receiver`] returned by [`new`](Self::new), e.g. to feed
+/// metrics or alerting. The `Stream` half only yields successfully decoded
+/// values and transport errors, which are still fatal. The `Sink` half is
+/// forwarded to the `Framed` unchanged.
+///
+/// The channel is bounded, so a peer sending garbage can't make it grow
+/// without limit. Decode errors arriving while it is full, or after the
+/// receiver was dropped, are discarded.
+#[pin_project]
+#[derive(Debug)]
+pub struct SplitErrors<Transport, Item, SinkItem, Codec>
+where
+    Codec: Deserializer<Item>,
+{
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    errors: mpsc::Sender<Codec::Error>,
+}
+
+impl<Transport, Item, SinkItem, Codec> SplitErrors<Transport, Item, SinkItem, Codec>
+where
+    Codec: Deserializer<Item>,
+{
+    /// Creates a new `SplitErrors` over `inner`, returning it along with the
+    /// receiving end of its decode errors.
+    ///
+    /// The channel holds `buffer` errors, plus one per sender as described
+    /// in [`mpsc::channel`].
+    pub fn new(
+        inner: Framed<Transport, Item, SinkItem, Codec>,
+        buffer: usize,
+    ) -> (Self, mpsc::Receiver<Codec::Error>) {
+        let (errors, rx) = mpsc::channel(buffer);
+        (Self { inner, errors }, rx)
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `SplitErrors`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for SplitErrors<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut framed = this.inner.project();
+
+        loop {
+            let bytes = match ready!(framed.inner.as_mut().try_poll_next(cx)) {
+                Some(bytes) => bytes?,
+                None => return Poll::Ready(None),
+            };
+
+            match framed.codec.as_mut().deserialize_owned(bytes) {
+                Ok(item) => return Poll::Ready(Some(Ok(item))),
+                Err(err) => {
+                    let _ = this.errors.try_send(err);
+                }
+            }
+        }
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem>
+    for SplitErrors<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem> + Deserializer<Item>,
+    <Codec as Serializer<SinkItem>>::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// What [`RecoverErrors`] does with an error read from the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Yield the error, as [`Framed`] does.
+    Fatal,
+    /// Drop the error and read the next frame.
+    Skip,
+}
+
+/// Lets a [`Framed`] recover from transport errors that only affect a single
+/// frame.
+///
+/// Every error read from the transport is passed to the classifier. Errors
+/// classified as [`ErrorAction::Skip`] are dropped and the next frame is read
+/// instead, e.g. after a framing layer reported one malformed length prefix
+/// but can resynchronize. [`ErrorAction::Fatal`] errors are yielded as usual.
+/// Errors returned by the codec are not classified.
+///
+/// The `Sink` half is forwarded to the `Framed` unchanged.
+#[pin_project]
+pub struct RecoverErrors<Transport, Item, SinkItem, Codec, F> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    classify: F,
+}
+
+impl<Transport, Item, SinkItem, Codec, F> RecoverErrors<Transport, Item, SinkItem, Codec, F> {
+    /// Creates a new `RecoverErrors` classifying the transport errors of
+    /// `inner` with `classify`.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>, classify: F) -> Self {
+        Self { inner, classify }
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `RecoverErrors`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec, F> fmt::Debug
+    for RecoverErrors<Transport, Item, SinkItem, Codec, F>
+where
+    Framed<Transport, Item, SinkItem, Codec>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecoverErrors")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec, F> Stream
+    for RecoverErrors<Transport, Item, SinkItem, Codec, F>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    Codec: Deserializer<Item>,
+    F: Fn(&Transport::Error) -> ErrorAction,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut framed = this.inner.project();
+
+        loop {
+            let bytes = match ready!(framed.inner.as_mut().try_poll_next(cx)) {
+                Some(Ok(bytes)) => bytes,
+                Some(Err(err)) => match (this.classify)(&err) {
+                    ErrorAction::Fatal => return Poll::Ready(Some(Err(err))),
+                    ErrorAction::Skip => continue,
+                },
+                None => return Poll::Ready(None),
+            };
+
+            return Poll::Ready(Some(Ok(framed.codec.as_mut().deserialize_owned(bytes)?)));
+        }
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec, F> Sink<SinkItem>
+    for RecoverErrors<Transport, Item, SinkItem, Codec, F>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// An event yielded by [`GapDetecting`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent<T> {
+    /// A decoded value.
+    Item(T),
+    /// The sequence number of the next frame was not the expected one.
+    ///
+    /// This is yielded right before the value of that frame.
+    Gap {
+        /// The sequence number following the previous frame's.
+        expected: u64,
+        /// The sequence number the frame carried.
+        got: u64,
+    },
+}
+
+/// Reports missing or reordered frames of a [`Framed`] decoding sequence
+/// numbers.
+///
+/// The codec must decode `(u64, T)` pairs of a sequence number and a value,
+/// like [`Sequenced`](combinators::Sequenced) does. The first frame is
+/// expected to carry `0` and every following one the successor of the
+/// previous number. When a frame doesn't, a [`StreamEvent::Gap`] is yielded
+/// before its value instead of dropping it silently, and counting resumes
+/// from that frame. The `Sink` half is forwarded to the `Framed` unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct GapDetecting<Transport, T, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, (u64, T), SinkItem, Codec>,
+    expected: u64,
+    pending: Option<T>,
+}
+
+impl<Transport, T, SinkItem, Codec> GapDetecting<Transport, T, SinkItem, Codec> {
+    /// Creates a new `GapDetecting` checking the sequence numbers decoded by
+    /// `inner`.
+    pub fn new(inner: Framed<Transport, (u64, T), SinkItem, Codec>) -> Self {
+        Self {
+            inner,
+            expected: 0,
+            pending: None,
+        }
+    }
+
+    /// Returns the sequence number the next frame is expected to carry.
+    pub fn expected(&self) -> u64 {
+        self.expected
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, (u64, T), SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, (u64, T), SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `GapDetecting`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, (u64, T), SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, T, SinkItem, Codec> Stream for GapDetecting<Transport, T, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    Codec: Deserializer<(u64, T)>,
+{
+    type Item = Result<StreamEvent<T>, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if let Some(item) = this.pending.take() {
+            return Poll::Ready(Some(Ok(StreamEvent::Item(item))));
+        }
+
+        let (seq, item) = match ready!(this.inner.poll_next(cx)) {
+            Some(res) => res?,
+            None => return Poll::Ready(None),
+        };
+
+        let expected = *this.expected;
+        *this.expected = seq.wrapping_add(1);
+
+        if seq == expected {
+            Poll::Ready(Some(Ok(StreamEvent::Item(item))))
+        } else {
+            *this.pending = Some(item);
+            Poll::Ready(Some(Ok(StreamEvent::Gap { expected, got: seq })))
+        }
+    }
+}
+
+impl<Transport, T, SinkItem, Codec> Sink<SinkItem> for GapDetecting<Transport, T, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// A [`Framed`] sink that lets urgent frames jump ahead of queued ones.
+///
+/// Values sent through the `Sink` are serialized right away but queued
+/// instead of being handed to the transport. Priority values, sent with
+/// [`send_priority`](Self::send_priority) or
+/// [`start_send_priority`](Self::start_send_priority), are queued ahead of
+/// every normal frame, in the order they were sent. The queue is drained into
+/// the transport, priority frames first, when the sink is flushed.
+///
+/// The queue is unbounded, so callers are expected to flush regularly. The
+/// `Stream` half is forwarded to the `Framed` unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct PriorityFramed<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    queue: VecDeque<Bytes>,
+    priority: usize,
+}
+
+impl<Transport, Item, SinkItem, Codec> PriorityFramed<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `PriorityFramed` queueing the frames written to `inner`.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>) -> Self {
+        Self {
+            inner,
+            queue: VecDeque::new(),
+            priority: 0,
+        }
+    }
+
+    /// Returns the number of frames waiting for the next flush.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `PriorityFramed`, returning the underlying `Framed`.
+    ///
+    /// Frames still queued are dropped.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> PriorityFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    /// Serializes `item` and queues it ahead of every normal frame.
+    pub fn start_send_priority(
+        self: Pin<&mut Self>,
+        item: SinkItem,
+    ) -> Result<(), Transport::Error> {
+        let this = self.project();
+        let bytes = this
+            .inner
+            .project()
+            .codec
+            .serialize(&item)
+            .map_err(Into::into)?;
+
+        this.queue.insert(*this.priority, bytes);
+        *this.priority += 1;
+
+        Ok(())
+    }
+
+    /// Queues `item` ahead of every normal frame, then flushes the sink.
+    pub async fn send_priority(&mut self, item: SinkItem) -> Result<(), Transport::Error>
+    where
+        Self: Unpin,
+    {
+        Pin::new(&mut *self).start_send_priority(item)?;
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_flush(cx)).await
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for PriorityFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem>
+    for PriorityFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        let this = self.project();
+        let bytes = this
+            .inner
+            .project()
+            .codec
+            .serialize(&item)
+            .map_err(Into::into)?;
+
+        this.queue.push_back(bytes);
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let mut framed = this.inner.project();
+
+        while !this.queue.is_empty() {
+            ready!(framed.inner.as_mut().poll_ready(cx))?;
+
+            if let Some(bytes) = this.queue.pop_front() {
+                *this.priority = this.priority.saturating_sub(1);
+                framed.inner.as_mut().start_send(bytes)?;
+                *framed.pending = true;
+            }
+        }
+
+        ready!(framed.inner.poll_flush(cx))?;
+        *framed.pending = false;
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// A [`Framed`] sink that accepts a whole batch of values at once.
+///
+/// Each `Vec<SinkItem>` sent through the `Sink` is serialized element by
+/// element, so every value still travels in a frame of its own. The frames
+/// are handed to the transport one `start_send` at a time, waiting on its
+/// `poll_ready` between them, and the transport is flushed once for the whole
+/// batch.
+///
+/// The `Stream` half is forwarded to the `Framed` unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct VectoredFramed<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    queue: VecDeque<Bytes>,
+}
+
+impl<Transport, Item, SinkItem, Codec> VectoredFramed<Transport, Item, SinkItem, Codec> {
+    /// Wraps `inner` so it accepts batches of values.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>) -> Self {
+        Self {
+            inner,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of serialized frames not yet handed to the
+    /// transport.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `VectoredFramed`, returning the underlying `Framed`.
+    ///
+    /// Frames still queued are dropped.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> VectoredFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+{
+    fn poll_write_queue(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Transport::Error>> {
+        let this = self.project();
+        let mut framed = this.inner.project();
+
+        while !this.queue.is_empty() {
+            ready!(framed.inner.as_mut().poll_ready(cx))?;
+
+            if let Some(bytes) = this.queue.pop_front() {
+                framed.inner.as_mut().start_send(bytes)?;
+                *framed.pending = true;
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for VectoredFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<Vec<SinkItem>>
+    for VectoredFramed<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_write_queue(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, items: Vec<SinkItem>) -> Result<(), Self::Error> {
+        let this = self.project();
+        let mut codec = this.inner.project().codec;
+
+        this.queue.reserve(items.len());
+        for item in &items {
+            let bytes = codec.as_mut().serialize(item).map_err(Into::into)?;
+            this.queue.push_back(bytes);
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_queue(cx))?;
+
+        let framed = self.project().inner.project();
+        ready!(framed.inner.poll_flush(cx))?;
+        *framed.pending = false;
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_queue(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// A [`Framed`] sink that sends a keepalive value once no value has been sent
+/// for a while.
+///
+/// Each time the sink is polled for readiness or flushed, `Keepalive` checks
+/// whether `interval` has passed since the last value went out, and if so
+/// sends the configured keepalive value first. The idle timer is armed while
+/// polling, so a task flushing the sink in a loop, e.g. alongside reads in a
+/// `select!`, is woken up in time to send it. Nothing is sent while the sink
+/// isn't polled at all.
+///
+/// The timer runs on the Tokio runtime, so the sink must be polled from
+/// within one. The `Stream` half is forwarded to the `Framed` unchanged.
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+#[pin_project]
+#[derive(Debug)]
+pub struct Keepalive<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    keepalive: SinkItem,
+    interval: std::time::Duration,
+    deadline: tokio::time::Instant,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec> Keepalive<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `Keepalive` sending `keepalive` through `inner` after
+    /// `interval` without any other value being sent.
+    pub fn new(
+        inner: Framed<Transport, Item, SinkItem, Codec>,
+        keepalive: SinkItem,
+        interval: std::time::Duration,
+    ) -> Self {
+        Self {
+            inner,
+            keepalive,
+            interval,
+            deadline: tokio::time::Instant::now() + interval,
+            sleep: None,
+        }
+    }
+
+    /// Returns the value sent when the connection is idle.
+    pub fn keepalive(&self) -> &SinkItem {
+        &self.keepalive
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `Keepalive`, returning the underlying `Framed`.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec> Keepalive<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    fn poll_keepalive(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Transport::Error>> {
+        use std::future::Future;
+
+        let this = self.project();
+        let deadline = *this.deadline;
+        let sleep = this
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        if sleep.deadline() != deadline {
+            sleep.as_mut().reset(deadline);
+        }
+        if sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut framed = this.inner.project();
+        ready!(framed.inner.as_mut().poll_ready(cx))?;
+        let bytes = framed.codec.serialize(this.keepalive).map_err(Into::into)?;
+        framed.inner.start_send(bytes)?;
+        *framed.pending = true;
+        *this.deadline = tokio::time::Instant::now() + *this.interval;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec> Stream for Keepalive<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<Item, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem>
+    for Keepalive<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_keepalive(cx))?;
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        let this = self.project();
+        this.inner.start_send(item)?;
+        *this.deadline = tokio::time::Instant::now() + *this.interval;
+
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_keepalive(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Flushes a sink in the background when it is dropped.
+///
+/// Dropping a [`Framed`] whose transport still buffers frames loses them.
+/// `FlushOnDrop` forwards everything to the wrapped sink and, when dropped,
+/// moves the sink into a task spawned on a Tokio runtime that flushes it and
+/// then drops it. Use [`into_inner`](Self::into_inner) to take the sink back
+/// without flushing.
+///
+/// Rust has no async drop, so this is best effort:
+///
+/// - The flush happens after the drop returns, and nothing reports its
+///   outcome. Errors are ignored, or logged with the `tracing` feature.
+/// - It needs a runtime that keeps running: frames are lost if the runtime
+///   shuts down first, e.g. when dropping at the end of `main`. Flush or
+///   close the sink explicitly wherever delivery matters.
+/// - A flush that never completes, e.g. to a peer that stopped reading, keeps
+///   the task and the connection alive. Add a timeout to the transport if
+///   that's a concern.
+#[cfg(feature = "flush-on-drop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "flush-on-drop")))]
+pub struct FlushOnDrop<S, SinkItem> {
+    inner: Option<S>,
+    handle: tokio::runtime::Handle,
+    flush: fn(S, &tokio::runtime::Handle),
+    item: PhantomData<fn(SinkItem)>,
+}
+
+#[cfg(feature = "flush-on-drop")]
+impl<S, SinkItem> FlushOnDrop<S, SinkItem>
+where
+    S: Sink<SinkItem> + Unpin + Send + 'static,
+{
+    /// Creates a new `FlushOnDrop` flushing `inner` on the current runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new(inner: S) -> Self {
+        Self::with_handle(inner, tokio::runtime::Handle::current())
+    }
+
+    /// Creates a new `FlushOnDrop` flushing `inner` on the runtime of
+    /// `handle`.
+    pub fn with_handle(inner: S, handle: tokio::runtime::Handle) -> Self {
+        fn spawn_flush<S, SinkItem>(mut inner: S, handle: &tokio::runtime::Handle)
+        where
+            S: Sink<SinkItem> + Unpin + Send + 'static,
+        {
+            handle.spawn(async move {
+                let res = std::future::poll_fn(|cx| {
+                    Sink::<SinkItem>::poll_flush(Pin::new(&mut inner), cx)
+                })
+                .await;
+                #[cfg(feature = "tracing")]
+                if res.is_err() {
+                    tracing::warn!("failed to flush a sink on drop");
+                }
+                drop(res);
+            });
+        }
+
+        Self {
+            inner: Some(inner),
+            handle,
+            flush: spawn_flush::<S, SinkItem>,
+            item: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "flush-on-drop")]
+impl<S, SinkItem> FlushOnDrop<S, SinkItem> {
+    /// Returns a reference to the wrapped sink.
+    pub fn get_ref(&self) -> &S {
+        self.inner.as_ref().expect("sink taken")
+    }
+
+    /// Returns a mutable reference to the wrapped sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.inner.as_mut().expect("sink taken")
+    }
+
+    /// Consumes the `FlushOnDrop`, returning the wrapped sink without
+    /// flushing it.
+    pub fn into_inner(mut self) -> S {
+        self.inner.take().expect("sink taken")
+    }
+}
+
+#[cfg(feature = "flush-on-drop")]
+impl<S: fmt::Debug, SinkItem> fmt::Debug for FlushOnDrop<S, SinkItem> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlushOnDrop")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "flush-on-drop")]
+impl<S, SinkItem> Drop for FlushOnDrop<S, SinkItem> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            (self.flush)(inner, &self.handle);
+        }
+    }
+}
+
+#[cfg(feature = "flush-on-drop")]
+impl<S, SinkItem> Stream for FlushOnDrop<S, SinkItem>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(self.get_mut().get_mut()).poll_next(cx)
+    }
+}
+
+#[cfg(feature = "flush-on-drop")]
+impl<S, SinkItem> Sink<SinkItem> for FlushOnDrop<S, SinkItem>
+where
+    S: Sink<SinkItem> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(self.get_mut().get_mut()).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        Pin::new(self.get_mut().get_mut()).start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(self.get_mut().get_mut()).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(self.get_mut().get_mut()).poll_close(cx)
+    }
+}
+
+/// A [`Framed`] that reconnects its transport when the connection fails.
+///
+/// `connect` is called to build a fresh transport, e.g. a length-delimited
+/// TCP connection, whenever the current one fails. Until it succeeds, both
+/// halves return `Poll::Pending`; failed attempts are retried after a backoff
+/// that starts at 100 milliseconds and doubles up to 10 seconds, see
+/// [`with_backoff`](Self::with_backoff). Attempts are retried forever, so
+/// bound the overall operation with a timeout if the peer may never come
+/// back. The codec and the options of the `Framed` are kept across
+/// connections, see [`Framed::replace_transport`].
+///
+/// A connection counts as failed when the transport ends or reports an error
+/// the [`reconnect_if`](Self::reconnect_if) predicate accepts, by default any
+/// transport error. Errors of the codec, e.g. a frame that fails to decode,
+/// never trigger a reconnection. Other errors are returned as usual, and the
+/// stream never ends.
+///
+/// Frames sent since the last successful flush may not have reached the peer
+/// when the connection fails. They are dropped unless
+/// [`resend_unflushed`](Self::resend_unflushed) is enabled, in which case
+/// they are sent again, in order, on the new connection. The peer may then
+/// see some frames twice, so this suits idempotent messages. At most
+/// [`with_max_unflushed`](Self::with_max_unflushed) frames, 1024 by default,
+/// are kept for this; older ones are dropped first.
+///
+/// Both halves drive the reconnection, so they should be polled from the
+/// same task. The backoff timer runs on the Tokio runtime.
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+#[pin_project]
+pub struct Reconnecting<Transport, Item, SinkItem, Codec, F, Fut> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    connect: F,
+    connecting: Option<Pin<Box<Fut>>>,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+    backoff: std::time::Duration,
+    min_backoff: std::time::Duration,
+    max_backoff: std::time::Duration,
+    reconnect_if: fn(&io::Error) -> bool,
+    resend: bool,
+    max_unflushed: usize,
+    unflushed: VecDeque<Bytes>,
+    replay: VecDeque<Bytes>,
+    reconnects: usize,
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec, F, Fut>
+    Reconnecting<Transport, Item, SinkItem, Codec, F, Fut>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = io::Result<Transport>>,
+{
+    /// Creates a new `Reconnecting` over the already connected `inner`,
+    /// calling `connect` for new connections.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>, connect: F) -> Self {
+        let min_backoff = std::time::Duration::from_millis(100);
+
+        Self {
+            inner,
+            connect,
+            connecting: None,
+            sleep: None,
+            backoff: min_backoff,
+            min_backoff,
+            max_backoff: std::time::Duration::from_secs(10),
+            reconnect_if: |_| true,
+            resend: false,
+            max_unflushed: 1024,
+            unflushed: VecDeque::new(),
+            replay: VecDeque::new(),
+            reconnects: 0,
+        }
+    }
+
+    /// Sets the delay before retrying after the first failed attempt to
+    /// connect, doubled after each further failure up to `max`.
+    pub fn with_backoff(mut self, min: std::time::Duration, max: std::time::Duration) -> Self {
+        self.backoff = min;
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// Sets which transport errors mean the connection failed.
+    ///
+    /// The predicate only sees errors of the transport, not those of the
+    /// codec.
+    pub fn reconnect_if(mut self, predicate: fn(&io::Error) -> bool) -> Self {
+        self.reconnect_if = predicate;
+        self
+    }
+
+    /// Sets whether frames that weren't flushed when the connection failed
+    /// are sent again on the new connection. Disabled by default.
+    pub fn resend_unflushed(mut self, enabled: bool) -> Self {
+        self.resend = enabled;
+        self
+    }
+
+    /// Sets how many unflushed frames are kept to be sent again.
+    pub fn with_max_unflushed(mut self, max: usize) -> Self {
+        self.max_unflushed = max;
+        self
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec, F, Fut>
+    Reconnecting<Transport, Item, SinkItem, Codec, F, Fut>
+{
+    /// Returns the number of times a new connection was established.
+    pub fn reconnects(&self) -> usize {
+        self.reconnects
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `Reconnecting`, returning the underlying `Framed`.
+    ///
+    /// Frames waiting to be sent again on a new connection are dropped.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec, F, Fut> fmt::Debug
+    for Reconnecting<Transport, Item, SinkItem, Codec, F, Fut>
+where
+    Framed<Transport, Item, SinkItem, Codec>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reconnecting")
+            .field("inner", &self.inner)
+            .field("connecting", &self.connecting.is_some())
+            .field("reconnects", &self.reconnects)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec, F, Fut>
+    Reconnecting<Transport, Item, SinkItem, Codec, F, Fut>
+where
+    Transport: Sink<Bytes, Error = io::Error>,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = io::Result<Transport>>,
+{
+    /// Drops the current connection and starts building a new one.
+    fn disconnect(self: Pin<&mut Self>) {
+        let this = self.project();
+        if this.connecting.is_some() || this.sleep.is_some() {
+            return;
+        }
+
+        if *this.resend {
+            while let Some(frame) = this.unflushed.pop_back() {
+                this.replay.push_front(frame);
+            }
+            let excess = this.replay.len().saturating_sub(*this.max_unflushed);
+            this.replay.drain(..excess);
+        } else {
+            this.unflushed.clear();
+            this.replay.clear();
+        }
+        *this.connecting = Some(Box::pin((this.connect)()));
+    }
+
+    /// Waits for a connection, sending the frames to resend once there is
+    /// one.
+    fn poll_connected(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        use std::future::Future;
+
+        loop {
+            let this = self.as_mut().project();
+
+            if let Some(sleep) = this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                *this.sleep = None;
+                *this.connecting = Some(Box::pin((this.connect)()));
+            }
+
+            if let Some(connecting) = this.connecting {
+                let res = ready!(connecting.as_mut().poll(cx));
+                *this.connecting = None;
+                match res {
+                    Ok(transport) => {
+                        let mut framed = this.inner.project();
+                        framed.inner.set(transport);
+                        *framed.pending = false;
+                        *this.backoff = *this.min_backoff;
+                        *this.reconnects += 1;
+                    }
+                    Err(_) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(backoff = ?*this.backoff, "failed to reconnect");
+                        *this.sleep = Some(Box::pin(tokio::time::sleep(*this.backoff)));
+                        *this.backoff = (*this.backoff * 2).min(*this.max_backoff);
+                    }
+                }
+                continue;
+            }
+
+            let mut inner = this.inner;
+            let mut failed = false;
+            while let Some(frame) = this.replay.front() {
+                let res = ready!(inner.as_mut().poll_transport_ready(cx))
+                    .and_then(|()| inner.as_mut().send_raw(frame.clone()));
+                if res.is_err() {
+                    failed = true;
+                    break;
+                }
+                this.unflushed.extend(this.replay.pop_front());
+            }
+
+            if !failed {
+                return Poll::Ready(());
+            }
+            self.as_mut().disconnect();
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec, F, Fut> Stream
+    for Reconnecting<Transport, Item, SinkItem, Codec, F, Fut>
+where
+    Transport: TryStream<Ok = BytesMut, Error = io::Error> + Sink<Bytes, Error = io::Error>,
+    io::Error: From<Codec::Error>,
+    Codec: Deserializer<Item>,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = io::Result<Transport>>,
+{
+    type Item = io::Result<Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            ready!(self.as_mut().poll_connected(cx));
+
+            let this = self.as_mut().project();
+            let mut framed = this.inner.project();
+
+            // Only the transport's errors are checked, the codec's are
+            // returned as is.
+            match ready!(framed.inner.as_mut().try_poll_next(cx)) {
+                Some(Ok(bytes)) => {
+                    let res = framed.codec.deserialize_owned(bytes);
+                    return Poll::Ready(Some(res.map_err(io::Error::from)));
+                }
+                Some(Err(err)) if !(this.reconnect_if)(&err) => {
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Some(Err(_)) | None => {}
+            }
+            self.as_mut().disconnect();
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+impl<Transport, Item, SinkItem, Codec, F, Fut> Sink<SinkItem>
+    for Reconnecting<Transport, Item, SinkItem, Codec, F, Fut>
+where
+    Transport: Sink<Bytes, Error = io::Error>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<io::Error>,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = io::Result<Transport>>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        loop {
+            ready!(self.as_mut().poll_connected(cx));
+
+            match ready!(self.as_mut().project().inner.poll_ready(cx)) {
+                Err(err) if (self.reconnect_if)(&err) => self.as_mut().disconnect(),
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        let framed = self.as_mut().project().inner.project();
+        let bytes = match framed.codec.serialize(&item) {
+            Ok(bytes) => bytes,
+            Err(_) if *framed.on_serialize_error == SkipOrFail::Skip => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("dropping a value that failed to serialize");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let this = self.as_mut().project();
+        match this.inner.send_raw(bytes.clone()) {
+            Ok(()) => {
+                if *this.resend {
+                    this.unflushed.push_back(bytes);
+                    if this.unflushed.len() > *this.max_unflushed {
+                        this.unflushed.pop_front();
+                    }
+                }
+                Ok(())
+            }
+            Err(err) if (this.reconnect_if)(&err) => {
+                if *this.resend {
+                    this.replay.push_back(bytes);
+                }
+                self.disconnect();
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        loop {
+            ready!(self.as_mut().poll_connected(cx));
+
+            let this = self.as_mut().project();
+            match ready!(this.inner.poll_flush(cx)) {
+                Ok(()) => {
+                    this.unflushed.clear();
+                    return Poll::Ready(Ok(()));
+                }
+                Err(err) if (this.reconnect_if)(&err) => self.as_mut().disconnect(),
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Groups the values decoded by a [`Framed`] into batches.
+///
+/// The `Stream` half yields a `Vec<Item>` once `capacity` values have been
+/// decoded. Errors are yielded as soon as they occur, and values decoded
+/// before an error stay in the batch being built. When the transport ends, the
+/// last, possibly partial, batch is yielded. With the `time` feature, a
+/// timeout can also flush a partial batch once its first value has waited
+/// long enough.
+///
+/// The `Sink` half is forwarded to the `Framed` unchanged.
+#[pin_project]
+#[derive(Debug)]
+pub struct Chunks<Transport, Item, SinkItem, Codec> {
+    #[pin]
+    inner: Framed<Transport, Item, SinkItem, Codec>,
+    items: Vec<Item>,
+    capacity: usize,
+    timer: BatchTimer,
+}
+
+/// Flushes partial batches of [`Chunks`] after a timeout.
+///
+/// Without the `time` feature there is no timeout and this does nothing.
+#[derive(Debug, Default)]
+struct BatchTimer {
+    #[cfg(feature = "time")]
+    timeout: Option<std::time::Duration>,
+    #[cfg(feature = "time")]
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+#[cfg(feature = "time")]
+impl BatchTimer {
+    fn start(&mut self) {
+        self.sleep = self
+            .timeout
+            .map(|timeout| Box::pin(tokio::time::sleep(timeout)));
+    }
+
+    fn stop(&mut self) {
+        self.sleep = None;
+    }
+
+    fn poll_elapsed(&mut self, cx: &mut Context<'_>) -> bool {
+        use std::future::Future;
+
+        match &mut self.sleep {
+            Some(sleep) => sleep.as_mut().poll(cx).is_ready(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(not(feature = "time"))]
+impl BatchTimer {
+    fn start(&mut self) {}
+
+    fn stop(&mut self) {}
+
+    fn poll_elapsed(&mut self, _: &mut Context<'_>) -> bool {
+        false
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Chunks<Transport, Item, SinkItem, Codec> {
+    /// Creates a new `Chunks` yielding batches of `capacity` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(inner: Framed<Transport, Item, SinkItem, Codec>, capacity: usize) -> Self {
+        assert!(capacity > 0, "batch capacity must be non-zero");
+
+        Self {
+            inner,
+            items: Vec::with_capacity(capacity),
+            capacity,
+            timer: BatchTimer::default(),
+        }
+    }
+
+    /// Creates a new `Chunks` yielding batches of `capacity` values, or fewer
+    /// once `timeout` has passed since the first value of the batch was
+    /// decoded.
+    ///
+    /// The timer runs on the Tokio runtime, so the stream must be polled from
+    /// within one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    #[cfg(feature = "time")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+    pub fn with_timeout(
+        inner: Framed<Transport, Item, SinkItem, Codec>,
+        capacity: usize,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let mut chunks = Self::new(inner, capacity);
+        chunks.timer.timeout = Some(timeout);
+        chunks
+    }
+
+    /// Returns a reference to the underlying `Framed`.
+    pub fn get_ref(&self) -> &Framed<Transport, Item, SinkItem, Codec> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `Framed`.
+    pub fn get_mut(&mut self) -> &mut Framed<Transport, Item, SinkItem, Codec> {
+        &mut self.inner
+    }
+
+    /// Consumes the `Chunks`, returning the underlying `Framed`.
+    ///
+    /// Values in the batch being built are dropped.
+    pub fn into_inner(self) -> Framed<Transport, Item, SinkItem, Codec> {
+        self.inner
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Stream for Chunks<Transport, Item, SinkItem, Codec>
+where
+    Transport: TryStream<Ok = BytesMut>,
+    Transport::Error: From<Codec::Error>,
+    BytesMut: From<Transport::Ok>,
+    Codec: Deserializer<Item>,
+{
+    type Item = Result<Vec<Item>, Transport::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(item))) => {
+                    this.items.push(item);
+
+                    if this.items.len() == 1 {
+                        this.timer.start();
+                    }
+                    if this.items.len() >= *this.capacity {
+                        break;
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) if this.items.is_empty() => return Poll::Ready(None),
+                Poll::Ready(None) => break,
+                Poll::Pending if this.timer.poll_elapsed(cx) => break,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        this.timer.stop();
+        let batch = std::mem::replace(this.items, Vec::with_capacity(*this.capacity));
+        Poll::Ready(Some(Ok(batch)))
+    }
+}
+
+impl<Transport, Item, SinkItem, Codec> Sink<SinkItem> for Chunks<Transport, Item, SinkItem, Codec>
+where
+    Transport: Sink<Bytes>,
+    Codec: Serializer<SinkItem>,
+    Codec::Error: Into<Transport::Error>,
+{
+    type Error = Transport::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: SinkItem) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Length-delimited framing over an I/O object, using
+/// [tokio-util](https://docs.rs/tokio-util)'s `LengthDelimitedCodec`.
+///
+/// ```no_run
+/// # #[cfg(feature = "json")]
+/// # async fn connect() -> std::io::Result<()> {
+/// use tokio::net::TcpStream;
+/// use tokio_serde::{formats::SymmetricalJson, length_delimited};
+///
+/// let socket = TcpStream::connect("127.0.0.1:17653").await?;
+/// // Java and Go peers commonly use 4-byte big-endian prefixes.
+/// let framed = length_delimited::Builder::new()
+///     .with_max_frame_length(1 << 20)
+///     .framed(socket, SymmetricalJson::<String>::default());
+/// # let _: tokio_serde::SymmetricallyFramed<_, String, _> = framed;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "length-delimited")]
+#[cfg_attr(docsrs, doc(cfg(feature = "length-delimited")))]
+pub mod length_delimited {
+    use crate::Framed;
+    use tokio::io::{AsyncRead, AsyncWrite};
+    use tokio_util::codec::{self, LengthDelimitedCodec};
+
+    /// The byte transport created by [`Builder::framed`].
+    pub type Transport<T> = codec::Framed<T, LengthDelimitedCodec>;
+
+    /// Configures the length prefix of every frame.
+    ///
+    /// The defaults match `LengthDelimitedCodec`'s: a 4-byte big-endian
+    /// prefix and frames of up to 8 MiB.
+    #[derive(Debug, Clone)]
+    pub struct Builder {
+        length_field_length: usize,
+        big_endian: bool,
+        max_frame_length: usize,
+    }
+
+    impl Default for Builder {
+        fn default() -> Self {
+            Self {
+                length_field_length: 4,
+                big_endian: true,
+                max_frame_length: 8 * 1024 * 1024,
+            }
+        }
+    }
+
+    impl Builder {
+        /// Creates a new `Builder` with the default options.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Sets the width of the length prefix, in bytes.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `length_field_length` is not between 1 and 8.
+        pub fn with_length_field_length(mut self, length_field_length: usize) -> Self {
+            assert!(
+                (1..=8).contains(&length_field_length),
+                "length field must be 1 to 8 bytes wide"
+            );
+            self.length_field_length = length_field_length;
+            self
+        }
+
+        /// Sets whether the length prefix is big-endian or little-endian.
+        pub fn with_big_endian(mut self, big_endian: bool) -> Self {
+            self.big_endian = big_endian;
+            self
+        }
+
+        /// Sets the largest frame accepted or sent, not counting the prefix.
+        ///
+        /// Longer frames are rejected with an [`std::io::ErrorKind::InvalidData`]
+        /// error.
+        pub fn with_max_frame_length(mut self, max_frame_length: usize) -> Self {
+            self.max_frame_length = max_frame_length;
+            self
+        }
+
+        /// Creates a `LengthDelimitedCodec` with these options.
+        pub fn codec(&self) -> LengthDelimitedCodec {
+            let mut builder = LengthDelimitedCodec::builder();
+            builder
+                .length_field_length(self.length_field_length)
+                .max_frame_length(self.max_frame_length);
+            if self.big_endian {
+                builder.big_endian();
+            } else {
+                builder.little_endian();
+            }
+            builder.new_codec()
+        }
+
+        /// Frames `io` with these options and encodes the frames with `codec`.
+        pub fn framed<T, Item, SinkItem, Codec>(
+            &self,
+            io: T,
+            codec: Codec,
+        ) -> Framed<Transport<T>, Item, SinkItem, Codec>
+        where
+            T: AsyncRead + AsyncWrite,
+        {
+            Framed::new(codec::Framed::new(io, self.codec()), codec)
+        }
+    }
+}
+
+/// Agreeing on a key for [`EncryptedBincode`](formats::EncryptedBincode)
+/// over the connection itself.
+///
+/// ```no_run
+/// # #[cfg(feature = "encrypted_bincode")]
+/// # async fn connect() -> std::io::Result<()> {
+/// use tokio::net::TcpStream;
+/// use tokio_serde::{formats::SymmetricalEncryptedBincode, key_exchange};
+///
+/// let mut socket = TcpStream::connect("127.0.0.1:17653").await?;
+/// let key = key_exchange::handshake(&mut socket, true).await?;
+/// let codec = SymmetricalEncryptedBincode::<String>::new(key, None);
+/// # let _ = codec;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "kx")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kx")))]
+pub mod key_exchange {
+    use sha2::{Digest, Sha256};
+    use std::io;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use x25519_dalek::{EphemeralSecret, PublicKey};
+
+    const CONTEXT: &[u8] = b"tokio-serde key exchange v1";
+
+    /// Performs an ephemeral X25519 exchange over `io` and returns the
+    /// derived 32-byte shared secret.
+    ///
+    /// Exactly one side of the connection must pass `is_initiator = true`;
+    /// it sends its public key first. The returned secret is the SHA-256 of
+    /// the Diffie-Hellman output and both public keys, and can be passed
+    /// directly to `EncryptedBincode::new`.
+    ///
+    /// The exchange is unauthenticated: it protects against passive
+    /// eavesdroppers, but an active attacker can sit in the middle and
+    /// agree on a key with each side. Authenticate the peer some other way,
+    /// e.g. with TLS or by comparing the keys out of band, if that matters.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the peer sends a
+    /// low-order point, which would make the secret predictable.
+    pub async fn handshake<T>(mut io: T, is_initiator: bool) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let secret = EphemeralSecret::random();
+        let public = PublicKey::from(&secret);
+
+        let mut peer = [0; 32];
+        if is_initiator {
+            io.write_all(public.as_bytes()).await?;
+            io.flush().await?;
+            io.read_exact(&mut peer).await?;
+        } else {
+            io.read_exact(&mut peer).await?;
+            io.write_all(public.as_bytes()).await?;
+            io.flush().await?;
+        }
+        let peer = PublicKey::from(peer);
+
+        let shared = secret.diffie_hellman(&peer);
+        if !shared.was_contributory() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "peer sent a low-order public key",
+            ));
+        }
+
+        let (initiator, responder) = if is_initiator {
+            (public, peer)
+        } else {
+            (peer, public)
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(CONTEXT);
+        hasher.update(initiator.as_bytes());
+        hasher.update(responder.as_bytes());
+        hasher.update(shared.as_bytes());
+        Ok(hasher.finalize().to_vec())
+    }
+}
+
+/// Building blocks for hand-written binary [`Serializer`](crate::Serializer)s
+/// and [`Deserializer`](crate::Deserializer)s.
+///
+/// Length prefixes are written in the byte order chosen by the [`ByteOrder`]
+/// type parameter, or as LEB128 varints. Readers fail with
+/// [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) when the buffer ends
+/// early instead of panicking like [`Buf`](bytes::Buf)'s getters.
+///
+/// ```
+/// use bytes::BytesMut;
+/// use tokio_serde::byteorder_helpers::{get_blob, put_blob, LittleEndian};
+///
+/// let mut buf = BytesMut::new();
+/// put_blob::<LittleEndian, _>(&mut buf, b"hello");
+/// assert_eq!(&buf[..4], &[5, 0, 0, 0]);
+///
+/// let blob = get_blob::<LittleEndian, _>(&mut buf).unwrap();
+/// assert_eq!(&blob[..], b"hello");
+/// ```
+pub mod byteorder_helpers {
+    use bytes::{Buf, BufMut, Bytes};
+    use std::io;
+
+    /// Byte order of fixed-width integers, selected at compile time.
+    pub trait ByteOrder {
+        /// Writes `n` to `buf` in this byte order.
+        fn put_u32<B: BufMut>(buf: &mut B, n: u32);
+
+        /// Reads a `u32` in this byte order from `buf`.
+        ///
+        /// `buf` must hold at least four bytes.
+        fn get_u32<B: Buf>(buf: &mut B) -> u32;
+    }
+
+    /// Most significant byte first, the network byte order.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct BigEndian;
+
+    /// Least significant byte first.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct LittleEndian;
+
+    impl ByteOrder for BigEndian {
+        fn put_u32<B: BufMut>(buf: &mut B, n: u32) {
+            buf.put_u32(n);
+        }
+
+        fn get_u32<B: Buf>(buf: &mut B) -> u32 {
+            buf.get_u32()
+        }
+    }
+
+    impl ByteOrder for LittleEndian {
+        fn put_u32<B: BufMut>(buf: &mut B, n: u32) {
+            buf.put_u32_le(n);
+        }
+
+        fn get_u32<B: Buf>(buf: &mut B) -> u32 {
+            buf.get_u32_le()
+        }
+    }
+
+    /// Longest LEB128 encoding of a `u64`.
+    pub const MAX_VARINT_LEN: usize = 10;
+
+    /// Writes `n` as an unsigned LEB128 varint of one to
+    /// [`MAX_VARINT_LEN`] bytes.
+    pub fn put_varint<B: BufMut>(buf: &mut B, mut n: u64) {
+        while n >= 0x80 {
+            buf.put_u8(n as u8 | 0x80);
+            n >>= 7;
+        }
+        buf.put_u8(n as u8);
+    }
+
+    /// Reads an unsigned LEB128 varint from `buf`.
+    ///
+    /// Fails with `InvalidData` if the varint does not fit in a `u64`.
+    pub fn get_varint<B: Buf>(buf: &mut B) -> io::Result<u64> {
+        let mut n = 0u64;
+        for i in 0..MAX_VARINT_LEN {
+            if !buf.has_remaining() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let byte = buf.get_u8();
+            let bits = u64::from(byte & 0x7f);
+            if i == MAX_VARINT_LEN - 1 && bits > 1 {
+                break;
+            }
+            n |= bits << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varint overflows u64",
+        ))
+    }
+
+    /// Writes `blob` preceded by its length as a `u32` in byte order `O`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `blob` is longer than `u32::MAX` bytes.
+    pub fn put_blob<O: ByteOrder, B: BufMut>(buf: &mut B, blob: &[u8]) {
+        assert!(blob.len() <= u32::MAX as usize, "blob too long");
+        O::put_u32(buf, blob.len() as u32);
+        buf.put_slice(blob);
+    }
+
+    /// Reads a blob written by [`put_blob`] with the same byte order.
+    pub fn get_blob<O: ByteOrder, B: Buf>(buf: &mut B) -> io::Result<Bytes> {
+        if buf.remaining() < 4 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let len = O::get_u32(buf) as usize;
+        take(buf, len)
+    }
+
+    /// Writes `blob` preceded by its length as a varint.
+    pub fn put_varint_blob<B: BufMut>(buf: &mut B, blob: &[u8]) {
+        put_varint(buf, blob.len() as u64);
+        buf.put_slice(blob);
+    }
+
+    /// Reads a blob written by [`put_varint_blob`].
+    pub fn get_varint_blob<B: Buf>(buf: &mut B) -> io::Result<Bytes> {
+        let len = get_varint(buf)?;
+        if len > buf.remaining() as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        take(buf, len as usize)
+    }
+
+    fn take<B: Buf>(buf: &mut B, len: usize) -> io::Result<Bytes> {
+        if buf.remaining() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(buf.copy_to_bytes(len))
+    }
+}
+
+/// Fingerprints of the shape of serde types, for use with
+/// [`Framed::negotiate`](crate::Framed::negotiate).
+///
+/// ```
+/// use serde::Deserialize;
+/// use tokio_serde::fingerprint;
+///
+/// #[derive(Deserialize)]
+/// struct V1 {
+///     id: u64,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct V2 {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// assert_ne!(fingerprint::of::<V1>(), fingerprint::of::<V2>());
+/// ```
+#[cfg(feature = "fingerprint")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fingerprint")))]
+pub mod fingerprint {
+    use serde::de::{self, Deserialize, Visitor};
+    use std::fmt;
+
+    /// Returns a fingerprint of the outer shape of `T`.
+    ///
+    /// The shape is what `T`'s `Deserialize` implementation asks for: the
+    /// name and field names of a struct, the name and variant names of an
+    /// enum, or the kind of a primitive. Field types and nested types are not
+    /// looked into, so renaming or adding a field changes the fingerprint
+    /// while changing the type of one does not. Types deserialized through
+    /// `deserialize_any`, e.g. untagged enums, all share one fingerprint.
+    /// Pass a hand-picked constant to `negotiate` instead if that isn't
+    /// precise enough.
+    ///
+    /// The fingerprint is a 64-bit FNV-1a hash, stable across builds and
+    /// platforms.
+    pub fn of<'de, T: Deserialize<'de>>() -> u64 {
+        let mut shape = String::new();
+        // The probe always fails once it has seen the shape.
+        let _ = T::deserialize(Probe { shape: &mut shape });
+        hash(shape.as_bytes())
+    }
+
+    fn hash(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Records the first request made by a `Deserialize` implementation.
+    struct Probe<'a> {
+        shape: &'a mut String,
+    }
+
+    impl Probe<'_> {
+        fn record<T>(self, shape: String) -> Result<T, Done> {
+            *self.shape = shape;
+            Err(Done)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Done;
+
+    impl fmt::Display for Done {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("shape recorded")
+        }
+    }
+
+    impl std::error::Error for Done {}
+
+    impl de::Error for Done {
+        fn custom<T: fmt::Display>(_: T) -> Self {
+            Done
+        }
+    }
+
+    macro_rules! probe_primitives {
+        ($($method:ident => $shape:literal,)*) => {$(
+            fn $method<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Done> {
+                self.record($shape.to_owned())
+            }
+        )*};
+    }
+
+    impl<'de> de::Deserializer<'de> for Probe<'_> {
+        type Error = Done;
+
+        probe_primitives! {
+            deserialize_any => "any",
+            deserialize_bool => "bool",
+            deserialize_i8 => "i8",
+            deserialize_i16 => "i16",
+            deserialize_i32 => "i32",
+            deserialize_i64 => "i64",
+            deserialize_i128 => "i128",
+            deserialize_u8 => "u8",
+            deserialize_u16 => "u16",
+            deserialize_u32 => "u32",
+            deserialize_u64 => "u64",
+            deserialize_u128 => "u128",
+            deserialize_f32 => "f32",
+            deserialize_f64 => "f64",
+            deserialize_char => "char",
+            deserialize_str => "str",
+            deserialize_string => "str",
+            deserialize_bytes => "bytes",
+            deserialize_byte_buf => "bytes",
+            deserialize_option => "option",
+            deserialize_unit => "unit",
+            deserialize_seq => "seq",
+            deserialize_map => "map",
+            deserialize_identifier => "identifier",
+            deserialize_ignored_any => "ignored",
+        }
+
+        fn deserialize_unit_struct<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            _: V,
+        ) -> Result<V::Value, Done> {
+            self.record(format!("unit_struct {}", name))
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            _: V,
+        ) -> Result<V::Value, Done> {
+            self.record(format!("newtype_struct {}", name))
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, _: V) -> Result<V::Value, Done> {
+            self.record(format!("tuple {}", len))
+        }
+
+        fn deserialize_tuple_struct<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            len: usize,
+            _: V,
+        ) -> Result<V::Value, Done> {
+            self.record(format!("tuple_struct {} {}", name, len))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Done> {
+            self.record(format!("struct {} {}", name, fields.join(",")))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            variants: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Done> {
+            self.record(format!("enum {} {}", name, variants.join(",")))
+        }
+    }
+}
+
+/// Serde-based codecs for common data formats.
+///
+/// # Binary data
+///
+/// serde sees `Vec<u8>` as a sequence, so self-describing binary formats like
+/// MessagePack and CBOR write it as an array of integers. Mark binary fields
+/// with `#[serde(with = "serde_bytes")]`, or use `serde_bytes::ByteBuf`, to
+/// write them as a native byte string, one byte per byte plus a short header,
+/// and decode them without going element by element.
+///
+/// [`Deserializer::deserialize`] only borrows the frame for the length of the
+/// call, so byte strings decoded through it are copied out of the frame and a
+/// `Cow<[u8]>` field comes back owned. With bincode, a [`FrameBytes`] field
+/// avoids that copy: decoded through [`Deserializer::deserialize_owned`], as
+/// [`Framed`] does, it shares the frame's allocation.
+///
+/// [`FrameBytes`]: crate::formats::FrameBytes
+#[cfg(any(
+    feature = "json",
+    feature = "bincode",
+    feature = "bincode2",
+    feature = "messagepack",
+    feature = "cbor",
+    feature = "encrypted_bincode",
+    feature = "simd-json",
+    feature = "transcode",
+    feature = "protobuf",
+    feature = "bytemuck"
+))]
+pub mod formats {
+    #[cfg(any(
+        feature = "json",
+        feature = "bincode",
+        feature = "messagepack",
+        feature = "cbor"
+    ))]
+    pub use self::any::*;
+    #[cfg(feature = "bincode")]
+    pub use self::bincode::*;
+    #[cfg(feature = "bincode2")]
+    pub use self::bincode2::*;
+    #[cfg(feature = "cbor")]
+    pub use self::cbor::*;
+    #[cfg(feature = "encrypted_bincode")]
+    pub use self::encrypted_bincode::*;
+    #[cfg(feature = "json")]
+    pub use self::json::*;
+    #[cfg(feature = "messagepack")]
+    pub use self::messagepack::*;
+    #[cfg(feature = "bytemuck")]
+    pub use self::plain::*;
+    #[cfg(feature = "protobuf")]
+    pub use self::protobuf::*;
+    #[cfg(any(feature = "json", feature = "bincode"))]
+    pub use self::seed::*;
+    #[cfg(feature = "simd-json")]
+    pub use self::simd_json::*;
+    #[cfg(feature = "transcode")]
+    pub use self::transcode::*;
+
+    use super::{Deserializer, Serializer};
+    use bytes::{Bytes, BytesMut};
+    use educe::Educe;
+    use std::{marker::PhantomData, pin::Pin};
+
+    /// Skips the UTF-8 byte order mark some producers put in front of text
+    /// formats. Parsers reject it as an unexpected character otherwise.
+    #[cfg(any(feature = "json", feature = "simd-json"))]
+    pub(crate) fn strip_bom(src: &[u8]) -> &[u8] {
+        src.strip_prefix(b"\xef\xbb\xbf").unwrap_or(src)
+    }
+
+    #[cfg(feature = "encrypted_bincode")]
+    mod encrypted_bincode {
+        use super::*;
         use bincode_crate::config::Options;
         use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
         use chacha20poly1305::aead::{Aead, NewAead};
         use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
-        use std::io;
-        use std::io::ErrorKind;
-        use secrecy::{ExposeSecret, Secret};
+        use secrecy::{ExposeSecret, Secret};
+        use serde::{Deserialize, Serialize};
+        use std::io;
+        use std::io::ErrorKind;
+        use zeroize::{Zeroize, Zeroizing};
+
+        const NONCE_LEN: usize = 24;
+
+        fn gen_key() -> Secret<Vec<u8>> {
+            let mut res = Key::default();
+            let mut rng = OsRng;
+            rng.fill_bytes(&mut res);
+            let key = Secret::new(res.to_vec());
+            res.zeroize();
+            key
+        }
+
+        /// Encrypted bincode codec using [bincode](https://docs.rs/bincode) crate
+        /// for serialization and [chacha20poly1305](https://docs.rs/chacha20poly1305) for encryption.
+        ///
+        /// The key and every intermediate plaintext buffer produced while
+        /// encrypting or decrypting a frame are zeroed when dropped, so
+        /// plaintext does not linger in freed heap memory. Values handed to or
+        /// returned from the codec are not covered by this guarantee.
+        #[cfg_attr(docsrs, doc(cfg(feature = "encrypted_bincode")))]
+        #[derive(Educe)]
+        #[educe(Debug)]
+        pub struct EncryptedBincode<Item, SinkItem, O = bincode_crate::DefaultOptions> {
+            #[educe(Debug(ignore))]
+            options: O,
+            #[educe(Debug(ignore))]
+            ghost: PhantomData<(Item, SinkItem)>,
+            #[educe(Debug(ignore))]
+            key: Secret<Vec<u8>>,
+            #[educe(Debug(ignore))]
+            cipher: XChaCha20Poly1305,
+        }
+
+        fn cipher(key: &Secret<Vec<u8>>) -> XChaCha20Poly1305 {
+            XChaCha20Poly1305::new(Key::from_slice(key.expose_secret()))
+        }
+
+        impl<Item, SinkItem, O> EncryptedBincode<Item, SinkItem, O>
+        where
+            O: Options + Default,
+        {
+            pub fn new(key: Vec<u8>, opts: Option<O>) -> Self {
+                let key = Secret::new(key);
+                Self {
+                    options: opts.unwrap_or_default(),
+                    ghost: PhantomData,
+                    cipher: cipher(&key),
+                    key,
+                }
+            }
+        }
+
+        impl<Item, SinkItem, O> EncryptedBincode<Item, SinkItem, O> {
+            /// Creates a new `EncryptedBincode` with the given bincode options
+            /// and a freshly generated random key.
+            ///
+            /// Unlike [`default`](Default::default), this works with any
+            /// options type. The key can be read back with
+            /// [`key`](Self::key) to share it with the peer.
+            pub fn new_random(opts: O) -> Self {
+                let key = gen_key();
+                Self {
+                    options: opts,
+                    ghost: PhantomData,
+                    cipher: cipher(&key),
+                    key,
+                }
+            }
+
+            /// Returns the key frames are currently encrypted with.
+            ///
+            /// Anyone holding these bytes can read and forge frames, so they
+            /// should only be handed to the peer over a secure channel.
+            pub fn key(&self) -> &[u8] {
+                self.key.expose_secret()
+            }
+
+            /// Replaces the key used for every following frame.
+            ///
+            /// The cipher is set up once per key rather than once per frame,
+            /// so this is also where it gets rebuilt. Frames encrypted under
+            /// the previous key can no longer be decrypted.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `key` is not 32 bytes long.
+            pub fn rotate_key(&mut self, key: Vec<u8>) {
+                self.key = Secret::new(key);
+                self.cipher = cipher(&self.key);
+            }
+        }
+
+        impl<Item, SinkItem> Default for EncryptedBincode<Item, SinkItem> {
+            fn default() -> Self {
+                let key = gen_key();
+                EncryptedBincode {
+                    options: Default::default(),
+                    ghost: PhantomData,
+                    cipher: cipher(&key),
+                    key,
+                }
+            }
+        }
+
+        impl<Item, SinkItem, O> From<O> for EncryptedBincode<Item, SinkItem, O>
+        where
+            O: Options,
+        {
+            fn from(options: O) -> Self {
+                Self::new_random(options)
+            }
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "encrypted_bincode")))]
+        pub type SymmetricalEncryptedBincode<T, O = bincode_crate::DefaultOptions> =
+            EncryptedBincode<T, T, O>;
+
+        impl<Item, SinkItem, O> Deserializer<Item> for EncryptedBincode<Item, SinkItem, O>
+        where
+            for<'a> Item: Deserialize<'a>,
+            O: Options + Clone,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                if src.len() < NONCE_LEN {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "frame is too short to carry a nonce",
+                    ));
+                }
+
+                let nonce = XNonce::from_slice(&src[..NONCE_LEN]);
+                let data = Zeroizing::new(
+                    self.cipher
+                        .decrypt(nonce, &src[NONCE_LEN..])
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+                );
+                self.options
+                    .clone()
+                    .deserialize(&data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Item, SinkItem, O> Serializer<SinkItem> for EncryptedBincode<Item, SinkItem, O>
+        where
+            SinkItem: Serialize,
+            O: Options + Clone,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let mut nonce = XNonce::default();
+                let mut rng = OsRng;
+                rng.fill_bytes(&mut nonce);
+                let mut res = nonce.to_vec();
+                let ser = Zeroizing::new(
+                    self.options
+                        .clone()
+                        .serialize(&item)
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?,
+                );
+                let mut other = self
+                    .cipher
+                    .encrypt(&nonce, ser.as_slice())
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                res.append(&mut other);
+                Ok(Bytes::from(res))
+            }
+        }
+    }
+
+    #[cfg(feature = "bincode2")]
+    mod bincode2 {
+        use super::*;
+        use bincode2_crate::config::{Config, Configuration};
+        use serde::{de::DeserializeOwned, Serialize};
+        use std::io;
+
+        /// Bincode codec using the 2.x API of the
+        /// [bincode](https://docs.rs/bincode) crate through its serde support.
+        ///
+        /// The encoding is determined by the bincode 2 configuration `C`,
+        /// [`bincode::config::standard`] by default, which uses
+        /// variable-length integers and is not compatible with the 1.x
+        /// [`Bincode`](super::Bincode) codec's defaults. Frames with bytes
+        /// left over after the value are rejected.
+        ///
+        /// [`bincode::config::standard`]: https://docs.rs/bincode/2/bincode/config/fn.standard.html
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode2")))]
+        #[derive(Educe)]
+        #[educe(Debug)]
+        pub struct Bincode2<Item, SinkItem, C = Configuration> {
+            #[educe(Debug(ignore))]
+            config: C,
+            #[educe(Debug(ignore))]
+            ghost: PhantomData<(Item, SinkItem)>,
+        }
+
+        impl<Item, SinkItem> Default for Bincode2<Item, SinkItem> {
+            fn default() -> Self {
+                Self::from(bincode2_crate::config::standard())
+            }
+        }
+
+        impl<Item, SinkItem, C> From<C> for Bincode2<Item, SinkItem, C>
+        where
+            C: Config,
+        {
+            fn from(config: C) -> Self {
+                Self {
+                    config,
+                    ghost: PhantomData,
+                }
+            }
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode2")))]
+        pub type SymmetricalBincode2<T, C = Configuration> = Bincode2<T, T, C>;
+
+        impl<Item, SinkItem, C> Deserializer<Item> for Bincode2<Item, SinkItem, C>
+        where
+            Item: DeserializeOwned,
+            C: Config,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let (item, read) = bincode2_crate::serde::decode_from_slice(src, self.config)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                if read != src.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "trailing bytes after bincode value",
+                    ));
+                }
+
+                Ok(item)
+            }
+        }
+
+        impl<Item, SinkItem, C> Serializer<SinkItem> for Bincode2<Item, SinkItem, C>
+        where
+            SinkItem: Serialize,
+            C: Config,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                bincode2_crate::serde::encode_to_vec(item, self.config)
+                    .map(Into::into)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+
+    #[cfg(feature = "bincode")]
+    mod bincode {
+        use super::human_readable::{Seed, Value, WithSeed};
+        use super::*;
+        use crate::with_headroom;
+        use bincode_crate::config::{
+            BigEndian, Bounded, FixintEncoding, LittleEndian, Options, VarintEncoding,
+            WithOtherEndian, WithOtherIntEncoding, WithOtherLimit,
+        };
+        use bytes::BufMut;
+        use serde::de::{self, DeserializeSeed, Visitor};
+        use serde::{Deserialize, Serialize};
+        use std::{cell::RefCell, fmt, io, ops::Deref};
+
+        /// Bincode codec using [bincode](https://docs.rs/bincode) crate.
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+        #[derive(Educe)]
+        #[educe(Debug)]
+        pub struct Bincode<Item, SinkItem, O = bincode_crate::DefaultOptions> {
+            #[educe(Debug(ignore))]
+            options: O,
+            #[educe(Debug(ignore))]
+            ghost: PhantomData<(Item, SinkItem)>,
+            byte_limit: Option<u64>,
+            human_readable: Option<bool>,
+        }
+
+        impl<Item, SinkItem> Default for Bincode<Item, SinkItem> {
+            fn default() -> Self {
+                Bincode {
+                    options: Default::default(),
+                    ghost: PhantomData,
+                    byte_limit: None,
+                    human_readable: None,
+                }
+            }
+        }
+
+        impl<Item, SinkItem, O> From<O> for Bincode<Item, SinkItem, O>
+        where
+            O: Options,
+        {
+            fn from(options: O) -> Self {
+                Self {
+                    options,
+                    ghost: PhantomData,
+                    byte_limit: None,
+                    human_readable: None,
+                }
+            }
+        }
+
+        impl<Item, SinkItem, O> Bincode<Item, SinkItem, O>
+        where
+            O: Options,
+        {
+            /// Limits the number of bytes bincode will read or write for a
+            /// single value.
+            ///
+            /// A frame that claims a larger value than `limit`, for instance a
+            /// collection with a huge declared length, is rejected with
+            /// [`io::ErrorKind::InvalidData`] before it can cause a large
+            /// allocation.
+            ///
+            /// This is independent of any maximum frame length enforced by the
+            /// transport, e.g. `LengthDelimitedCodec::max_frame_length`: that
+            /// bounds the bytes received, while this bounds what bincode is
+            /// willing to decode from them. Setting `limit` no higher than the
+            /// transport's maximum frame length keeps both consistent.
+            pub fn with_byte_limit(mut self, limit: u64) -> Self {
+                self.byte_limit = Some(limit);
+                self
+            }
+
+            /// Sets what serde's `is_human_readable` reports to the types
+            /// being encoded and decoded.
+            ///
+            /// bincode is a binary format, so types such as `IpAddr` pick
+            /// their compact form by default. Forcing `true` makes them use the
+            /// same representation as in JSON. Both peers must agree.
+            pub fn with_human_readable(mut self, human_readable: bool) -> Self {
+                self.human_readable = Some(human_readable);
+                self
+            }
+
+            /// Returns an iterator decoding the back-to-back values of `src`
+            /// one at a time.
+            ///
+            /// This is meant for frames batching many records: each value is
+            /// decoded only when the iterator is advanced, so the records can
+            /// be processed without collecting them into a `Vec` first. The
+            /// byte limit, if any, applies to every value separately.
+            pub fn deserialize_iter<'a, T>(&'a self, src: &'a BytesMut) -> DecodeIter<'a, Self, T>
+            where
+                T: for<'de> Deserialize<'de>,
+            {
+                DecodeIter {
+                    codec: self,
+                    rest: src,
+                    ghost: PhantomData,
+                }
+            }
+        }
+
+        /// Iterator over the values of a frame, returned by
+        /// [`Bincode::deserialize_iter`].
+        ///
+        /// It ends once the frame is used up. A value that fails to decode,
+        /// including one cut short by the end of the frame, is yielded as an
+        /// error and ends the iteration.
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+        #[derive(Educe)]
+        #[educe(Debug)]
+        pub struct DecodeIter<'a, Inner, T> {
+            #[educe(Debug(ignore))]
+            codec: &'a Inner,
+            rest: &'a [u8],
+            #[educe(Debug(ignore))]
+            ghost: PhantomData<fn() -> T>,
+        }
+
+        impl<'a, Item, SinkItem, O, T> Iterator for DecodeIter<'a, Bincode<Item, SinkItem, O>, T>
+        where
+            O: Options + Clone,
+            T: for<'de> Deserialize<'de>,
+        {
+            type Item = io::Result<T>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.rest.is_empty() {
+                    return None;
+                }
+
+                let codec = self.codec;
+                let seed = Seed::new(codec.human_readable);
+                let options = codec.options.clone();
+                let res = match codec.byte_limit {
+                    Some(limit) => options
+                        .with_limit(limit)
+                        .deserialize_from_seed(seed, &mut self.rest),
+                    None => options.deserialize_from_seed(seed, &mut self.rest),
+                };
+
+                Some(res.map_err(|e| {
+                    self.rest = &[];
+                    io::Error::new(io::ErrorKind::InvalidData, e)
+                }))
+            }
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+        pub type SymmetricalBincode<T, O = bincode_crate::DefaultOptions> = Bincode<T, T, O>;
+
+        /// Builder for a [`Bincode`] codec with custom bincode options.
+        ///
+        /// Each method wraps the options configured so far, so the resulting
+        /// codec is fully typed without having to spell out the bincode option
+        /// types or import [`bincode_crate::Options`].
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use bytes::BytesMut;
+        /// use std::pin::Pin;
+        /// use tokio_serde::{formats::BincodeBuilder, Deserializer, Serializer};
+        ///
+        /// let mut codec = BincodeBuilder::new()
+        ///     .big_endian()
+        ///     .fixint()
+        ///     .with_limit(16)
+        ///     .build::<u32, u32>();
+        ///
+        /// let buf = Pin::new(&mut codec).serialize(&5).unwrap();
+        /// assert_eq!(buf, &b"\x00\x00\x00\x05"[..]);
+        ///
+        /// let value = Pin::new(&mut codec).deserialize(&BytesMut::from(&buf[..])).unwrap();
+        /// assert_eq!(value, 5);
+        /// ```
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+        #[derive(Educe)]
+        #[educe(Debug)]
+        pub struct BincodeBuilder<O = bincode_crate::DefaultOptions> {
+            #[educe(Debug(ignore))]
+            options: O,
+        }
+
+        impl BincodeBuilder {
+            /// Creates a builder starting from bincode's default options.
+            pub fn new() -> Self {
+                Self {
+                    options: bincode_crate::DefaultOptions::new(),
+                }
+            }
+        }
+
+        impl Default for BincodeBuilder {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<O> BincodeBuilder<O>
+        where
+            O: Options,
+        {
+            /// Encodes integers in big endian byte order.
+            pub fn big_endian(self) -> BincodeBuilder<WithOtherEndian<O, BigEndian>> {
+                BincodeBuilder {
+                    options: self.options.with_big_endian(),
+                }
+            }
+
+            /// Encodes integers in little endian byte order.
+            pub fn little_endian(self) -> BincodeBuilder<WithOtherEndian<O, LittleEndian>> {
+                BincodeBuilder {
+                    options: self.options.with_little_endian(),
+                }
+            }
+
+            /// Encodes integers with their full fixed width.
+            pub fn fixint(self) -> BincodeBuilder<WithOtherIntEncoding<O, FixintEncoding>> {
+                BincodeBuilder {
+                    options: self.options.with_fixint_encoding(),
+                }
+            }
+
+            /// Encodes integers with a variable length encoding.
+            pub fn varint(self) -> BincodeBuilder<WithOtherIntEncoding<O, VarintEncoding>> {
+                BincodeBuilder {
+                    options: self.options.with_varint_encoding(),
+                }
+            }
+
+            /// Limits the number of bytes read or written for a single value.
+            pub fn with_limit(self, limit: u64) -> BincodeBuilder<WithOtherLimit<O, Bounded>> {
+                BincodeBuilder {
+                    options: self.options.with_limit(limit),
+                }
+            }
+
+            /// Builds a [`Bincode`] codec using the configured options.
+            pub fn build<Item, SinkItem>(self) -> Bincode<Item, SinkItem, O> {
+                Bincode::from(self.options)
+            }
+        }
+
+        thread_local! {
+            static CURRENT_FRAME: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+        }
+
+        /// Makes the frame being decoded visible to [`FrameBytes`] until
+        /// dropped.
+        struct CurrentFrame(Option<Bytes>);
+
+        impl CurrentFrame {
+            fn enter(frame: Bytes) -> Self {
+                CurrentFrame(CURRENT_FRAME.with(|current| current.replace(Some(frame))))
+            }
+        }
+
+        impl Drop for CurrentFrame {
+            fn drop(&mut self) {
+                let prev = self.0.take();
+                CURRENT_FRAME.with(|current| *current.borrow_mut() = prev);
+            }
+        }
+
+        /// A byte blob that borrows from the frame it was decoded from.
+        ///
+        /// When a [`Bincode`] codec decodes an owned frame, as [`Framed`]
+        /// does, `FrameBytes` fields are sliced out of the frame's buffer
+        /// instead of being copied. Anywhere else, including codecs with a
+        /// byte limit, the bytes are copied once. It is written as a plain
+        /// byte array, so the wire format matches `serde_bytes`.
+        ///
+        /// [`Framed`]: crate::Framed
+        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+        #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+        pub struct FrameBytes(Bytes);
+
+        impl FrameBytes {
+            /// Creates a new `FrameBytes` from `bytes`.
+            pub fn new(bytes: Bytes) -> Self {
+                FrameBytes(bytes)
+            }
+
+            /// Consumes the `FrameBytes`, returning the underlying buffer.
+            pub fn into_bytes(self) -> Bytes {
+                self.0
+            }
+        }
+
+        impl From<Bytes> for FrameBytes {
+            fn from(bytes: Bytes) -> Self {
+                FrameBytes(bytes)
+            }
+        }
+
+        impl From<FrameBytes> for Bytes {
+            fn from(bytes: FrameBytes) -> Self {
+                bytes.0
+            }
+        }
+
+        impl Deref for FrameBytes {
+            type Target = Bytes;
+
+            fn deref(&self) -> &Bytes {
+                &self.0
+            }
+        }
+
+        impl Serialize for FrameBytes {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for FrameBytes {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_bytes(FrameBytesVisitor)
+            }
+        }
+
+        struct FrameBytesVisitor;
+
+        impl<'de> Visitor<'de> for FrameBytesVisitor {
+            type Value = FrameBytes;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte array")
+            }
+
+            fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<FrameBytes, E> {
+                let sliced = CURRENT_FRAME.with(|current| {
+                    let current = current.borrow();
+                    let frame = current.as_ref()?;
+                    let start = (v.as_ptr() as usize).checked_sub(frame.as_ptr() as usize)?;
+                    if start + v.len() <= frame.len() {
+                        Some(frame.slice_ref(v))
+                    } else {
+                        None
+                    }
+                });
+
+                Ok(FrameBytes(
+                    sliced.unwrap_or_else(|| Bytes::copy_from_slice(v)),
+                ))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<FrameBytes, E> {
+                Ok(FrameBytes(Bytes::copy_from_slice(v)))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<FrameBytes, E> {
+                Ok(FrameBytes(Bytes::from(v)))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<FrameBytes, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(FrameBytes(Bytes::from(bytes)))
+            }
+        }
+
+        impl<Item, SinkItem, O> Deserializer<Item> for Bincode<Item, SinkItem, O>
+        where
+            for<'a> Item: Deserialize<'a>,
+            O: Options + Clone,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let limit = match self.byte_limit {
+                    Some(limit) => limit,
+                    None => {
+                        return self
+                            .options
+                            .clone()
+                            .deserialize_seed(Seed::new(self.human_readable), src)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                    }
+                };
+
+                // bincode ignores the limit when decoding from a slice, so
+                // decode through a reader and check for trailing bytes here.
+                let mut reader = &src[..];
+                let item = self
+                    .options
+                    .clone()
+                    .with_limit(limit)
+                    .deserialize_from_seed(Seed::new(self.human_readable), &mut reader)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                if !reader.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "trailing bytes after bincode value",
+                    ));
+                }
+
+                Ok(item)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                if self.byte_limit.is_some() {
+                    return self.deserialize(&src);
+                }
+
+                let frame = src.freeze();
+                let _guard = CurrentFrame::enter(frame.clone());
+                self.options
+                    .clone()
+                    .deserialize_seed(Seed::new(self.human_readable), &frame)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Item, SinkItem, O, S> Deserializer<Item> for SeedCodec<Bincode<Item, SinkItem, O>, S>
+        where
+            O: Options + Clone,
+            S: Clone + for<'de> DeserializeSeed<'de, Value = Item>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let codec = &self.codec;
+                let seed = WithSeed::new(self.seed.clone(), codec.human_readable);
+                let options = codec.options.clone();
+                let limit = match codec.byte_limit {
+                    Some(limit) => limit,
+                    None => {
+                        return options
+                            .deserialize_seed(seed, src)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                    }
+                };
+
+                let mut reader = &src[..];
+                let item = options
+                    .with_limit(limit)
+                    .deserialize_from_seed(seed, &mut reader)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                if !reader.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "trailing bytes after bincode value",
+                    ));
+                }
+
+                Ok(item)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                let codec = &self.codec;
+                if codec.byte_limit.is_some() {
+                    return self.deserialize(&src);
+                }
+
+                let frame = src.freeze();
+                let _guard = CurrentFrame::enter(frame.clone());
+                codec
+                    .options
+                    .clone()
+                    .deserialize_seed(
+                        WithSeed::new(self.seed.clone(), codec.human_readable),
+                        &frame,
+                    )
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Item, SinkItem, O> Serializer<SinkItem> for Bincode<Item, SinkItem, O>
+        where
+            SinkItem: Serialize,
+            O: Options + Clone,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let options = self.options.clone();
+                let item = Value::new(item, self.human_readable);
+                let res = match self.byte_limit {
+                    Some(limit) => options.with_limit(limit).serialize(&item),
+                    None => options.serialize(&item),
+                };
+
+                Ok(res
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .into())
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                fn encode<O: Options + Clone>(
+                    options: O,
+                    item: &impl Serialize,
+                    headroom: usize,
+                ) -> bincode_crate::Result<BytesMut> {
+                    let len = options.clone().serialized_size(item)?;
+                    let mut frame = with_headroom(headroom, len as usize).writer();
+                    options.serialize_into(&mut frame, item)?;
+                    Ok(frame.into_inner())
+                }
+
+                let options = self.options.clone();
+                let item = Value::new(item, self.human_readable);
+                let res = match self.byte_limit {
+                    Some(limit) => encode(options.with_limit(limit), &item, headroom),
+                    None => encode(options, &item, headroom),
+                };
+
+                res.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let options = self.options.clone();
+                let item = Value::new(item, self.human_readable);
+                let res = match self.byte_limit {
+                    Some(limit) => options
+                        .with_limit(limit)
+                        .serialize_into(buf.writer(), &item),
+                    None => options.serialize_into(buf.writer(), &item),
+                };
+
+                // Keep the `WriteZero` raised by a full `buf`.
+                res.map_err(|e| match *e {
+                    bincode_crate::ErrorKind::Io(e) => e,
+                    e => io::Error::new(io::ErrorKind::InvalidData, e),
+                })
+            }
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod json {
+        use super::human_readable::{self, Seed, Value, WithSeed};
+        use super::small_frame::{freeze, SmallFrame};
+        use super::*;
+        use crate::combinators::{decode_open, OpenEnum, UnknownVariant};
+        use crate::with_headroom;
+        use bytes::BufMut;
+        use pin_project::pin_project;
+        use serde::{de::DeserializeSeed, Deserialize, Serialize};
+        #[cfg(feature = "json-raw")]
+        use serde_json::value::RawValue;
+
+        /// JSON codec using [serde_json](https://docs.rs/serde_json) crate.
+        ///
+        /// With the `unified-error` feature enabled the codec reports
+        /// [`std::io::Error`] like the other built-in codecs instead of
+        /// [`serde_json::Error`]. Parse errors then wrap a [`JsonError`] which
+        /// keeps the position in the frame where decoding failed.
+        ///
+        /// The `json-ordered` feature makes maps keep their insertion order
+        /// through a decode and re-encode cycle. It enables serde_json's
+        /// `preserve_order`, so [`serde_json::Map`] and [`serde_json::Value`]
+        /// objects keep the key order of the frame. Re-encoding such a value
+        /// then yields the same bytes as the frame it came from, given compact
+        /// input. `preserve_order` applies to every user of serde_json in the
+        /// build, so there is nothing to switch on per codec.
+        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct Json<Item, SinkItem> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<(Item, SinkItem)>,
+            human_readable: Option<bool>,
+            concatenated: bool,
+        }
+
+        impl<Item, SinkItem> Json<Item, SinkItem> {
+            /// Creates a codec for streams of concatenated JSON values.
+            ///
+            /// Some producers write JSON values back to back, separated only
+            /// by optional whitespace, relying on JSON being self-delimiting.
+            /// In this mode [`Deserializer::deserialize`] decodes the first
+            /// value of the frame and ignores whatever follows it. Use
+            /// [`deserialize_with_remainder`](Self::deserialize_with_remainder)
+            /// to learn how many bytes it took and loop over the rest.
+            ///
+            /// `Deserializer::deserialize` returns one item per frame, so a
+            /// [`Framed`](crate::Framed) using this codec keeps the first
+            /// value of each frame and silently drops every value after it.
+            /// Only use it there if the transport delivers one value per
+            /// frame, and call `deserialize_with_remainder` on the frames
+            /// yourself otherwise.
+            pub fn concatenated() -> Self {
+                Self {
+                    concatenated: true,
+                    ..Self::default()
+                }
+            }
+
+            /// Sets what serde's `is_human_readable` reports to the types
+            /// being encoded and decoded.
+            ///
+            /// JSON is human-readable by default. Forcing `false` makes types
+            /// such as `IpAddr` use the compact form they have in binary
+            /// formats. Both peers must agree.
+            pub fn with_human_readable(mut self, human_readable: bool) -> Self {
+                self.human_readable = Some(human_readable);
+                self
+            }
+
+            /// Deserializes a value that may borrow from `src`.
+            ///
+            /// Unlike [`Deserializer::deserialize`], the decoded value is tied
+            /// to the lifetime of the frame buffer, so fields like `&str` or
+            /// `Cow<str>` marked `#[serde(borrow)]` can point into `src`
+            /// instead of being copied. Strings containing escape sequences
+            /// still have to be unescaped into an owned buffer.
+            pub fn deserialize_cow<'de, T>(&self, src: &'de BytesMut) -> Result<T, Error>
+            where
+                T: Deserialize<'de>,
+            {
+                from_slice(src, self.human_readable).map_err(into_error)
+            }
+
+            /// Deserializes the first JSON value of `src` and returns it
+            /// together with the number of bytes it took.
+            ///
+            /// The count includes the whitespace following the value, so
+            /// `&src[consumed..]` starts at the next value, or is empty once
+            /// the last one has been read. Trailing data after the first value
+            /// is not an error, which makes this the building block for
+            /// reading [concatenated](Self::concatenated) JSON streams.
+            pub fn deserialize_with_remainder<'de, T>(
+                &self,
+                src: &'de [u8],
+            ) -> Result<(T, usize), Error>
+            where
+                T: Deserialize<'de>,
+            {
+                first_value(src, self.human_readable).map_err(into_error)
+            }
+
+            /// Serializes the elements of `iter` as a single JSON array frame.
+            ///
+            /// Elements are written to the frame one at a time, so a large
+            /// result set can be exported from an iterator without collecting
+            /// it into a `Vec` first. The frame decodes like a serialized
+            /// `Vec` of the elements.
+            pub fn serialize_array<I>(&self, iter: I) -> Result<Bytes, Error>
+            where
+                I: IntoIterator,
+                I::Item: Serialize,
+            {
+                use serde::ser::{SerializeSeq, Serializer as _};
+
+                let mut frame = BytesMut::new().writer();
+                let mut ser = serde_json::Serializer::new(&mut frame);
+                let mut seq = ser.serialize_seq(None).map_err(into_error)?;
+                for element in iter {
+                    seq.serialize_element(&Value::new(&element, self.human_readable))
+                        .map_err(into_error)?;
+                }
+                seq.end().map_err(into_error)?;
+
+                Ok(frame.into_inner().freeze())
+            }
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+        pub type SymmetricalJson<T> = Json<T, T>;
+
+        #[cfg(feature = "json-raw")]
+        impl<Item, SinkItem> Json<Item, SinkItem> {
+            /// Checks that `src` holds a single JSON value and returns it
+            /// without parsing it into a Rust type.
+            ///
+            /// Together with [`get_field`] this lets a router look at a few
+            /// fields and forward the rest untouched: a `Json` codec with
+            /// `SinkItem = Box<RawValue>` writes the value back out byte for
+            /// byte. Requires the `json-raw` feature.
+            #[cfg_attr(docsrs, doc(cfg(feature = "json-raw")))]
+            pub fn deserialize_raw(&self, src: &BytesMut) -> Result<Box<RawValue>, Error> {
+                serde_json::from_slice(strip_bom(src)).map_err(into_error)
+            }
+        }
+
+        /// Returns the raw value of the top-level field `name` of the JSON
+        /// object `raw`, or `None` if there is no such field.
+        ///
+        /// The other fields are only scanned over, not parsed into values.
+        /// Decode the result with [`serde_json::from_str`] on
+        /// [`RawValue::get`]. Fails if `raw` is not an object.
+        #[cfg(feature = "json-raw")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "json-raw")))]
+        pub fn get_field<'a>(raw: &'a RawValue, name: &str) -> Result<Option<&'a RawValue>, Error> {
+            use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+            use std::borrow::Cow;
+
+            struct FieldVisitor<'n>(&'n str);
+
+            impl<'de> Visitor<'de> for FieldVisitor<'_> {
+                type Value = Option<&'de RawValue>;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.write_str("a JSON object")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                    let mut found = None;
+                    while let Some(key) = map.next_key::<Cow<'de, str>>()? {
+                        if found.is_none() && key == self.0 {
+                            found = Some(map.next_value()?);
+                        } else {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                    Ok(found)
+                }
+            }
+
+            let mut de = serde_json::Deserializer::from_str(raw.get());
+            de::Deserializer::deserialize_map(&mut de, FieldVisitor(name)).map_err(into_error)
+        }
+
+        fn from_slice<'de, T>(src: &'de [u8], human_readable: Option<bool>) -> serde_json::Result<T>
+        where
+            T: Deserialize<'de>,
+        {
+            let mut de = serde_json::Deserializer::from_slice(strip_bom(src));
+            let item = Seed::new(human_readable).deserialize(&mut de)?;
+            de.end()?;
+            Ok(item)
+        }
+
+        fn first_value<'de, T>(
+            src: &'de [u8],
+            human_readable: Option<bool>,
+        ) -> serde_json::Result<(T, usize)>
+        where
+            T: Deserialize<'de>,
+        {
+            use serde::de::IgnoredAny;
+
+            let stripped = strip_bom(src);
+            let mut de = serde_json::Deserializer::from_slice(stripped);
+            let item = Seed::new(human_readable).deserialize(&mut de)?;
+            // A stream starts at the deserializer's current position, which
+            // is right after the value just read.
+            let end = de.into_iter::<IgnoredAny>().byte_offset();
+            let trailing = stripped[end..]
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+
+            Ok((item, src.len() - stripped.len() + end + trailing))
+        }
+
+        #[cfg(not(feature = "unified-error"))]
+        type Error = serde_json::Error;
+        #[cfg(feature = "unified-error")]
+        type Error = std::io::Error;
+
+        #[cfg(not(feature = "unified-error"))]
+        fn into_error(err: serde_json::Error) -> Error {
+            err
+        }
+
+        #[cfg(feature = "unified-error")]
+        fn into_error(err: serde_json::Error) -> Error {
+            use serde_json::error::Category;
+
+            let kind = match err.classify() {
+                Category::Io => err.io_error_kind().unwrap_or(std::io::ErrorKind::Other),
+                Category::Eof => std::io::ErrorKind::UnexpectedEof,
+                Category::Syntax | Category::Data => std::io::ErrorKind::InvalidData,
+            };
+
+            std::io::Error::new(kind, JsonError::from(err))
+        }
+
+        /// A JSON error annotated with where in the frame it occurred.
+        ///
+        /// The underlying [`serde_json::Error`] is available as the error's
+        /// [`source`](std::error::Error::source).
+        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+        #[derive(Debug)]
+        pub struct JsonError {
+            line: usize,
+            column: usize,
+            source: serde_json::Error,
+        }
+
+        impl JsonError {
+            /// One-based line of the frame at which the error occurred, or
+            /// zero if the error is not tied to a position in the input.
+            pub fn line(&self) -> usize {
+                self.line
+            }
+
+            /// One-based column of the frame at which the error occurred, or
+            /// zero if the error is not tied to a position in the input.
+            pub fn column(&self) -> usize {
+                self.column
+            }
+
+            /// Consumes the `JsonError`, returning the underlying
+            /// [`serde_json::Error`].
+            pub fn into_inner(self) -> serde_json::Error {
+                self.source
+            }
+        }
+
+        impl From<serde_json::Error> for JsonError {
+            fn from(source: serde_json::Error) -> Self {
+                Self {
+                    line: source.line(),
+                    column: source.column(),
+                    source,
+                }
+            }
+        }
+
+        impl std::fmt::Display for JsonError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                if self.line == 0 {
+                    f.write_str("failed to process JSON frame")
+                } else {
+                    write!(
+                        f,
+                        "invalid JSON frame at line {} column {}",
+                        self.line, self.column
+                    )
+                }
+            }
+        }
+
+        impl std::error::Error for JsonError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.source)
+            }
+        }
+
+        impl<Item, SinkItem> Deserializer<Item> for Json<Item, SinkItem>
+        where
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                if self.concatenated {
+                    return self.deserialize_with_remainder(src).map(|(item, _)| item);
+                }
+                from_slice(src, self.human_readable).map_err(into_error)
+            }
+
+            fn deserialize_in_place(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+                place: &mut Item,
+            ) -> Result<(), Self::Error> {
+                if self.concatenated {
+                    *place = self.deserialize(src)?;
+                    return Ok(());
+                }
+                let mut de = serde_json::Deserializer::from_slice(strip_bom(src));
+                human_readable::deserialize_in_place(&mut de, self.human_readable, place)
+                    .and_then(|()| de.end())
+                    .map_err(into_error)
+            }
+        }
+
+        impl<Item, SinkItem, S> Deserializer<Item> for SeedCodec<Json<Item, SinkItem>, S>
+        where
+            S: Clone + for<'de> DeserializeSeed<'de, Value = Item>,
+        {
+            type Error = Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let seed = WithSeed::new(self.seed.clone(), self.codec.human_readable);
+                let mut de = serde_json::Deserializer::from_slice(strip_bom(src));
+                seed.deserialize(&mut de)
+                    .and_then(|item| de.end().map(|()| item))
+                    .map_err(into_error)
+            }
+        }
+
+        impl<Item, SinkItem> Deserializer<Item> for OpenEnum<Json<Item, SinkItem>>
+        where
+            for<'a> Item: Deserialize<'a> + UnknownVariant,
+        {
+            type Error = Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let human_readable = self.inner.human_readable;
+                decode_open(src, |seed| {
+                    let mut de = serde_json::Deserializer::from_slice(strip_bom(src));
+                    WithSeed::new(seed, human_readable)
+                        .deserialize(&mut de)
+                        .and_then(|item| de.end().map(|()| item))
+                })
+                .map_err(into_error)
+            }
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for Json<Item, SinkItem>
+        where
+            SinkItem: Serialize,
+        {
+            type Error = Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let mut frame = SmallFrame::new();
+                serde_json::to_writer(&mut frame, &Value::new(item, self.human_readable))
+                    .map_err(into_error)?;
+                Ok(freeze(frame))
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let mut frame = with_headroom(headroom, 0).writer();
+                serde_json::to_writer(&mut frame, &Value::new(item, self.human_readable))
+                    .map_err(into_error)?;
+                Ok(frame.into_inner())
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                serde_json::to_writer(buf.writer(), &Value::new(item, self.human_readable))
+                    .map_err(into_error)
+            }
+        }
+
+        /// JSON codec sending [JSON Merge Patch] (RFC 7386) diffs instead of
+        /// full documents.
+        ///
+        /// The codec keeps the last value it sent and the last value it
+        /// received. Each serialized frame is the merge patch turning the
+        /// previously sent value into the new one, and each received patch is
+        /// applied to the previously received value to rebuild the full
+        /// document. Both states start out as `null`, so the first frame
+        /// carries the whole value. This saves bandwidth when syncing large
+        /// documents that change a little at a time.
+        ///
+        /// Peers must process every frame, in order, with a single codec per
+        /// direction. Merge patches can't express `null` members, which are
+        /// removed instead, so values should not rely on them.
+        ///
+        /// [JSON Merge Patch]: https://www.rfc-editor.org/rfc/rfc7386
+        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+        #[pin_project]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct JsonMergePatch<Item, SinkItem> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<(Item, SinkItem)>,
+            #[educe(Debug(ignore))]
+            sent: serde_json::Value,
+            #[educe(Debug(ignore))]
+            received: serde_json::Value,
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+        pub type SymmetricalJsonMergePatch<T> = JsonMergePatch<T, T>;
+
+        /// Returns the merge patch turning `old` into `new`.
+        fn merge_diff(old: &serde_json::Value, new: &serde_json::Value) -> serde_json::Value {
+            use serde_json::{Map, Value};
+
+            match (old, new) {
+                (Value::Object(old), Value::Object(new)) => {
+                    let mut patch = Map::new();
+                    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                        patch.insert(key.clone(), Value::Null);
+                    }
+                    for (key, value) in new {
+                        match old.get(key) {
+                            Some(prev) if prev == value => {}
+                            Some(prev) => {
+                                patch.insert(key.clone(), merge_diff(prev, value));
+                            }
+                            None => {
+                                patch.insert(key.clone(), merge_diff(&Value::Null, value));
+                            }
+                        }
+                    }
+                    Value::Object(patch)
+                }
+                (_, Value::Object(new)) => {
+                    let mut patch = new.clone();
+                    patch.retain(|_, value| !value.is_null());
+                    Value::Object(patch)
+                }
+                (_, new) => new.clone(),
+            }
+        }
+
+        /// Applies the merge patch `patch` to `target`, as specified by
+        /// RFC 7386.
+        fn merge_apply(target: &mut serde_json::Value, patch: serde_json::Value) {
+            use serde_json::{Map, Value};
+
+            let patch = match patch {
+                Value::Object(patch) => patch,
+                patch => {
+                    *target = patch;
+                    return;
+                }
+            };
+
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(target) = target {
+                for (key, value) in patch {
+                    if value.is_null() {
+                        target.remove(&key);
+                    } else {
+                        merge_apply(target.entry(key).or_insert(Value::Null), value);
+                    }
+                }
+            }
+        }
+
+        impl<Item, SinkItem> Deserializer<Item> for JsonMergePatch<Item, SinkItem>
+        where
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let patch = serde_json::from_slice(strip_bom(src)).map_err(into_error)?;
+                let received = self.project().received;
+                merge_apply(received, patch);
+
+                serde_json::from_value(received.clone()).map_err(into_error)
+            }
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for JsonMergePatch<Item, SinkItem>
+        where
+            SinkItem: Serialize,
+        {
+            type Error = Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let value = serde_json::to_value(item).map_err(into_error)?;
+                let sent = self.project().sent;
+                let patch = merge_diff(sent, &value);
+                let frame = serde_json::to_vec(&patch).map_err(into_error)?;
+                *sent = value;
+
+                Ok(frame.into())
+            }
+        }
+    }
+
+    #[cfg(feature = "simd-json")]
+    mod simd_json {
+        use super::*;
+        use pin_project::pin_project;
+        use serde::{Deserialize, Serialize};
+        use std::io;
+
+        /// JSON codec using the SIMD accelerated
+        /// [simd-json](https://docs.rs/simd-json) crate.
+        ///
+        /// The wire format is the same as [`Json`](super::Json)'s, so the two
+        /// codecs interoperate.
+        ///
+        /// simd-json parses its input in place, so every frame is first copied
+        /// into a scratch buffer owned by the codec. The buffer is reused
+        /// between frames, but the copy still makes this codec a better fit
+        /// for large frames, where the faster parsing outweighs the copy.
+        #[cfg_attr(docsrs, doc(cfg(feature = "simd-json")))]
+        #[pin_project]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct SimdJson<Item, SinkItem> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<(Item, SinkItem)>,
+            #[educe(Debug(ignore))]
+            scratch: Vec<u8>,
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "simd-json")))]
+        pub type SymmetricalSimdJson<T> = SimdJson<T, T>;
+
+        impl<Item, SinkItem> Deserializer<Item> for SimdJson<Item, SinkItem>
+        where
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let scratch = self.project().scratch;
+                scratch.clear();
+                scratch.extend_from_slice(strip_bom(src));
+
+                simd_json_crate::from_slice(scratch)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for SimdJson<Item, SinkItem>
+        where
+            SinkItem: Serialize,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                Ok(simd_json_crate::to_vec(item)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .into())
+            }
+        }
+    }
+
+    #[cfg(feature = "messagepack")]
+    mod messagepack {
+        use super::human_readable::{Seed, Value};
+        use super::small_frame::{freeze, SmallFrame};
+        use super::*;
+        use crate::with_headroom;
+        use bytes::Buf;
+        use bytes::BufMut;
+        use serde::{de::DeserializeSeed, Deserialize, Serialize};
+        use std::io;
+
+        /// MessagePack codec using [rmp-serde](https://docs.rs/rmp-serde) crate.
+        ///
+        /// See [binary data](super#binary-data) for writing `Vec<u8>` fields
+        /// as a native MessagePack `bin`.
+        #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct MessagePack<Item, SinkItem> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<(Item, SinkItem)>,
+            compatible: bool,
+            human_readable: Option<bool>,
+        }
+
+        impl<Item, SinkItem> MessagePack<Item, SinkItem> {
+            /// Creates a codec in compatible mode.
+            ///
+            /// The default mode writes structs as arrays, enum variants by
+            /// index and values in their compact binary form. That breaks types
+            /// using `#[serde(flatten)]`: serde buffers flattened fields and
+            /// decodes them as if the format were human-readable, so e.g. an
+            /// `IpAddr` written as bytes can't be read back.
+            ///
+            /// Compatible mode writes structs as maps keyed by field name,
+            /// enum variants by name and marks the format as human-readable so
+            /// both sides agree on the representation. The trade-off is larger
+            /// frames, since field and variant names are sent with every value.
+            /// Both peers must use the same mode.
+            pub fn compatible() -> Self {
+                Self {
+                    ghost: PhantomData,
+                    compatible: true,
+                    human_readable: None,
+                }
+            }
+
+            /// Sets what serde's `is_human_readable` reports to the types
+            /// being encoded and decoded.
+            ///
+            /// This overrides the choice made by the mode, which is `false` by
+            /// default and `true` in [compatible](Self::compatible) mode. Both
+            /// peers must agree.
+            pub fn with_human_readable(mut self, human_readable: bool) -> Self {
+                self.human_readable = Some(human_readable);
+                self
+            }
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
+        pub type SymmetricalMessagePack<T> = MessagePack<T, T>;
+
+        impl<Item, SinkItem> Deserializer<Item> for MessagePack<Item, SinkItem>
+        where
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let reader = std::io::Cursor::new(src).reader();
+                let seed = Seed::new(self.human_readable);
+                let res = if self.compatible {
+                    seed.deserialize(
+                        &mut rmp_serde::Deserializer::new(reader).with_human_readable(),
+                    )
+                } else {
+                    seed.deserialize(&mut rmp_serde::Deserializer::new(reader))
+                };
+
+                res.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for MessagePack<Item, SinkItem>
+        where
+            SinkItem: Serialize,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let mut frame = SmallFrame::new();
+                self.write(&mut frame, item)?;
+                Ok(freeze(frame))
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let mut frame = with_headroom(headroom, 0).writer();
+                self.write(&mut frame, item)?;
+                Ok(frame.into_inner())
+            }
+        }
+
+        impl<Item, SinkItem> MessagePack<Item, SinkItem> {
+            fn write<W, T>(&self, frame: &mut W, item: &T) -> io::Result<()>
+            where
+                W: io::Write,
+                T: Serialize,
+            {
+                let item = Value::new(item, self.human_readable);
+                let res = if self.compatible {
+                    item.serialize(
+                        &mut rmp_serde::Serializer::new(frame)
+                            .with_struct_map()
+                            .with_string_variants()
+                            .with_human_readable(),
+                    )
+                } else {
+                    rmp_serde::encode::write(frame, &item)
+                };
+
+                res.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+
+    #[cfg(feature = "cbor")]
+    mod cbor {
+        use super::human_readable::{Seed, Value, WithSeed};
+        use super::small_frame::{freeze, SmallFrame};
+        use super::*;
+        use crate::combinators::{decode_open, OpenEnum, UnknownVariant};
+        use crate::with_headroom;
+        use bytes::BufMut;
+        use serde::{de::DeserializeSeed, Deserialize, Serialize};
+        use std::{convert::TryFrom, io};
+
+        /// CBOR codec using [serde_cbor](https://docs.rs/serde_cbor) crate.
+        ///
+        /// See [binary data](super#binary-data) for writing `Vec<u8>` fields
+        /// as a native CBOR byte string.
+        #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct Cbor<Item, SinkItem> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            _mkr: PhantomData<(Item, SinkItem)>,
+            limits: Option<(usize, usize)>,
+            human_readable: Option<bool>,
+            canonical: bool,
+        }
+
+        impl<Item, SinkItem> Cbor<Item, SinkItem> {
+            /// Creates a codec writing the deterministic encoding of RFC 8949
+            /// section 4.2, e.g. for payloads that get signed.
+            ///
+            /// Map keys are sorted by the bytewise order of their encodings,
+            /// lengths are always definite, and integers and floats use their
+            /// shortest form. Equal values then always encode to the same
+            /// bytes, whatever order a map's entries were inserted in.
+            /// Values are converted to a [`serde_cbor::Value`] first, which
+            /// makes serializing slower. Decoding is unaffected.
+            pub fn canonical() -> Self {
+                Self {
+                    canonical: true,
+                    ..Self::default()
+                }
+            }
+
+            /// Limits how deeply values may nest and how many elements a
+            /// single array or map may hold.
+            ///
+            /// Every frame is scanned before it is decoded. Frames nesting
+            /// arrays, maps or tags more than `max_depth` levels deep, or
+            /// holding an array or map with more than `max_len` elements, are
+            /// rejected with [`io::ErrorKind::InvalidData`]. This guards
+            /// against inputs crafted to exhaust the stack or memory.
+            pub fn with_limits(mut self, max_depth: usize, max_len: usize) -> Self {
+                self.limits = Some((max_depth, max_len));
+                self
+            }
+
+            /// Sets what serde's `is_human_readable` reports to the types
+            /// being encoded and decoded.
+            ///
+            /// CBOR is a binary format, so types such as `IpAddr` pick their
+            /// compact form by default. Forcing `true` makes them use the same
+            /// representation as in JSON. Both peers must agree.
+            pub fn with_human_readable(mut self, human_readable: bool) -> Self {
+                self.human_readable = Some(human_readable);
+                self
+            }
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+        pub type SymmetricalCbor<T> = Cbor<T, T>;
+
+        /// CBOR codec for dynamic [`serde_json::Value`]s.
+        ///
+        /// Passes schemaless JSON data through a pipeline in CBOR's binary
+        /// encoding, which is smaller than JSON text: numbers and lengths are
+        /// written in binary instead of digits, and strings need no escaping.
+        /// Objects, arrays, strings, booleans, numbers and nulls all map onto
+        /// CBOR natively, so values decode exactly as they were sent,
+        /// including integers beyond the range of `f64`. Frames from other
+        /// producers decode as long as they only use these types and text map
+        /// keys.
+        ///
+        /// The options of [`Cbor`], e.g. [`Cbor::canonical`], apply.
+        #[cfg(feature = "json")]
+        #[cfg_attr(docsrs, doc(cfg(all(feature = "cbor", feature = "json"))))]
+        pub type ValueCbor = Cbor<serde_json::Value, serde_json::Value>;
+
+        impl<Item, SinkItem> Deserializer<Item> for Cbor<Item, SinkItem>
+        where
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                if let Some((max_depth, max_len)) = self.limits {
+                    check_limits(src, max_depth, max_len)?;
+                }
+
+                let mut de = serde_cbor::Deserializer::from_slice(src);
+                let item = Seed::new(self.human_readable)
+                    .deserialize(&mut de)
+                    .map_err(into_io_error)?;
+                de.end().map_err(into_io_error)?;
+                Ok(item)
+            }
+        }
+
+        impl<Item, SinkItem> Deserializer<Item> for OpenEnum<Cbor<Item, SinkItem>>
+        where
+            for<'a> Item: Deserialize<'a> + UnknownVariant,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let codec = &self.inner;
+                if let Some((max_depth, max_len)) = codec.limits {
+                    check_limits(src, max_depth, max_len)?;
+                }
+
+                decode_open(src, |seed| {
+                    let mut de = serde_cbor::Deserializer::from_slice(src);
+                    WithSeed::new(seed, codec.human_readable)
+                        .deserialize(&mut de)
+                        .and_then(|item| de.end().map(|()| item))
+                })
+                .map_err(into_io_error)
+            }
+        }
+
+        fn limit_error(msg: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+
+        /// An array, map, tag or indefinite-length string being scanned.
+        struct Open {
+            /// Items left to read, or `None` until a break for indefinite
+            /// lengths.
+            remaining: Option<u64>,
+            /// Items read so far.
+            read: u64,
+            /// Most items the container may hold.
+            max: u64,
+        }
+
+        /// Walks the CBOR item at the start of `src` without decoding it,
+        /// checking nesting depth and collection lengths.
+        ///
+        /// Malformed input is left for serde_cbor to report.
+        fn check_limits(src: &[u8], max_depth: usize, max_len: usize) -> io::Result<()> {
+            let max_len = max_len as u64;
+            let mut stack = vec![Open {
+                remaining: Some(1),
+                read: 0,
+                max: 1,
+            }];
+            let mut pos = 0;
+
+            while let Some(open) = stack.last_mut() {
+                match open.remaining {
+                    Some(0) => {
+                        stack.pop();
+                        continue;
+                    }
+                    Some(ref mut n) => *n -= 1,
+                    None if src.get(pos) == Some(&0xff) => {
+                        pos += 1;
+                        stack.pop();
+                        continue;
+                    }
+                    None => {}
+                }
+
+                open.read += 1;
+                if open.read > open.max {
+                    return Err(limit_error("CBOR collection exceeds length limit"));
+                }
+
+                let byte = match src.get(pos) {
+                    Some(&byte) => byte,
+                    None => return Ok(()),
+                };
+                pos += 1;
+
+                let arg_len = match byte & 0x1f {
+                    0..=23 | 31 => 0,
+                    24 => 1,
+                    25 => 2,
+                    26 => 4,
+                    27 => 8,
+                    _ => return Ok(()),
+                };
+                let arg = match src.get(pos..pos + arg_len) {
+                    Some(bytes) if arg_len > 0 => {
+                        bytes.iter().fold(0u64, |acc, &b| acc << 8 | u64::from(b))
+                    }
+                    Some(_) => u64::from(byte & 0x1f),
+                    None => return Ok(()),
+                };
+                pos += arg_len;
+                let indefinite = byte & 0x1f == 31;
+
+                let (remaining, max) = match byte >> 5 {
+                    // Byte and text strings: skip definite ones, scan the
+                    // chunks of indefinite ones.
+                    2 | 3 if !indefinite => {
+                        pos = match usize::try_from(arg).ok().and_then(|n| pos.checked_add(n)) {
+                            Some(end) if end <= src.len() => end,
+                            _ => return Ok(()),
+                        };
+                        continue;
+                    }
+                    2 | 3 => (None, u64::MAX),
+                    4 if indefinite => (None, max_len),
+                    4 => (Some(arg), max_len),
+                    5 if indefinite => (None, max_len.saturating_mul(2)),
+                    5 => (Some(arg.saturating_mul(2)), max_len.saturating_mul(2)),
+                    6 => (Some(1), 1),
+                    _ => continue,
+                };
+
+                if remaining.is_some_and(|n| n > max) {
+                    return Err(limit_error("CBOR collection exceeds length limit"));
+                }
+
+                // The root sentinel doesn't count towards the depth.
+                if stack.len() > max_depth {
+                    return Err(limit_error("CBOR value exceeds nesting limit"));
+                }
+
+                stack.push(Open {
+                    remaining,
+                    read: 0,
+                    max,
+                });
+            }
+
+            Ok(())
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for Cbor<Item, SinkItem>
+        where
+            SinkItem: Serialize,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let mut frame = SmallFrame::new();
+                self.write(&mut frame, item)?;
+                Ok(freeze(frame))
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let mut frame = with_headroom(headroom, 0).writer();
+                self.write(&mut frame, item)?;
+                Ok(frame.into_inner())
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                self.write(&mut buf.writer(), item)
+            }
+        }
+
+        impl<Item, SinkItem> Cbor<Item, SinkItem> {
+            fn write<W, T>(&self, out: &mut W, item: &T) -> io::Result<()>
+            where
+                W: io::Write,
+                T: Serialize,
+            {
+                let item = Value::new(item, self.human_readable);
+                if self.canonical {
+                    let value = serde_cbor::value::to_value(&item).map_err(into_io_error)?;
+                    write_canonical(out, &value)
+                } else {
+                    serde_cbor::to_writer(out, &item).map_err(into_io_error)
+                }
+            }
+        }
+
+        /// Writes the head of a data item with the shortest encoding of `arg`.
+        fn write_head<W: io::Write>(out: &mut W, major: u8, arg: u64) -> io::Result<()> {
+            let major = major << 5;
+            if arg < 24 {
+                out.write_all(&[major | arg as u8])
+            } else if let Ok(arg) = u8::try_from(arg) {
+                out.write_all(&[major | 24, arg])
+            } else if let Ok(arg) = u16::try_from(arg) {
+                out.write_all(&[major | 25])?;
+                out.write_all(&arg.to_be_bytes())
+            } else if let Ok(arg) = u32::try_from(arg) {
+                out.write_all(&[major | 26])?;
+                out.write_all(&arg.to_be_bytes())
+            } else {
+                out.write_all(&[major | 27])?;
+                out.write_all(&arg.to_be_bytes())
+            }
+        }
+
+        /// Writes `value` with sorted map keys and definite lengths.
+        ///
+        /// serde_cbor already writes scalars in their shortest form, but
+        /// orders map keys length-first, so maps are sorted here by their
+        /// encoded keys.
+        fn write_canonical<W: io::Write>(out: &mut W, value: &serde_cbor::Value) -> io::Result<()> {
+            use serde_cbor::Value as CborValue;
+
+            match value {
+                CborValue::Array(items) => {
+                    write_head(out, 4, items.len() as u64)?;
+                    items.iter().try_for_each(|item| write_canonical(out, item))
+                }
+                CborValue::Map(map) => {
+                    let mut entries = map
+                        .iter()
+                        .map(|(key, value)| {
+                            let mut key_buf = Vec::new();
+                            write_canonical(&mut key_buf, key)?;
+                            Ok((key_buf, value))
+                        })
+                        .collect::<io::Result<Vec<_>>>()?;
+                    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                    write_head(out, 5, entries.len() as u64)?;
+                    for (key, value) in entries {
+                        out.write_all(&key)?;
+                        write_canonical(out, value)?;
+                    }
+                    Ok(())
+                }
+                CborValue::Tag(tag, inner) => {
+                    write_head(out, 6, *tag)?;
+                    write_canonical(out, inner)
+                }
+                _ => serde_cbor::to_writer(out, value).map_err(into_io_error),
+            }
+        }
+
+        fn into_io_error(cbor_err: serde_cbor::Error) -> io::Error {
+            use io::ErrorKind;
+            use serde_cbor::error::Category;
+            use std::error::Error;
+
+            match cbor_err.classify() {
+                Category::Eof => io::Error::new(ErrorKind::UnexpectedEof, cbor_err),
+                Category::Syntax => io::Error::new(ErrorKind::InvalidInput, cbor_err),
+                Category::Data => io::Error::new(ErrorKind::InvalidData, cbor_err),
+                Category::Io => {
+                    // Extract the underlying io error's type
+                    let kind = cbor_err
+                        .source()
+                        .and_then(|err| err.downcast_ref::<io::Error>())
+                        .map(|io_err| io_err.kind())
+                        .unwrap_or(ErrorKind::Other);
+                    io::Error::new(kind, cbor_err)
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "bytemuck")]
+    mod plain {
+        use super::*;
+        use bytemuck_crate::Pod;
+        use std::io;
+
+        /// Codec sending plain-old-data values as their raw bytes, using the
+        /// [bytemuck](https://docs.rs/bytemuck) crate.
+        ///
+        /// Serializing copies the bytes of the value into the frame, and
+        /// deserializing copies them back out, with no encoding step at all.
+        /// Frames whose length isn't exactly `size_of::<T>()` are rejected
+        /// with [`io::ErrorKind::InvalidData`]. Frames don't need to be
+        /// aligned for `T`.
+        ///
+        /// The wire format is the in-memory layout of `T` on the sending
+        /// machine: its byte order, field layout and padding. Both peers must
+        /// agree on all of them, so use `#[repr(C)]` types with explicit
+        /// padding fields, and only talk to peers of the same endianness, or
+        /// store fields in a fixed byte order, e.g. with `to_le()`, before
+        /// sending.
+        #[cfg_attr(docsrs, doc(cfg(feature = "bytemuck")))]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct Plain<T> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<T>,
+        }
+
+        impl<T: Pod> Deserializer<T> for Plain<T> {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<T, Self::Error> {
+                bytemuck_crate::try_pod_read_unaligned(src).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}: frame of {} bytes for a value of {} bytes",
+                            e,
+                            src.len(),
+                            std::mem::size_of::<T>()
+                        ),
+                    )
+                })
+            }
+        }
+
+        impl<T: Pod> Serializer<T> for Plain<T> {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &T) -> Result<Bytes, Self::Error> {
+                Ok(Bytes::copy_from_slice(bytemuck_crate::bytes_of(item)))
+            }
+        }
+    }
+
+    #[cfg(feature = "protobuf")]
+    mod protobuf {
+        use super::*;
+        use protobuf_crate::Message;
+        use std::io;
+
+        /// Protocol Buffers codec using the
+        /// [protobuf](https://docs.rs/protobuf) crate.
+        ///
+        /// Items are the message types generated by `protobuf-codegen`, which
+        /// implement [`Message`] rather than serde's traits. Each frame holds
+        /// exactly one message, without the length prefix of protobuf's
+        /// delimited encoding, so the transport has to do the framing.
+        #[cfg_attr(docsrs, doc(cfg(feature = "protobuf")))]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct Protobuf<Item, SinkItem> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<(Item, SinkItem)>,
+        }
+
+        #[cfg_attr(docsrs, doc(cfg(feature = "protobuf")))]
+        pub type SymmetricalProtobuf<T> = Protobuf<T, T>;
+
+        impl<Item, SinkItem> Deserializer<Item> for Protobuf<Item, SinkItem>
+        where
+            Item: Message,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                Item::parse_from_bytes(src)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for Protobuf<Item, SinkItem>
+        where
+            SinkItem: Message,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                Ok(item
+                    .write_to_bytes()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .into())
+            }
+        }
+    }
+
+    #[cfg(any(feature = "json", feature = "bincode"))]
+    mod seed {
+        use super::*;
+        use bytes::BufMut;
+        use pin_project::pin_project;
+        use std::io;
+
+        /// Wraps a codec to decode items with a serde
+        /// [`DeserializeSeed`](serde::de::DeserializeSeed) instead of their
+        /// `Deserialize` implementation.
+        ///
+        /// The seed carries context that types can't find on their own, such
+        /// as an interner or an arena. It is cloned for every frame, so it is
+        /// typically a reference or an `Arc` to the shared state. Values are
+        /// serialized by the wrapped codec as usual.
+        ///
+        /// Supported for [`Json`] and [`Bincode`] codecs, whose options,
+        /// such as the human-readable override or the byte limit, still
+        /// apply.
+        #[cfg_attr(docsrs, doc(cfg(any(feature = "json", feature = "bincode"))))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct SeedCodec<Codec, S> {
+            #[pin]
+            pub(super) codec: Codec,
+            pub(super) seed: S,
+        }
+
+        impl<Codec, S> SeedCodec<Codec, S> {
+            /// Creates a new `SeedCodec` decoding with `seed`.
+            pub fn new(codec: Codec, seed: S) -> Self {
+                Self { codec, seed }
+            }
+
+            /// Returns a reference to the seed.
+            pub fn seed(&self) -> &S {
+                &self.seed
+            }
+
+            /// Returns a mutable reference to the seed, e.g. to swap in
+            /// fresh context between frames.
+            pub fn seed_mut(&mut self) -> &mut S {
+                &mut self.seed
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Codec {
+                &self.codec
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Codec {
+                &mut self.codec
+            }
+
+            /// Consumes the `SeedCodec`, returning the inner codec and the
+            /// seed.
+            pub fn into_inner(self) -> (Codec, S) {
+                (self.codec, self.seed)
+            }
+        }
+
+        impl<Codec, S, SinkItem> Serializer<SinkItem> for SeedCodec<Codec, S>
+        where
+            Codec: Serializer<SinkItem>,
+        {
+            type Error = Codec::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().codec.serialize(item)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project().codec.serialize_mut(item, headroom)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                self.project().codec.serialize_to_buf(item, buf)
+            }
+        }
+    }
+
+    #[cfg(any(
+        feature = "json",
+        feature = "bincode",
+        feature = "messagepack",
+        feature = "cbor"
+    ))]
+    mod any {
+        use super::*;
+        #[cfg(all(feature = "json", not(feature = "unified-error")))]
+        use crate::put_frame;
+        use bytes::BufMut;
+        use pin_project::pin_project;
+        use serde::{Deserialize, Serialize};
+        use std::{convert::TryFrom, io, str::FromStr};
+
+        /// One of the enabled format codecs, chosen at runtime.
+        ///
+        /// This allows picking the wire format from configuration without
+        /// making the rest of the program generic over the codec. Each variant
+        /// holds the codec's default configuration when created by name, and
+        /// every error is reported as an [`io::Error`].
+        ///
+        /// ```
+        /// # #[cfg(feature = "json")] {
+        /// use tokio_serde::formats::AnyCodec;
+        ///
+        /// let codec: AnyCodec<String, String> = "json".parse().unwrap();
+        /// assert_eq!(codec.name(), "json");
+        /// # }
+        /// ```
+        #[cfg_attr(
+            docsrs,
+            doc(cfg(any(
+                feature = "json",
+                feature = "bincode",
+                feature = "messagepack",
+                feature = "cbor"
+            )))
+        )]
+        #[pin_project(project = AnyCodecProj)]
+        #[derive(Debug)]
+        pub enum AnyCodec<Item, SinkItem> {
+            /// The [`Json`] codec, named `"json"`.
+            #[cfg(feature = "json")]
+            Json(#[pin] Json<Item, SinkItem>),
+            /// The [`Bincode`] codec, named `"bincode"`.
+            #[cfg(feature = "bincode")]
+            Bincode(#[pin] Bincode<Item, SinkItem>),
+            /// The [`MessagePack`] codec, named `"messagepack"`.
+            #[cfg(feature = "messagepack")]
+            MessagePack(#[pin] MessagePack<Item, SinkItem>),
+            /// The [`Cbor`] codec, named `"cbor"`.
+            #[cfg(feature = "cbor")]
+            Cbor(#[pin] Cbor<Item, SinkItem>),
+        }
+
+        impl<Item, SinkItem> AnyCodec<Item, SinkItem> {
+            /// Creates the codec named `name` with its default configuration,
+            /// or returns `None` if no enabled format has that name.
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    #[cfg(feature = "json")]
+                    "json" => Some(AnyCodec::Json(Json::default())),
+                    #[cfg(feature = "bincode")]
+                    "bincode" => Some(AnyCodec::Bincode(Bincode::default())),
+                    #[cfg(feature = "messagepack")]
+                    "messagepack" => Some(AnyCodec::MessagePack(MessagePack::default())),
+                    #[cfg(feature = "cbor")]
+                    "cbor" => Some(AnyCodec::Cbor(Cbor::default())),
+                    _ => None,
+                }
+            }
+
+            /// Returns the name of the active format, as accepted by
+            /// [`from_name`](Self::from_name).
+            pub fn name(&self) -> &'static str {
+                match self {
+                    #[cfg(feature = "json")]
+                    AnyCodec::Json(_) => "json",
+                    #[cfg(feature = "bincode")]
+                    AnyCodec::Bincode(_) => "bincode",
+                    #[cfg(feature = "messagepack")]
+                    AnyCodec::MessagePack(_) => "messagepack",
+                    #[cfg(feature = "cbor")]
+                    AnyCodec::Cbor(_) => "cbor",
+                }
+            }
+        }
+
+        impl<Item, SinkItem> FromStr for AnyCodec<Item, SinkItem> {
+            type Err = io::Error;
+
+            /// Parses a format name, failing with [`io::ErrorKind::InvalidInput`]
+            /// for names of unknown or disabled formats.
+            fn from_str(name: &str) -> Result<Self, Self::Err> {
+                Self::from_name(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("unknown codec `{}`", name),
+                    )
+                })
+            }
+        }
+
+        /// Unifies [`Json`]'s error, which is only an [`io::Error`] with the
+        /// `unified-error` feature.
+        #[cfg(feature = "json")]
+        fn json_error(err: impl Into<io::Error>) -> io::Error {
+            err.into()
+        }
+
+        macro_rules! any_codec_conversions {
+            ($($feature:literal => $variant:ident($codec:ident),)*) => {$(
+                #[cfg(feature = $feature)]
+                impl<Item, SinkItem> From<$codec<Item, SinkItem>> for AnyCodec<Item, SinkItem> {
+                    fn from(codec: $codec<Item, SinkItem>) -> Self {
+                        AnyCodec::$variant(codec)
+                    }
+                }
+
+                /// Extracts the codec, or returns the `AnyCodec` unchanged
+                /// if another format is active.
+                #[cfg(feature = $feature)]
+                impl<Item, SinkItem> TryFrom<AnyCodec<Item, SinkItem>> for $codec<Item, SinkItem> {
+                    type Error = AnyCodec<Item, SinkItem>;
+
+                    fn try_from(codec: AnyCodec<Item, SinkItem>) -> Result<Self, Self::Error> {
+                        match codec {
+                            AnyCodec::$variant(codec) => Ok(codec),
+                            #[allow(unreachable_patterns)]
+                            other => Err(other),
+                        }
+                    }
+                }
+            )*};
+        }
+
+        any_codec_conversions! {
+            "json" => Json(Json),
+            "bincode" => Bincode(Bincode),
+            "messagepack" => MessagePack(MessagePack),
+            "cbor" => Cbor(Cbor),
+        }
+
+        impl<Item, SinkItem> Deserializer<Item> for AnyCodec<Item, SinkItem>
+        where
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                match self.project() {
+                    #[cfg(feature = "json")]
+                    AnyCodecProj::Json(codec) => codec.deserialize(src).map_err(json_error),
+                    #[cfg(feature = "bincode")]
+                    AnyCodecProj::Bincode(codec) => codec.deserialize(src),
+                    #[cfg(feature = "messagepack")]
+                    AnyCodecProj::MessagePack(codec) => codec.deserialize(src),
+                    #[cfg(feature = "cbor")]
+                    AnyCodecProj::Cbor(codec) => codec.deserialize(src),
+                }
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                match self.project() {
+                    #[cfg(feature = "json")]
+                    AnyCodecProj::Json(codec) => codec.deserialize_owned(src).map_err(json_error),
+                    #[cfg(feature = "bincode")]
+                    AnyCodecProj::Bincode(codec) => codec.deserialize_owned(src),
+                    #[cfg(feature = "messagepack")]
+                    AnyCodecProj::MessagePack(codec) => codec.deserialize_owned(src),
+                    #[cfg(feature = "cbor")]
+                    AnyCodecProj::Cbor(codec) => codec.deserialize_owned(src),
+                }
+            }
+        }
+
+        impl<Item, SinkItem> Serializer<SinkItem> for AnyCodec<Item, SinkItem>
+        where
+            SinkItem: Serialize,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                match self.project() {
+                    #[cfg(feature = "json")]
+                    AnyCodecProj::Json(codec) => codec.serialize(item).map_err(json_error),
+                    #[cfg(feature = "bincode")]
+                    AnyCodecProj::Bincode(codec) => codec.serialize(item),
+                    #[cfg(feature = "messagepack")]
+                    AnyCodecProj::MessagePack(codec) => codec.serialize(item),
+                    #[cfg(feature = "cbor")]
+                    AnyCodecProj::Cbor(codec) => codec.serialize(item),
+                }
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                match self.project() {
+                    #[cfg(feature = "json")]
+                    AnyCodecProj::Json(codec) => {
+                        codec.serialize_mut(item, headroom).map_err(json_error)
+                    }
+                    #[cfg(feature = "bincode")]
+                    AnyCodecProj::Bincode(codec) => codec.serialize_mut(item, headroom),
+                    #[cfg(feature = "messagepack")]
+                    AnyCodecProj::MessagePack(codec) => codec.serialize_mut(item, headroom),
+                    #[cfg(feature = "cbor")]
+                    AnyCodecProj::Cbor(codec) => codec.serialize_mut(item, headroom),
+                }
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                match self.project() {
+                    #[cfg(all(feature = "json", feature = "unified-error"))]
+                    AnyCodecProj::Json(codec) => codec.serialize_to_buf(item, buf),
+                    // `serde_json::Error` can't report a full `buf`.
+                    #[cfg(all(feature = "json", not(feature = "unified-error")))]
+                    AnyCodecProj::Json(codec) => {
+                        let frame = codec.serialize_mut(item, 0)?;
+                        put_frame(buf, &frame)
+                    }
+                    #[cfg(feature = "bincode")]
+                    AnyCodecProj::Bincode(codec) => codec.serialize_to_buf(item, buf),
+                    #[cfg(feature = "messagepack")]
+                    AnyCodecProj::MessagePack(codec) => codec.serialize_to_buf(item, buf),
+                    #[cfg(feature = "cbor")]
+                    AnyCodecProj::Cbor(codec) => codec.serialize_to_buf(item, buf),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "transcode")]
+    mod transcode {
+        use super::*;
+        use std::io;
+
+        #[cfg(any(feature = "json", feature = "cbor", feature = "messagepack"))]
+        fn invalid_data<E>(err: E) -> io::Error
+        where
+            E: Into<Box<dyn std::error::Error + Send + Sync>>,
+        {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        }
+
+        /// A serialization format [`Transcode`] can convert from and to.
+        ///
+        /// Implemented by [`JsonFormat`], [`CborFormat`] and
+        /// [`MessagePackFormat`], each behind the feature of its format.
+        #[cfg_attr(docsrs, doc(cfg(feature = "transcode")))]
+        pub trait TranscodeFormat {
+            /// Reads the value encoded in `src` and encodes it in format `To`.
+            fn transcode<To: TranscodeFormat>(src: &[u8]) -> io::Result<Vec<u8>>;
+
+            /// Encodes the value produced by `deserializer` in this format.
+            fn write<'de, D: serde::Deserializer<'de>>(deserializer: D) -> io::Result<Vec<u8>>;
+        }
+
+        /// The JSON format, as written by [`Json`](super::Json).
+        #[cfg(feature = "json")]
+        #[cfg_attr(docsrs, doc(cfg(all(feature = "transcode", feature = "json"))))]
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct JsonFormat;
+
+        #[cfg(feature = "json")]
+        impl TranscodeFormat for JsonFormat {
+            fn transcode<To: TranscodeFormat>(src: &[u8]) -> io::Result<Vec<u8>> {
+                let mut de = serde_json::Deserializer::from_slice(strip_bom(src));
+                let out = To::write(&mut de)?;
+                de.end().map_err(invalid_data)?;
+                Ok(out)
+            }
+
+            fn write<'de, D: serde::Deserializer<'de>>(deserializer: D) -> io::Result<Vec<u8>> {
+                let mut out = Vec::new();
+                serde_transcode::transcode(
+                    deserializer,
+                    &mut serde_json::Serializer::new(&mut out),
+                )
+                .map_err(invalid_data)?;
+                Ok(out)
+            }
+        }
+
+        /// The CBOR format, as written by [`Cbor`](super::Cbor).
+        #[cfg(feature = "cbor")]
+        #[cfg_attr(docsrs, doc(cfg(all(feature = "transcode", feature = "cbor"))))]
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct CborFormat;
+
+        #[cfg(feature = "cbor")]
+        impl TranscodeFormat for CborFormat {
+            fn transcode<To: TranscodeFormat>(src: &[u8]) -> io::Result<Vec<u8>> {
+                let mut de = serde_cbor::Deserializer::from_slice(src);
+                let out = To::write(&mut de)?;
+                de.end().map_err(invalid_data)?;
+                Ok(out)
+            }
+
+            fn write<'de, D: serde::Deserializer<'de>>(deserializer: D) -> io::Result<Vec<u8>> {
+                let mut out = Vec::new();
+                let mut serializer = serde_cbor::Serializer::new(&mut out);
+                serde_transcode::transcode(deserializer, &mut serializer).map_err(invalid_data)?;
+                Ok(out)
+            }
+        }
+
+        /// The MessagePack format, as written by
+        /// [`MessagePack`](super::MessagePack).
+        #[cfg(feature = "messagepack")]
+        #[cfg_attr(docsrs, doc(cfg(all(feature = "transcode", feature = "messagepack"))))]
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct MessagePackFormat;
+
+        #[cfg(feature = "messagepack")]
+        impl TranscodeFormat for MessagePackFormat {
+            fn transcode<To: TranscodeFormat>(mut src: &[u8]) -> io::Result<Vec<u8>> {
+                let out = To::write(&mut rmp_serde::Deserializer::new(&mut src))?;
+                if !src.is_empty() {
+                    return Err(invalid_data("trailing bytes after MessagePack value"));
+                }
+                Ok(out)
+            }
+
+            fn write<'de, D: serde::Deserializer<'de>>(deserializer: D) -> io::Result<Vec<u8>> {
+                let mut out = Vec::new();
+                serde_transcode::transcode(deserializer, &mut rmp_serde::Serializer::new(&mut out))
+                    .map_err(invalid_data)?;
+                Ok(out)
+            }
+        }
+
+        /// Converts frames between two formats without decoding them into a
+        /// concrete type, using [serde-transcode](https://docs.rs/serde-transcode).
+        ///
+        /// Decoding takes a frame in format `In` and yields the same value
+        /// encoded in format `Out`, as `Bytes`. Encoding goes the other way,
+        /// from `Out` back to `In`. A proxy between peers speaking different
+        /// formats can then forward values it knows nothing about. Frames
+        /// that aren't valid `In`, or values `Out` can't represent, are
+        /// rejected with [`io::ErrorKind::InvalidData`].
+        ///
+        /// Conversions are only as faithful as the formats allow, e.g. CBOR
+        /// byte strings become arrays of numbers in JSON.
+        #[cfg_attr(docsrs, doc(cfg(feature = "transcode")))]
+        #[derive(Educe)]
+        #[educe(Debug, Default)]
+        pub struct Transcode<In, Out> {
+            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
+            ghost: PhantomData<(In, Out)>,
+        }
+
+        impl<In, Out> Deserializer<Bytes> for Transcode<In, Out>
+        where
+            In: TranscodeFormat,
+            Out: TranscodeFormat,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Bytes, Self::Error> {
+                In::transcode::<Out>(src).map(Bytes::from)
+            }
+        }
+
+        impl<In, Out> Serializer<Bytes> for Transcode<In, Out>
+        where
+            In: TranscodeFormat,
+            Out: TranscodeFormat,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &Bytes) -> Result<Bytes, Self::Error> {
+                Out::transcode::<In>(item).map(Bytes::from)
+            }
+        }
+    }
+
+    #[cfg(any(feature = "json", feature = "messagepack", feature = "cbor"))]
+    mod small_frame {
+        use bytes::Bytes;
+        use smallvec::SmallVec;
+
+        /// A serialization buffer keeping frames of up to 64 bytes inline.
+        ///
+        /// Serializing into a `Vec` and converting it to `Bytes` costs two
+        /// allocations whenever the `Vec` has spare capacity, which is almost
+        /// always the case. Writing small frames to the stack first and
+        /// copying them out leaves a single, exactly sized allocation.
+        pub(crate) type SmallFrame = SmallVec<[u8; 64]>;
+
+        pub(crate) fn freeze(frame: SmallFrame) -> Bytes {
+            if frame.spilled() {
+                frame.into_vec().into()
+            } else {
+                Bytes::copy_from_slice(&frame)
+            }
+        }
+    }
+
+    /// Serde adapters forcing the value of `is_human_readable`.
+    ///
+    /// [`Wrap`] forwards every call to the wrapped serializer, deserializer or
+    /// visitor and wraps whatever it hands out in turn, so nested values see
+    /// the forced flag too.
+    #[cfg(any(
+        feature = "json",
+        feature = "bincode",
+        feature = "messagepack",
+        feature = "cbor"
+    ))]
+    mod human_readable {
+        use serde::de::{self, Deserialize, DeserializeSeed, Visitor};
+        use serde::ser::{self, Serialize};
+        use std::{fmt, marker::PhantomData};
+
+        /// Serializes a value, overriding `is_human_readable` if
+        /// `human_readable` is set.
+        pub(crate) struct Value<'a, T: ?Sized> {
+            value: &'a T,
+            human_readable: Option<bool>,
+        }
+
+        impl<'a, T: ?Sized> Value<'a, T> {
+            pub(crate) fn new(value: &'a T, human_readable: Option<bool>) -> Self {
+                Self {
+                    value,
+                    human_readable,
+                }
+            }
+        }
+
+        impl<T: Serialize + ?Sized> Serialize for Value<'_, T> {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self.human_readable {
+                    Some(hr) => self.value.serialize(Wrap::new(serializer, hr)),
+                    None => self.value.serialize(serializer),
+                }
+            }
+        }
+
+        /// Deserializes into `place`, overriding `is_human_readable` if
+        /// `human_readable` is set.
+        #[cfg(feature = "json")]
+        pub(crate) fn deserialize_in_place<'de, D, T>(
+            deserializer: D,
+            human_readable: Option<bool>,
+            place: &mut T,
+        ) -> Result<(), D::Error>
+        where
+            D: de::Deserializer<'de>,
+            T: Deserialize<'de>,
+        {
+            match human_readable {
+                Some(hr) => T::deserialize_in_place(Wrap::new(deserializer, hr), place),
+                None => T::deserialize_in_place(deserializer, place),
+            }
+        }
+
+        /// Deserializes a `T`, overriding `is_human_readable` if
+        /// `human_readable` is set.
+        pub(crate) struct Seed<T> {
+            human_readable: Option<bool>,
+            ghost: PhantomData<T>,
+        }
+
+        impl<T> Seed<T> {
+            pub(crate) fn new(human_readable: Option<bool>) -> Self {
+                Self {
+                    human_readable,
+                    ghost: PhantomData,
+                }
+            }
+        }
+
+        impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for Seed<T> {
+            type Value = T;
+
+            #[cfg(not(feature = "path-error"))]
+            fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+                self.deserialize_untracked(deserializer)
+            }
+
+            /// Prefixes errors inside nested values with the path to the
+            /// value, e.g. `servers[2].port`. The error is rebuilt with
+            /// `de::Error::custom`, so format-specific details such as
+            /// serde_json's error category are lost in that case.
+            #[cfg(feature = "path-error")]
+            fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+                let mut track = serde_path_to_error::Track::new();
+                let res = self.deserialize_untracked(serde_path_to_error::Deserializer::new(
+                    deserializer,
+                    &mut track,
+                ));
+
+                res.map_err(|err| {
+                    let path = track.path();
+                    if path.iter().next().is_none() {
+                        err
+                    } else {
+                        de::Error::custom(format_args!("{}: {}", path, err))
+                    }
+                })
+            }
+        }
+
+        /// Runs `seed`, overriding `is_human_readable` if `human_readable`
+        /// is set.
+        #[cfg(any(feature = "json", feature = "bincode", feature = "cbor"))]
+        pub(crate) struct WithSeed<S> {
+            seed: S,
+            human_readable: Option<bool>,
+        }
+
+        #[cfg(any(feature = "json", feature = "bincode", feature = "cbor"))]
+        impl<S> WithSeed<S> {
+            pub(crate) fn new(seed: S, human_readable: Option<bool>) -> Self {
+                Self {
+                    seed,
+                    human_readable,
+                }
+            }
+        }
+
+        #[cfg(any(feature = "json", feature = "bincode", feature = "cbor"))]
+        impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for WithSeed<S> {
+            type Value = S::Value;
+
+            fn deserialize<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<S::Value, D::Error> {
+                match self.human_readable {
+                    Some(hr) => self.seed.deserialize(Wrap::new(deserializer, hr)),
+                    None => self.seed.deserialize(deserializer),
+                }
+            }
+        }
+
+        impl<T> Seed<T> {
+            fn deserialize_untracked<'de, D>(self, deserializer: D) -> Result<T, D::Error>
+            where
+                T: Deserialize<'de>,
+                D: de::Deserializer<'de>,
+            {
+                match self.human_readable {
+                    Some(hr) => T::deserialize(Wrap::new(deserializer, hr)),
+                    None => T::deserialize(deserializer),
+                }
+            }
+        }
+
+        struct Wrap<T> {
+            inner: T,
+            hr: bool,
+        }
+
+        impl<T> Wrap<T> {
+            fn new(inner: T, hr: bool) -> Self {
+                Self { inner, hr }
+            }
+        }
+
+        impl<T: Serialize + ?Sized> Serialize for Wrap<&T> {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.inner.serialize(Wrap::new(serializer, self.hr))
+            }
+        }
+
+        macro_rules! forward_serialize {
+            ($($method:ident($ty:ty);)*) => {$(
+                fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+                    self.inner.$method(v)
+                }
+            )*};
+        }
+
+        impl<S: ser::Serializer> ser::Serializer for Wrap<S> {
+            type Ok = S::Ok;
+            type Error = S::Error;
+            type SerializeSeq = Wrap<S::SerializeSeq>;
+            type SerializeTuple = Wrap<S::SerializeTuple>;
+            type SerializeTupleStruct = Wrap<S::SerializeTupleStruct>;
+            type SerializeTupleVariant = Wrap<S::SerializeTupleVariant>;
+            type SerializeMap = Wrap<S::SerializeMap>;
+            type SerializeStruct = Wrap<S::SerializeStruct>;
+            type SerializeStructVariant = Wrap<S::SerializeStructVariant>;
+
+            forward_serialize! {
+                serialize_bool(bool);
+                serialize_i8(i8);
+                serialize_i16(i16);
+                serialize_i32(i32);
+                serialize_i64(i64);
+                serialize_i128(i128);
+                serialize_u8(u8);
+                serialize_u16(u16);
+                serialize_u32(u32);
+                serialize_u64(u64);
+                serialize_u128(u128);
+                serialize_f32(f32);
+                serialize_f64(f64);
+                serialize_char(char);
+                serialize_str(&str);
+                serialize_bytes(&[u8]);
+                serialize_unit_struct(&'static str);
+            }
+
+            fn serialize_none(self) -> Result<S::Ok, S::Error> {
+                self.inner.serialize_none()
+            }
+
+            fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+                self.inner.serialize_some(&Wrap::new(value, self.hr))
+            }
+
+            fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+                self.inner.serialize_unit()
+            }
+
+            fn serialize_unit_variant(
+                self,
+                name: &'static str,
+                index: u32,
+                variant: &'static str,
+            ) -> Result<S::Ok, S::Error> {
+                self.inner.serialize_unit_variant(name, index, variant)
+            }
+
+            fn serialize_newtype_struct<T: Serialize + ?Sized>(
+                self,
+                name: &'static str,
+                value: &T,
+            ) -> Result<S::Ok, S::Error> {
+                self.inner
+                    .serialize_newtype_struct(name, &Wrap::new(value, self.hr))
+            }
+
+            fn serialize_newtype_variant<T: Serialize + ?Sized>(
+                self,
+                name: &'static str,
+                index: u32,
+                variant: &'static str,
+                value: &T,
+            ) -> Result<S::Ok, S::Error> {
+                self.inner.serialize_newtype_variant(
+                    name,
+                    index,
+                    variant,
+                    &Wrap::new(value, self.hr),
+                )
+            }
+
+            fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+                Ok(Wrap::new(self.inner.serialize_seq(len)?, self.hr))
+            }
+
+            fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+                Ok(Wrap::new(self.inner.serialize_tuple(len)?, self.hr))
+            }
+
+            fn serialize_tuple_struct(
+                self,
+                name: &'static str,
+                len: usize,
+            ) -> Result<Self::SerializeTupleStruct, S::Error> {
+                Ok(Wrap::new(
+                    self.inner.serialize_tuple_struct(name, len)?,
+                    self.hr,
+                ))
+            }
+
+            fn serialize_tuple_variant(
+                self,
+                name: &'static str,
+                index: u32,
+                variant: &'static str,
+                len: usize,
+            ) -> Result<Self::SerializeTupleVariant, S::Error> {
+                Ok(Wrap::new(
+                    self.inner
+                        .serialize_tuple_variant(name, index, variant, len)?,
+                    self.hr,
+                ))
+            }
+
+            fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+                Ok(Wrap::new(self.inner.serialize_map(len)?, self.hr))
+            }
+
+            fn serialize_struct(
+                self,
+                name: &'static str,
+                len: usize,
+            ) -> Result<Self::SerializeStruct, S::Error> {
+                Ok(Wrap::new(self.inner.serialize_struct(name, len)?, self.hr))
+            }
+
+            fn serialize_struct_variant(
+                self,
+                name: &'static str,
+                index: u32,
+                variant: &'static str,
+                len: usize,
+            ) -> Result<Self::SerializeStructVariant, S::Error> {
+                Ok(Wrap::new(
+                    self.inner
+                        .serialize_struct_variant(name, index, variant, len)?,
+                    self.hr,
+                ))
+            }
+
+            fn collect_str<T: fmt::Display + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+                self.inner.collect_str(value)
+            }
+
+            fn is_human_readable(&self) -> bool {
+                self.hr
+            }
+        }
+
+        macro_rules! impl_serialize_compound {
+            ($($trait:ident::$method:ident($($key:ident: $key_ty:ty)?);)*) => {$(
+                impl<S: ser::$trait> ser::$trait for Wrap<S> {
+                    type Ok = S::Ok;
+                    type Error = S::Error;
+
+                    fn $method<T: Serialize + ?Sized>(
+                        &mut self,
+                        $($key: $key_ty,)?
+                        value: &T,
+                    ) -> Result<(), S::Error> {
+                        self.inner.$method($($key,)? &Wrap::new(value, self.hr))
+                    }
+
+                    fn end(self) -> Result<S::Ok, S::Error> {
+                        self.inner.end()
+                    }
+                }
+            )*};
+        }
+
+        impl_serialize_compound! {
+            SerializeSeq::serialize_element();
+            SerializeTuple::serialize_element();
+            SerializeTupleStruct::serialize_field();
+            SerializeTupleVariant::serialize_field();
+            SerializeStruct::serialize_field(key: &'static str);
+            SerializeStructVariant::serialize_field(key: &'static str);
+        }
+
+        impl<S: ser::SerializeMap> ser::SerializeMap for Wrap<S> {
+            type Ok = S::Ok;
+            type Error = S::Error;
+
+            fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+                self.inner.serialize_key(&Wrap::new(key, self.hr))
+            }
+
+            fn serialize_value<T: Serialize + ?Sized>(
+                &mut self,
+                value: &T,
+            ) -> Result<(), S::Error> {
+                self.inner.serialize_value(&Wrap::new(value, self.hr))
+            }
+
+            fn end(self) -> Result<S::Ok, S::Error> {
+                self.inner.end()
+            }
+        }
+
+        macro_rules! forward_deserialize {
+            ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+                fn $method<V: Visitor<'de>>(
+                    self,
+                    $($arg: $ty,)*
+                    visitor: V,
+                ) -> Result<V::Value, D::Error> {
+                    self.inner.$method($($arg,)* Wrap::new(visitor, self.hr))
+                }
+            )*};
+        }
+
+        impl<'de, D: de::Deserializer<'de>> de::Deserializer<'de> for Wrap<D> {
+            type Error = D::Error;
+
+            forward_deserialize! {
+                deserialize_any();
+                deserialize_bool();
+                deserialize_i8();
+                deserialize_i16();
+                deserialize_i32();
+                deserialize_i64();
+                deserialize_i128();
+                deserialize_u8();
+                deserialize_u16();
+                deserialize_u32();
+                deserialize_u64();
+                deserialize_u128();
+                deserialize_f32();
+                deserialize_f64();
+                deserialize_char();
+                deserialize_str();
+                deserialize_string();
+                deserialize_bytes();
+                deserialize_byte_buf();
+                deserialize_option();
+                deserialize_unit();
+                deserialize_unit_struct(name: &'static str);
+                deserialize_newtype_struct(name: &'static str);
+                deserialize_seq();
+                deserialize_tuple(len: usize);
+                deserialize_tuple_struct(name: &'static str, len: usize);
+                deserialize_map();
+                deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+                deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+                deserialize_identifier();
+                deserialize_ignored_any();
+            }
+
+            fn is_human_readable(&self) -> bool {
+                self.hr
+            }
+        }
+
+        macro_rules! forward_visit {
+            ($($method:ident($ty:ty);)*) => {$(
+                fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+                    self.inner.$method(v)
+                }
+            )*};
+        }
+
+        impl<'de, V: Visitor<'de>> Visitor<'de> for Wrap<V> {
+            type Value = V::Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.inner.expecting(f)
+            }
+
+            forward_visit! {
+                visit_bool(bool);
+                visit_i8(i8);
+                visit_i16(i16);
+                visit_i32(i32);
+                visit_i64(i64);
+                visit_i128(i128);
+                visit_u8(u8);
+                visit_u16(u16);
+                visit_u32(u32);
+                visit_u64(u64);
+                visit_u128(u128);
+                visit_f32(f32);
+                visit_f64(f64);
+                visit_char(char);
+                visit_str(&str);
+                visit_borrowed_str(&'de str);
+                visit_string(String);
+                visit_bytes(&[u8]);
+                visit_borrowed_bytes(&'de [u8]);
+                visit_byte_buf(Vec<u8>);
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+                self.inner.visit_none()
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+                self.inner.visit_unit()
+            }
+
+            fn visit_some<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<V::Value, D::Error> {
+                self.inner.visit_some(Wrap::new(deserializer, self.hr))
+            }
+
+            fn visit_newtype_struct<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<V::Value, D::Error> {
+                self.inner
+                    .visit_newtype_struct(Wrap::new(deserializer, self.hr))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+                self.inner.visit_seq(Wrap::new(seq, self.hr))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+                self.inner.visit_map(Wrap::new(map, self.hr))
+            }
+
+            fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+                self.inner.visit_enum(Wrap::new(data, self.hr))
+            }
+        }
+
+        impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Wrap<S> {
+            type Value = S::Value;
+
+            fn deserialize<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<S::Value, D::Error> {
+                self.inner.deserialize(Wrap::new(deserializer, self.hr))
+            }
+        }
+
+        impl<'de, A: de::SeqAccess<'de>> de::SeqAccess<'de> for Wrap<A> {
+            type Error = A::Error;
+
+            fn next_element_seed<T: DeserializeSeed<'de>>(
+                &mut self,
+                seed: T,
+            ) -> Result<Option<T::Value>, A::Error> {
+                self.inner.next_element_seed(Wrap::new(seed, self.hr))
+            }
+
+            fn size_hint(&self) -> Option<usize> {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<'de, A: de::MapAccess<'de>> de::MapAccess<'de> for Wrap<A> {
+            type Error = A::Error;
+
+            fn next_key_seed<K: DeserializeSeed<'de>>(
+                &mut self,
+                seed: K,
+            ) -> Result<Option<K::Value>, A::Error> {
+                self.inner.next_key_seed(Wrap::new(seed, self.hr))
+            }
+
+            fn next_value_seed<T: DeserializeSeed<'de>>(
+                &mut self,
+                seed: T,
+            ) -> Result<T::Value, A::Error> {
+                self.inner.next_value_seed(Wrap::new(seed, self.hr))
+            }
+
+            fn size_hint(&self) -> Option<usize> {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<'de, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Wrap<A> {
+            type Error = A::Error;
+            type Variant = Wrap<A::Variant>;
+
+            fn variant_seed<T: DeserializeSeed<'de>>(
+                self,
+                seed: T,
+            ) -> Result<(T::Value, Self::Variant), A::Error> {
+                let (value, variant) = self.inner.variant_seed(Wrap::new(seed, self.hr))?;
+                Ok((value, Wrap::new(variant, self.hr)))
+            }
+        }
+
+        impl<'de, A: de::VariantAccess<'de>> de::VariantAccess<'de> for Wrap<A> {
+            type Error = A::Error;
+
+            fn unit_variant(self) -> Result<(), A::Error> {
+                self.inner.unit_variant()
+            }
+
+            fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+                self,
+                seed: T,
+            ) -> Result<T::Value, A::Error> {
+                self.inner.newtype_variant_seed(Wrap::new(seed, self.hr))
+            }
+
+            fn tuple_variant<V: Visitor<'de>>(
+                self,
+                len: usize,
+                visitor: V,
+            ) -> Result<V::Value, A::Error> {
+                self.inner.tuple_variant(len, Wrap::new(visitor, self.hr))
+            }
+
+            fn struct_variant<V: Visitor<'de>>(
+                self,
+                fields: &'static [&'static str],
+                visitor: V,
+            ) -> Result<V::Value, A::Error> {
+                self.inner
+                    .struct_variant(fields, Wrap::new(visitor, self.hr))
+            }
+        }
+    }
+}
+
+/// Codecs that wrap another codec to add framing-level behaviour on top of it.
+pub mod combinators {
+    #[cfg(feature = "base64")]
+    pub use self::base64::*;
+    pub use self::cached::*;
+    pub use self::capture::*;
+    pub use self::either::*;
+    #[cfg(feature = "serde")]
+    pub use self::enveloped::*;
+    #[cfg(feature = "cbor")]
+    pub use self::finite_floats::*;
+    pub use self::hybrid_envelope::*;
+    pub use self::interleaved::*;
+    pub use self::map_err::*;
+    #[cfg(feature = "gzip")]
+    pub use self::maybe_gzip::*;
+    pub use self::named::*;
+    #[cfg(any(feature = "json", feature = "cbor"))]
+    pub use self::open_enum::*;
+    #[cfg(feature = "padded")]
+    pub use self::padded::*;
+    #[cfg(feature = "partially_encrypted")]
+    pub use self::partially_encrypted::*;
+    pub use self::pointer::*;
+    pub use self::reassembler::*;
+    pub use self::require_version::*;
+    pub use self::result::*;
+    #[cfg(feature = "secure_stack")]
+    pub use self::secure_stack::*;
+    pub use self::sequenced::*;
+    #[cfg(feature = "hmac")]
+    pub use self::signed::*;
+    #[cfg(feature = "zstd")]
+    pub use self::smart_compress::*;
+    #[cfg(feature = "json")]
+    pub use self::strict_json::*;
+    pub use self::tagged::*;
+    #[cfg(feature = "timed")]
+    pub use self::timed::*;
+    pub use self::validated::*;
+
+    use super::{put_frame, Deserializer, Serializer};
+    use bytes::{BufMut, Bytes, BytesMut};
+    use pin_project::pin_project;
+    use std::{io, pin::Pin};
+
+    /// The default limit on the size of a decompressed frame.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 8 * 1024 * 1024;
+
+    #[cfg(feature = "base64")]
+    mod base64 {
+        use super::*;
+        use base64_crate::{
+            engine::general_purpose::{STANDARD, URL_SAFE},
+            Engine,
+        };
+
+        /// The base64 alphabet used by [`Base64`].
+        #[cfg_attr(docsrs, doc(cfg(feature = "base64")))]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Alphabet {
+            /// The standard alphabet from RFC 4648, with `+` and `/`.
+            Standard,
+            /// The URL and filename safe alphabet from RFC 4648, with `-` and
+            /// `_`.
+            UrlSafe,
+        }
+
+        /// Base64-encodes the frames written by the inner codec.
+        ///
+        /// This allows tunneling binary formats over channels that only carry
+        /// text. Encoded frames are padded. Frames that aren't valid base64
+        /// in the configured alphabet are rejected with
+        /// [`io::ErrorKind::InvalidData`].
+        #[cfg_attr(docsrs, doc(cfg(feature = "base64")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Base64<Inner> {
+            #[pin]
+            inner: Inner,
+            alphabet: Alphabet,
+        }
+
+        impl<Inner> Base64<Inner> {
+            /// Creates a new `Base64` codec encoding frames with `alphabet`.
+            pub fn new(inner: Inner, alphabet: Alphabet) -> Self {
+                Self { inner, alphabet }
+            }
+
+            /// Returns the alphabet frames are encoded with.
+            pub fn alphabet(&self) -> Alphabet {
+                self.alphabet
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Base64`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        fn engine(alphabet: Alphabet) -> &'static impl Engine {
+            match alphabet {
+                Alphabet::Standard => &STANDARD,
+                Alphabet::UrlSafe => &URL_SAFE,
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for Base64<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let decoded = engine(*this.alphabet)
+                    .decode(src)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                this.inner
+                    .deserialize_owned(BytesMut::from(&decoded[..]))
+                    .map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Base64<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+
+                Ok(engine(*this.alphabet).encode(payload).into())
+            }
+        }
+    }
+
+    mod cached {
+        use super::*;
+        use std::{
+            collections::{hash_map::DefaultHasher, VecDeque},
+            fmt,
+            hash::{Hash, Hasher},
+        };
+
+        /// Reuses the frames of recently serialized values.
+        ///
+        /// The codec remembers the frames of the last `capacity` distinct
+        /// values it serialized, evicting the least recently used one. A value
+        /// equal to one of them is answered with a clone of the stored
+        /// [`Bytes`], which only bumps a reference count, instead of calling
+        /// the inner codec. This pays off in a fan-out loop serializing each
+        /// message once per subscriber with a shared codec, e.g. before
+        /// [`send_raw`](crate::Framed::send_raw), or when a connection keeps
+        /// repeating itself.
+        ///
+        /// Values are looked up by their [`Hash`] impl and compared with
+        /// [`Eq`], so a clone of each cached value is kept. Inner codecs whose
+        /// output depends on more than the value, e.g. one adding a sequence
+        /// number or a random nonce, must not be wrapped. Deserializing is
+        /// passed through to the inner codec.
+        #[pin_project]
+        pub struct Cached<Inner, SinkItem> {
+            #[pin]
+            inner: Inner,
+            capacity: usize,
+            entries: VecDeque<(u64, SinkItem, Bytes)>,
+        }
+
+        impl<Inner, SinkItem> Cached<Inner, SinkItem> {
+            /// Creates a new `Cached` remembering up to `capacity` frames.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `capacity` is zero.
+            pub fn new(inner: Inner, capacity: usize) -> Self {
+                assert!(capacity > 0, "capacity must be non-zero");
+
+                Self {
+                    inner,
+                    capacity,
+                    entries: VecDeque::with_capacity(capacity),
+                }
+            }
+
+            /// Forgets every cached frame.
+            pub fn clear(&mut self) {
+                self.entries.clear();
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Cached`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner: fmt::Debug, SinkItem> fmt::Debug for Cached<Inner, SinkItem> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Cached")
+                    .field("inner", &self.inner)
+                    .field("capacity", &self.capacity)
+                    .field("cached", &self.entries.len())
+                    .finish()
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Cached<Inner, SinkItem>
+        where
+            Inner: Serializer<SinkItem>,
+            SinkItem: Hash + Eq + Clone,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+
+                let mut hasher = DefaultHasher::new();
+                item.hash(&mut hasher);
+                let hash = hasher.finish();
+
+                let hit = this
+                    .entries
+                    .iter()
+                    .position(|(h, value, _)| *h == hash && value == item);
+                if let Some(i) = hit {
+                    let entry = this.entries.remove(i).expect("index in bounds");
+                    let frame = entry.2.clone();
+                    this.entries.push_front(entry);
+                    return Ok(frame);
+                }
+
+                let frame = this.inner.serialize(item)?;
+                if this.entries.len() == *this.capacity {
+                    this.entries.pop_back();
+                }
+                this.entries.push_front((hash, item.clone(), frame.clone()));
+
+                Ok(frame)
+            }
+        }
+
+        impl<Inner, SinkItem, Item> Deserializer<Item> for Cached<Inner, SinkItem>
+        where
+            Inner: Deserializer<Item>,
+        {
+            type Error = Inner::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                self.project().inner.deserialize(src)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                self.project().inner.deserialize_owned(src)
+            }
+        }
+    }
+
+    mod capture {
+        use super::*;
+        use futures_core::Stream;
+        use std::{
+            convert::TryFrom,
+            io::{Read, Write},
+            task::{Context, Poll},
+        };
+
+        /// Records every frame read by the inner codec, for later replay.
+        ///
+        /// Each frame is appended to the writer, usually a [`std::fs::File`],
+        /// as a big-endian `u32` length followed by the raw bytes, before being
+        /// decoded by the inner codec as usual. Failing to write the capture
+        /// fails the decode, so no frame goes unrecorded. The capture can be
+        /// read back with [`replay`]. Frames written through the `Serializer`
+        /// half are not recorded.
+        ///
+        /// Writes are blocking, which is fine for debugging sessions but
+        /// stalls the executor on slow disks.
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Capture<Inner, W> {
+            #[pin]
+            inner: Inner,
+            out: W,
+        }
+
+        impl<Inner, W> Capture<Inner, W> {
+            /// Creates a new `Capture` recording the frames of `inner` to
+            /// `out`.
+            pub fn new(inner: Inner, out: W) -> Self {
+                Self { inner, out }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Capture`, returning the inner codec and the
+            /// writer.
+            pub fn into_inner(self) -> (Inner, W) {
+                (self.inner, self.out)
+            }
+        }
+
+        impl<Inner, W, Item> Deserializer<Item> for Capture<Inner, W>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+            W: Write,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let len = u32::try_from(src.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "frame is too long to capture")
+                })?;
+
+                this.out.write_all(&len.to_be_bytes())?;
+                this.out.write_all(&src)?;
+
+                this.inner.deserialize_owned(src).map_err(Into::into)
+            }
+        }
+
+        impl<Inner, W, SinkItem> Serializer<SinkItem> for Capture<Inner, W>
+        where
+            Inner: Serializer<SinkItem>,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project().inner.serialize_mut(item, headroom)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                self.project().inner.serialize_to_buf(item, buf)
+            }
+        }
+
+        /// Reads back the frames recorded by a [`Capture`].
+        ///
+        /// The returned stream yields the frames in the order they were
+        /// captured and can be used as the transport of a
+        /// [`Framed`](crate::Framed) to feed them to a codec again. A capture
+        /// cut off in the middle of a frame yields an
+        /// [`io::ErrorKind::UnexpectedEof`] error. Like [`Capture`], it reads
+        /// from `reader` with blocking calls.
+        pub fn replay<R: Read>(reader: R) -> Replay<R> {
+            Replay {
+                reader,
+                done: false,
+            }
+        }
+
+        /// The stream returned by [`replay`].
+        #[derive(Debug)]
+        pub struct Replay<R> {
+            reader: R,
+            done: bool,
+        }
+
+        impl<R: Read> Replay<R> {
+            fn read_frame(&mut self) -> io::Result<Option<BytesMut>> {
+                let mut len = [0; 4];
+                let mut filled = 0;
+                while filled < len.len() {
+                    match self.reader.read(&mut len[filled..]) {
+                        Ok(0) if filled == 0 => return Ok(None),
+                        Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                        Ok(n) => filled += n,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                let mut frame = BytesMut::zeroed(u32::from_be_bytes(len) as usize);
+                self.reader.read_exact(&mut frame)?;
+                Ok(Some(frame))
+            }
+        }
+
+        impl<R: Read + Unpin> Stream for Replay<R> {
+            type Item = io::Result<BytesMut>;
+
+            fn poll_next(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<Option<Self::Item>> {
+                if self.done {
+                    return Poll::Ready(None);
+                }
+
+                let res = self.read_frame().transpose();
+                if !matches!(res, Some(Ok(_))) {
+                    self.done = true;
+                }
+                Poll::Ready(res)
+            }
+        }
+    }
+
+    mod either {
+        use super::*;
+
+        /// One of two codecs, picked at runtime.
+        ///
+        /// Serializing and deserializing dispatch to whichever variant is
+        /// active. Both codecs must handle the same items and report the same
+        /// error type. Unlike `AnyCodec`, which chooses among the built-in
+        /// formats, this works with any two codecs, e.g. user-defined ones
+        /// selected by configuration at startup.
+        #[pin_project(project = EitherProj)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Either<A, B> {
+            /// The first codec.
+            Left(#[pin] A),
+            /// The second codec.
+            Right(#[pin] B),
+        }
+
+        impl<A, B> Either<A, B> {
+            /// Returns `true` if the first codec is active.
+            pub fn is_left(&self) -> bool {
+                matches!(self, Either::Left(_))
+            }
+
+            /// Returns `true` if the second codec is active.
+            pub fn is_right(&self) -> bool {
+                matches!(self, Either::Right(_))
+            }
+        }
+
+        impl<A, B, Item> Deserializer<Item> for Either<A, B>
+        where
+            A: Deserializer<Item>,
+            B: Deserializer<Item, Error = A::Error>,
+        {
+            type Error = A::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                match self.project() {
+                    EitherProj::Left(codec) => codec.deserialize(src),
+                    EitherProj::Right(codec) => codec.deserialize(src),
+                }
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                match self.project() {
+                    EitherProj::Left(codec) => codec.deserialize_owned(src),
+                    EitherProj::Right(codec) => codec.deserialize_owned(src),
+                }
+            }
+        }
+
+        impl<A, B, SinkItem> Serializer<SinkItem> for Either<A, B>
+        where
+            A: Serializer<SinkItem>,
+            B: Serializer<SinkItem, Error = A::Error>,
+        {
+            type Error = A::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                match self.project() {
+                    EitherProj::Left(codec) => codec.serialize(item),
+                    EitherProj::Right(codec) => codec.serialize(item),
+                }
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                match self.project() {
+                    EitherProj::Left(codec) => codec.serialize_mut(item, headroom),
+                    EitherProj::Right(codec) => codec.serialize_mut(item, headroom),
+                }
+            }
+
+            fn serialize_to_buf<BM: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut BM,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                match self.project() {
+                    EitherProj::Left(codec) => codec.serialize_to_buf(item, buf),
+                    EitherProj::Right(codec) => codec.serialize_to_buf(item, buf),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    mod enveloped {
+        use super::*;
+        use serde::{
+            de::{self, MapAccess, SeqAccess, Visitor},
+            ser::SerializeStruct,
+            Deserialize, Serialize,
+        };
+        use std::{
+            fmt,
+            marker::PhantomData,
+            time::{Duration, SystemTime, UNIX_EPOCH},
+        };
+
+        /// Metadata carried by every frame of an [`Enveloped`] codec.
+        #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct EnvelopeMeta {
+            /// Send time in milliseconds since the Unix epoch.
+            pub ts: u64,
+            /// Time to live in milliseconds, counted from `ts`.
+            pub ttl_ms: u32,
+        }
+
+        impl EnvelopeMeta {
+            /// Returns the point in time after which the frame is stale.
+            ///
+            /// Returns `None` if that point can't be represented, e.g. for a
+            /// timestamp close to `u64::MAX` sent by a misbehaving peer. Such
+            /// a frame never expires.
+            pub fn expires_at(&self) -> Option<SystemTime> {
+                let ms = self.ts.saturating_add(u64::from(self.ttl_ms));
+                UNIX_EPOCH.checked_add(Duration::from_millis(ms))
+            }
+
+            /// Returns `true` if the frame's time to live has elapsed.
+            pub fn is_expired(&self) -> bool {
+                self.expires_at()
+                    .is_some_and(|expires_at| SystemTime::now() > expires_at)
+            }
+        }
+
+        /// The value an [`Enveloped`] codec hands to its inner codec.
+        ///
+        /// It serializes as a struct named `Envelope` with the fields `ts`,
+        /// `ttl_ms` and `payload`, in that order, so e.g. JSON frames look
+        /// like `{"ts":1700000000000,"ttl_ms":30000,"payload":...}`.
+        #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Envelope<T> {
+            /// Send time in milliseconds since the Unix epoch.
+            pub ts: u64,
+            /// Time to live in milliseconds, counted from `ts`.
+            pub ttl_ms: u32,
+            /// The wrapped value.
+            pub payload: T,
+        }
+
+        const FIELDS: &[&str] = &["ts", "ttl_ms", "payload"];
+
+        impl<T: Serialize> Serialize for Envelope<T> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut envelope = serializer.serialize_struct("Envelope", 3)?;
+                envelope.serialize_field("ts", &self.ts)?;
+                envelope.serialize_field("ttl_ms", &self.ttl_ms)?;
+                envelope.serialize_field("payload", &self.payload)?;
+                envelope.end()
+            }
+        }
+
+        impl<'de, T: Deserialize<'de>> Deserialize<'de> for Envelope<T> {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_struct("Envelope", FIELDS, EnvelopeVisitor(PhantomData))
+            }
+        }
+
+        enum Field {
+            Ts,
+            TtlMs,
+            Payload,
+            Other,
+        }
+
+        impl<'de> Deserialize<'de> for Field {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct FieldVisitor;
+
+                impl<'de> Visitor<'de> for FieldVisitor {
+                    type Value = Field;
+
+                    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                        f.write_str("a field name")
+                    }
+
+                    fn visit_str<E: de::Error>(self, v: &str) -> Result<Field, E> {
+                        Ok(match v {
+                            "ts" => Field::Ts,
+                            "ttl_ms" => Field::TtlMs,
+                            "payload" => Field::Payload,
+                            _ => Field::Other,
+                        })
+                    }
+
+                    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Field, E> {
+                        Ok(match v {
+                            0 => Field::Ts,
+                            1 => Field::TtlMs,
+                            2 => Field::Payload,
+                            _ => Field::Other,
+                        })
+                    }
+                }
+
+                deserializer.deserialize_identifier(FieldVisitor)
+            }
+        }
+
+        struct EnvelopeVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for EnvelopeVisitor<T> {
+            type Value = Envelope<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("struct Envelope")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Envelope<T>, A::Error> {
+                let ts = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let ttl_ms = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let payload = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+
+                Ok(Envelope {
+                    ts,
+                    ttl_ms,
+                    payload,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Envelope<T>, A::Error> {
+                let (mut ts, mut ttl_ms, mut payload) = (None, None, None);
+                while let Some(field) = map.next_key()? {
+                    match field {
+                        Field::Ts => ts = Some(map.next_value()?),
+                        Field::TtlMs => ttl_ms = Some(map.next_value()?),
+                        Field::Payload => payload = Some(map.next_value()?),
+                        Field::Other => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                Ok(Envelope {
+                    ts: ts.ok_or_else(|| de::Error::missing_field("ts"))?,
+                    ttl_ms: ttl_ms.ok_or_else(|| de::Error::missing_field("ttl_ms"))?,
+                    payload: payload.ok_or_else(|| de::Error::missing_field("payload"))?,
+                })
+            }
+        }
+
+        /// Tags every frame with its send time and a time to live.
+        ///
+        /// On serialize the value is wrapped in an [`Envelope`] holding the
+        /// current time (milliseconds since the Unix epoch) and the configured
+        /// TTL (milliseconds), and the envelope is serialized with the inner
+        /// codec, which must therefore handle `Envelope<SinkItem>`. The value
+        /// is cloned into the envelope. On deserialize the inner codec decodes
+        /// an `Envelope<Item>`, which is returned as [`EnvelopeMeta`]
+        /// alongside the value, so the receiver can drop stale messages.
+        #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Enveloped<Inner> {
+            #[pin]
+            inner: Inner,
+            ttl_ms: u32,
+            skew_ms: Option<u64>,
+            last_ts: Option<u64>,
+        }
+
+        /// A frame of a [monotonic](Enveloped::monotonic) [`Enveloped`] codec
+        /// was sent before the last accepted one.
+        ///
+        /// It is wrapped in an [`io::Error`] of kind
+        /// [`InvalidData`](io::ErrorKind::InvalidData) and can be recovered
+        /// with [`io::Error::get_ref`] and a downcast.
+        #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct StaleFrame {
+            ts: u64,
+            last_ts: u64,
+        }
+
+        impl StaleFrame {
+            /// Returns the send time of the rejected frame, in milliseconds
+            /// since the Unix epoch.
+            pub fn ts(&self) -> u64 {
+                self.ts
+            }
+
+            /// Returns the send time of the last accepted frame, in
+            /// milliseconds since the Unix epoch.
+            pub fn last_ts(&self) -> u64 {
+                self.last_ts
+            }
+        }
+
+        impl std::fmt::Display for StaleFrame {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(
+                    f,
+                    "stale frame sent at {} ms, last accepted frame was sent at {} ms",
+                    self.ts, self.last_ts
+                )
+            }
+        }
+
+        impl std::error::Error for StaleFrame {}
+
+        impl<Inner> Enveloped<Inner> {
+            /// Creates a new `Enveloped` codec stamping frames with `ttl`.
+            ///
+            /// TTLs longer than `u32::MAX` milliseconds are saturated.
+            pub fn new(inner: Inner, ttl: Duration) -> Self {
+                Self {
+                    inner,
+                    ttl_ms: ttl.as_millis().min(u128::from(u32::MAX)) as u32,
+                    skew_ms: None,
+                    last_ts: None,
+                }
+            }
+
+            /// Rejects frames sent before the last accepted one, to guard
+            /// against replayed or reordered frames.
+            ///
+            /// The codec remembers the newest timestamp it has accepted. A
+            /// frame stamped more than `tolerance` before it fails to decode
+            /// with an `InvalidData` error wrapping [`StaleFrame`]. The
+            /// tolerance absorbs clock skew between several senders sharing
+            /// the stream; use [`Duration::ZERO`] for a single sender.
+            pub fn monotonic(mut self, tolerance: Duration) -> Self {
+                self.skew_ms = Some(tolerance.as_millis().min(u128::from(u64::MAX)) as u64);
+                self
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Enveloped`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner> Enveloped<Inner> {
+            fn open<Item>(
+                self: Pin<&mut Self>,
+                envelope: Envelope<Item>,
+            ) -> Result<(EnvelopeMeta, Item), io::Error> {
+                let meta = EnvelopeMeta {
+                    ts: envelope.ts,
+                    ttl_ms: envelope.ttl_ms,
+                };
+
+                let this = self.project();
+                if let Some(skew_ms) = *this.skew_ms {
+                    if let Some(last_ts) = *this.last_ts {
+                        if meta.ts.saturating_add(skew_ms) < last_ts {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                StaleFrame {
+                                    ts: meta.ts,
+                                    last_ts,
+                                },
+                            ));
+                        }
+                    }
+                    *this.last_ts = Some(this.last_ts.map_or(meta.ts, |last| last.max(meta.ts)));
+                }
+
+                Ok((meta, envelope.payload))
+            }
+
+            fn seal<T: Clone>(&self, item: &T) -> io::Result<Envelope<T>> {
+                let ts = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(io::Error::other)?
+                    .as_millis() as u64;
+
+                Ok(Envelope {
+                    ts,
+                    ttl_ms: self.ttl_ms,
+                    payload: item.clone(),
+                })
+            }
+        }
+
+        impl<Inner, Item> Deserializer<(EnvelopeMeta, Item)> for Enveloped<Inner>
+        where
+            Inner: Deserializer<Envelope<Item>>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                mut self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<(EnvelopeMeta, Item), Self::Error> {
+                let envelope = self
+                    .as_mut()
+                    .project()
+                    .inner
+                    .deserialize(src)
+                    .map_err(Into::into)?;
+                self.open(envelope)
+            }
+
+            fn deserialize_owned(
+                mut self: Pin<&mut Self>,
+                src: BytesMut,
+            ) -> Result<(EnvelopeMeta, Item), Self::Error> {
+                let envelope = self
+                    .as_mut()
+                    .project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)?;
+                self.open(envelope)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Enveloped<Inner>
+        where
+            Inner: Serializer<Envelope<SinkItem>>,
+            Inner::Error: Into<io::Error>,
+            SinkItem: Clone,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let envelope = self.seal(item)?;
+                self.project()
+                    .inner
+                    .serialize(&envelope)
+                    .map_err(Into::into)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let envelope = self.seal(item)?;
+                self.project()
+                    .inner
+                    .serialize_mut(&envelope, headroom)
+                    .map_err(Into::into)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+
+    #[cfg(feature = "cbor")]
+    mod finite_floats {
+        use super::*;
+        use serde::Deserialize;
+        use serde_cbor::Value;
+
+        /// Rejects frames containing NaN or an infinite float.
+        ///
+        /// The inner codec decodes each frame into a dynamic
+        /// [`serde_cbor::Value`], which keeps floats exactly as they were on
+        /// the wire. Any float in it that isn't finite, however deeply nested,
+        /// fails the frame with [`io::ErrorKind::InvalidData`]; otherwise the
+        /// value is converted into the target type. Going through `Value`
+        /// costs an extra allocation per frame in exchange for working with
+        /// any inner codec that can decode into it, such as [`Cbor`] or
+        /// `MessagePack`. JSON text can't carry non-finite floats in the
+        /// first place.
+        ///
+        /// Serializing is passed through to the inner codec.
+        ///
+        /// [`Cbor`]: crate::formats::Cbor
+        #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct FiniteFloats<Inner> {
+            #[pin]
+            inner: Inner,
+        }
+
+        impl<Inner> FiniteFloats<Inner> {
+            /// Creates a new `FiniteFloats` checking the values decoded by
+            /// `inner`.
+            pub fn new(inner: Inner) -> Self {
+                Self { inner }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `FiniteFloats`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        fn is_finite(value: &Value) -> bool {
+            match value {
+                Value::Float(f) => f.is_finite(),
+                Value::Array(values) => values.iter().all(is_finite),
+                Value::Map(map) => map.iter().all(|(k, v)| is_finite(k) && is_finite(v)),
+                Value::Tag(_, value) => is_finite(value),
+                _ => true,
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for FiniteFloats<Inner>
+        where
+            Inner: Deserializer<Value>,
+            Inner::Error: Into<io::Error>,
+            for<'a> Item: Deserialize<'a>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let value = self.project().inner.deserialize(src).map_err(Into::into)?;
+
+                if !is_finite(&value) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame contains a non-finite float",
+                    ));
+                }
+
+                serde_cbor::value::from_value(value)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for FiniteFloats<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project().inner.serialize_mut(item, headroom)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                self.project().inner.serialize_to_buf(item, buf)
+            }
+        }
+    }
+
+    mod hybrid_envelope {
+        use super::*;
+        use std::{fmt, marker::PhantomData};
+
+        /// A header with a fixed-size binary encoding, carried in front of
+        /// the body by [`HybridEnvelope`].
+        ///
+        /// Implementations write and read their fields directly, e.g. with
+        /// [`BufMut::put_u32`](bytes::BufMut::put_u32) and
+        /// [`Buf::get_u32`](bytes::Buf::get_u32), so routing fields can be
+        /// read without going through serde.
+        pub trait FixedHeader: Sized {
+            /// The number of bytes written by [`write`](Self::write).
+            const LEN: usize;
+
+            /// Appends exactly [`LEN`](Self::LEN) bytes encoding the header to
+            /// `dst`.
+            fn write(&self, dst: &mut BytesMut);
+
+            /// Decodes a header from `src`, which holds exactly
+            /// [`LEN`](Self::LEN) bytes.
+            ///
+            /// Errors are returned from the codec as is, so invalid headers
+            /// should be reported as [`io::ErrorKind::InvalidData`].
+            fn read(src: &mut &[u8]) -> io::Result<Self>;
+        }
+
+        /// Frames a fixed binary header followed by a body encoded with the
+        /// inner codec.
+        ///
+        /// Values are sent as `(header, body)` pairs. The header is written by
+        /// its [`FixedHeader`] implementation and the body by the inner codec,
+        /// in the same frame. On deserialize the first `H::LEN` bytes are
+        /// decoded as the header and the rest is handed to the inner codec.
+        /// Frames shorter than a header, and headers whose `write` doesn't
+        /// produce exactly `H::LEN` bytes, are rejected with
+        /// [`io::ErrorKind::InvalidData`]; errors from the inner codec are
+        /// converted into [`io::Error`].
+        #[pin_project]
+        pub struct HybridEnvelope<H, Inner> {
+            #[pin]
+            inner: Inner,
+            header: PhantomData<fn(H) -> H>,
+        }
+
+        impl<H, Inner> HybridEnvelope<H, Inner> {
+            /// Creates a new `HybridEnvelope` encoding bodies with `inner`.
+            pub fn new(inner: Inner) -> Self {
+                Self {
+                    inner,
+                    header: PhantomData,
+                }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `HybridEnvelope`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<H, Inner: fmt::Debug> fmt::Debug for HybridEnvelope<H, Inner> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("HybridEnvelope")
+                    .field("inner", &self.inner)
+                    .finish()
+            }
+        }
+
+        impl<H, Inner, Item> Deserializer<(H, Item)> for HybridEnvelope<H, Inner>
+        where
+            H: FixedHeader,
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<(H, Item), Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<(H, Item), Self::Error> {
+                if src.len() < H::LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame is too short to carry a header",
+                    ));
+                }
+
+                let body = src.split_off(H::LEN);
+                let header = H::read(&mut &src[..])?;
+                let item = self
+                    .project()
+                    .inner
+                    .deserialize_owned(body)
+                    .map_err(Into::into)?;
+
+                Ok((header, item))
+            }
+        }
+
+        impl<H, Inner, SinkItem> Serializer<(H, SinkItem)> for HybridEnvelope<H, Inner>
+        where
+            H: FixedHeader,
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &(H, SinkItem)) -> Result<Bytes, Self::Error> {
+                let (header, body) = item;
+                let body = self.project().inner.serialize(body).map_err(Into::into)?;
+
+                let mut frame = BytesMut::with_capacity(H::LEN + body.len());
+                header.write(&mut frame);
+                if frame.len() != H::LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "header length doesn't match FixedHeader::LEN",
+                    ));
+                }
+                frame.extend_from_slice(&body);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    mod interleaved {
+        use super::*;
+        use bytes::{Buf, BufMut};
+
+        /// Identifier of a logical stream multiplexed by [`Interleaved`].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct Lane(pub u8);
+
+        /// Multiplexes several logical streams over a single transport.
+        ///
+        /// Values are sent as `(Lane, value)` pairs: the lane id is written as
+        /// a single byte in front of the inner codec's output and is handed
+        /// back alongside the decoded value, leaving it to the caller to
+        /// demultiplex the lanes, e.g. to keep control messages apart from
+        /// bulk data.
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Interleaved<Inner> {
+            #[pin]
+            inner: Inner,
+        }
+
+        impl<Inner> Interleaved<Inner> {
+            /// Creates a new `Interleaved` codec.
+            pub fn new(inner: Inner) -> Self {
+                Self { inner }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Interleaved`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<(Lane, Item)> for Interleaved<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<(Lane, Item), Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<(Lane, Item), Self::Error> {
+                if src.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame is missing its lane id",
+                    ));
+                }
+
+                let lane = Lane(src.get_u8());
+                let item = self
+                    .project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)?;
+
+                Ok((lane, item))
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<(Lane, SinkItem)> for Interleaved<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(
+                self: Pin<&mut Self>,
+                item: &(Lane, SinkItem),
+            ) -> Result<Bytes, Self::Error> {
+                let (Lane(lane), item) = item;
+                let payload = self.project().inner.serialize(item).map_err(Into::into)?;
+
+                let mut frame = BytesMut::with_capacity(1 + payload.len());
+                frame.put_u8(*lane);
+                frame.put_slice(&payload);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    mod map_err {
+        use super::*;
+        use std::fmt;
+
+        /// Converts the errors of the inner codec with a closure.
+        ///
+        /// This lets a codec report the application's own error type, so the
+        /// codec errors coming out of a [`Framed`](crate::Framed) built on it
+        /// need no `From` impl or conversion at every call site. The
+        /// transport's error type still has to be convertible from
+        /// [`io::Error`] for the errors `Framed` raises itself. The same
+        /// closure handles serialize and deserialize errors, so it must accept
+        /// the inner codec's error type for both.
+        #[pin_project]
+        pub struct MapErr<Inner, F> {
+            #[pin]
+            inner: Inner,
+            f: F,
+        }
+
+        impl<Inner, F> MapErr<Inner, F> {
+            /// Creates a new `MapErr` converting the errors of `inner` with
+            /// `f`.
+            pub fn new(inner: Inner, f: F) -> Self {
+                Self { inner, f }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `MapErr`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner: fmt::Debug, F> fmt::Debug for MapErr<Inner, F> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("MapErr")
+                    .field("inner", &self.inner)
+                    .finish_non_exhaustive()
+            }
+        }
+
+        impl<Inner, F, E, Item> Deserializer<Item> for MapErr<Inner, F>
+        where
+            Inner: Deserializer<Item>,
+            F: Fn(Inner::Error) -> E,
+        {
+            type Error = E;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, E> {
+                let this = self.project();
+                this.inner.deserialize(src).map_err(this.f)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, E> {
+                let this = self.project();
+                this.inner.deserialize_owned(src).map_err(this.f)
+            }
+
+            fn deserialize_in_place(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+                place: &mut Item,
+            ) -> Result<(), E> {
+                let this = self.project();
+                this.inner.deserialize_in_place(src, place).map_err(this.f)
+            }
+        }
+
+        impl<Inner, F, E, SinkItem> Serializer<SinkItem> for MapErr<Inner, F>
+        where
+            Inner: Serializer<SinkItem>,
+            F: Fn(Inner::Error) -> E,
+        {
+            type Error = E;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, E> {
+                let this = self.project();
+                this.inner.serialize(item).map_err(this.f)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, E> {
+                let this = self.project();
+                this.inner.serialize_mut(item, headroom).map_err(this.f)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), E>
+            where
+                E: From<io::Error>,
+            {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)?;
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    mod maybe_gzip {
+        use super::*;
+        use crate::with_headroom;
+        use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+        use std::io::{Read, Write};
+
+        const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+        /// Decompresses gzip frames, and passes other frames through, before
+        /// handing them to the inner codec.
+        ///
+        /// This lets a receiver serve peers that gzip their frames alongside
+        /// peers that don't, when the frames carry no flag telling them apart.
+        /// A frame starting with the gzip magic bytes `1f 8b` is decompressed,
+        /// and rejected with [`io::ErrorKind::InvalidData`] if that fails.
+        /// Inner formats whose output may itself start with those bytes, e.g.
+        /// some binary formats, can't be told apart from gzip and shouldn't be
+        /// used with this codec. Text formats such as JSON are safe.
+        ///
+        /// Serialized frames are gzip-compressed unless compression is turned
+        /// off with [`with_compression`](Self::with_compression).
+        ///
+        /// A gzip frame can decompress to far more than its own length, so
+        /// decompression stops once the payload grows past
+        /// [`with_max_decompressed_len`](Self::with_max_decompressed_len), 8
+        /// MiB by default, and the frame is rejected with
+        /// [`io::ErrorKind::InvalidData`].
+        #[cfg_attr(docsrs, doc(cfg(feature = "gzip")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct MaybeGzip<Inner> {
+            #[pin]
+            inner: Inner,
+            compress: bool,
+            max_decompressed_len: usize,
+        }
+
+        impl<Inner> MaybeGzip<Inner> {
+            /// Creates a new `MaybeGzip` codec compressing the frames it
+            /// writes.
+            pub fn new(inner: Inner) -> Self {
+                Self {
+                    inner,
+                    compress: true,
+                    max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+                }
+            }
+
+            /// Sets whether serialized frames are gzip-compressed.
+            ///
+            /// Decoding accepts both kinds of frames either way.
+            pub fn with_compression(mut self, compress: bool) -> Self {
+                self.compress = compress;
+                self
+            }
+
+            /// Sets the largest payload a gzip frame may decompress to.
+            pub fn with_max_decompressed_len(mut self, max: usize) -> Self {
+                self.max_decompressed_len = max;
+                self
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `MaybeGzip`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for MaybeGzip<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+
+                if !src.starts_with(&MAGIC) {
+                    return this.inner.deserialize(src).map_err(Into::into);
+                }
+
+                let max = *this.max_decompressed_len;
+                let mut payload = Vec::new();
+                GzDecoder::new(&src[..])
+                    .take(max as u64 + 1)
+                    .read_to_end(&mut payload)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                if payload.len() > max {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "decompressed frame exceeds the maximum length",
+                    ));
+                }
+
+                this.inner
+                    .deserialize_owned(BytesMut::from(&payload[..]))
+                    .map_err(Into::into)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                if src.starts_with(&MAGIC) {
+                    return self.deserialize(&src);
+                }
+
+                self.project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for MaybeGzip<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+
+                if !*this.compress {
+                    return Ok(payload);
+                }
+
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&payload)?;
+                Ok(encoder.finish()?.into())
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let this = self.project();
+                if !*this.compress {
+                    return this.inner.serialize_mut(item, headroom).map_err(Into::into);
+                }
+
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+                let frame = with_headroom(headroom, 0).writer();
+                let mut encoder = GzEncoder::new(frame, Compression::default());
+                encoder.write_all(&payload)?;
+                Ok(encoder.finish()?.into_inner())
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let this = self.project();
+                if !*this.compress {
+                    let frame = this.inner.serialize_mut(item, 0).map_err(Into::into)?;
+                    return put_frame(buf, &frame);
+                }
+
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+                let mut encoder = GzEncoder::new(buf.writer(), Compression::default());
+                encoder.write_all(&payload)?;
+                encoder.finish()?;
+                Ok(())
+            }
+        }
+    }
+
+    mod named {
+        use super::*;
+        use crate::byteorder_helpers::{get_varint, put_varint, MAX_VARINT_LEN};
+        use bytes::BufMut;
+        use std::convert::TryFrom;
+
+        /// Prefixes every frame with the name of the message type.
+        ///
+        /// On serialize the frame is laid out as the length of `type_name` as
+        /// an unsigned LEB128 varint, the UTF-8 bytes of `type_name`, then the
+        /// inner codec's output. On deserialize the name is read back and
+        /// returned alongside the decoded value, which lets gateways log or
+        /// route messages by name without knowing their type.
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Named<Inner> {
+            #[pin]
+            inner: Inner,
+            type_name: &'static str,
+        }
+
+        impl<Inner> Named<Inner> {
+            /// Creates a new `Named` codec tagging frames with `type_name`.
+            pub fn new(inner: Inner, type_name: &'static str) -> Self {
+                Self { inner, type_name }
+            }
+
+            /// Returns the name written in front of every frame.
+            pub fn type_name(&self) -> &'static str {
+                self.type_name
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Named`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        fn invalid_data(msg: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+
+        impl<Inner, Item> Deserializer<(String, Item)> for Named<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<(String, Item), Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<(String, Item), Self::Error> {
+                let len =
+                    get_varint(&mut src).map_err(|_| invalid_data("invalid type name length"))?;
+                let len = usize::try_from(len)
+                    .ok()
+                    .filter(|&len| len <= src.len())
+                    .ok_or_else(|| invalid_data("type name exceeds frame"))?;
+
+                let name = src.split_to(len);
+                let name = std::str::from_utf8(&name)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .to_owned();
+
+                let item = self
+                    .project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)?;
+
+                Ok((name, item))
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Named<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.serialize_mut(item, 0).map(BytesMut::freeze)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let this = self.project();
+                let name = this.type_name.as_bytes();
+
+                let mut len = [0; MAX_VARINT_LEN];
+                let mut rest = &mut len[..];
+                put_varint(&mut rest, name.len() as u64);
+                let written = MAX_VARINT_LEN - rest.len();
+                let len = &len[..written];
+
+                let header_len = len.len() + name.len();
+                let mut frame = this
+                    .inner
+                    .serialize_mut(item, headroom + header_len)
+                    .map_err(Into::into)?;
+
+                let mut header = &mut frame[headroom..headroom + header_len];
+                header.put_slice(len);
+                header.put_slice(name);
+                Ok(frame)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+
+    #[cfg(any(feature = "json", feature = "cbor"))]
+    mod open_enum {
+        use super::*;
+        use serde::de::{self, DeserializeSeed, Visitor};
+        use std::{cell::Cell, fmt, marker::PhantomData};
+
+        /// An enum with a fallback case for variants the decoder doesn't know.
+        pub trait UnknownVariant: Sized {
+            /// Builds the fallback value from the unrecognised variant `tag`
+            /// and the `raw` frame it was read from.
+            fn unknown_variant(tag: String, raw: Bytes) -> Self;
+        }
+
+        /// Decodes unknown enum variants into a fallback value instead of
+        /// failing.
+        ///
+        /// When a frame names a variant `Item` doesn't have, the variant's
+        /// tag and the untouched frame are handed to
+        /// [`UnknownVariant::unknown_variant`]. This lets older binaries keep
+        /// reading streams written by newer ones. Any other decode error is
+        /// returned as is.
+        ///
+        /// The tag is caught where serde reads it: the variant name of an
+        /// externally tagged enum, or the tag field of an internally or
+        /// adjacently tagged one. Supported for [`Json`] and [`Cbor`] codecs.
+        ///
+        /// [`Json`]: crate::formats::Json
+        /// [`Cbor`]: crate::formats::Cbor
+        #[cfg_attr(docsrs, doc(cfg(any(feature = "json", feature = "cbor"))))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct OpenEnum<Inner> {
+            #[pin]
+            pub(crate) inner: Inner,
+        }
+
+        impl<Inner> OpenEnum<Inner> {
+            /// Creates a new `OpenEnum` wrapping `inner`.
+            pub fn new(inner: Inner) -> Self {
+                Self { inner }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `OpenEnum`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for OpenEnum<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project().inner.serialize_mut(item, headroom)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                self.project().inner.serialize_to_buf(item, buf)
+            }
+        }
+
+        /// Runs `decode` with a seed noting the tag `Item` rejected, and
+        /// turns a failure on that tag into the fallback value.
+        pub(crate) fn decode_open<Item, E>(
+            src: &BytesMut,
+            decode: impl FnOnce(OpenSeed<'_, Item>) -> Result<Item, E>,
+        ) -> Result<Item, E>
+        where
+            Item: UnknownVariant,
+        {
+            let unknown = Cell::new(None);
+            let seed = OpenSeed {
+                unknown: &unknown,
+                ghost: PhantomData,
+            };
+
+            decode(seed).or_else(|err| match unknown.take() {
+                Some(tag) => Ok(Item::unknown_variant(tag, src.clone().freeze())),
+                None => Err(err),
+            })
+        }
+
+        /// Deserializes an `Item`, recording the variant tag its visitor
+        /// rejected in `unknown`.
+        pub(crate) struct OpenSeed<'a, Item> {
+            unknown: &'a Cell<Option<String>>,
+            ghost: PhantomData<Item>,
+        }
+
+        impl<'de, Item: de::Deserialize<'de>> DeserializeSeed<'de> for OpenSeed<'_, Item> {
+            type Value = Item;
+
+            fn deserialize<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Item, D::Error> {
+                Item::deserialize(Capture::new(deserializer, self.unknown))
+            }
+        }
+
+        /// Wraps the top level of the value to find where the tag is read.
+        struct Capture<'a, T> {
+            inner: T,
+            unknown: &'a Cell<Option<String>>,
+        }
+
+        impl<'a, T> Capture<'a, T> {
+            fn new(inner: T, unknown: &'a Cell<Option<String>>) -> Self {
+                Self { inner, unknown }
+            }
+        }
+
+        /// Wraps the deserializer of the tag itself.
+        struct Tag<'a, T> {
+            inner: T,
+            unknown: &'a Cell<Option<String>>,
+        }
+
+        impl<'a, T> Tag<'a, T> {
+            fn new(inner: T, unknown: &'a Cell<Option<String>>) -> Self {
+                Self { inner, unknown }
+            }
+        }
+
+        macro_rules! forward_deserialize {
+            ($wrap:ident: $($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+                fn $method<V: Visitor<'de>>(
+                    self,
+                    $($arg: $ty,)*
+                    visitor: V,
+                ) -> Result<V::Value, D::Error> {
+                    self.inner.$method($($arg,)* $wrap!(self, visitor))
+                }
+            )*};
+        }
+
+        macro_rules! capture {
+            ($self:ident, $visitor:ident) => {
+                Capture::new($visitor, $self.unknown)
+            };
+        }
+
+        macro_rules! unwrapped {
+            ($self:ident, $visitor:ident) => {
+                $visitor
+            };
+        }
+
+        impl<'de, D: de::Deserializer<'de>> de::Deserializer<'de> for Capture<'_, D> {
+            type Error = D::Error;
+
+            forward_deserialize! { capture:
+                deserialize_any();
+                deserialize_bool();
+                deserialize_i8();
+                deserialize_i16();
+                deserialize_i32();
+                deserialize_i64();
+                deserialize_i128();
+                deserialize_u8();
+                deserialize_u16();
+                deserialize_u32();
+                deserialize_u64();
+                deserialize_u128();
+                deserialize_f32();
+                deserialize_f64();
+                deserialize_char();
+                deserialize_str();
+                deserialize_string();
+                deserialize_bytes();
+                deserialize_byte_buf();
+                deserialize_option();
+                deserialize_unit();
+                deserialize_unit_struct(name: &'static str);
+                deserialize_newtype_struct(name: &'static str);
+                deserialize_seq();
+                deserialize_tuple(len: usize);
+                deserialize_tuple_struct(name: &'static str, len: usize);
+                deserialize_map();
+                deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+                deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+                deserialize_identifier();
+                deserialize_ignored_any();
+            }
+
+            fn is_human_readable(&self) -> bool {
+                self.inner.is_human_readable()
+            }
+        }
+
+        macro_rules! forward_visit {
+            ($($method:ident($ty:ty);)*) => {$(
+                fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+                    self.inner.$method(v)
+                }
+            )*};
+        }
+
+        /// Forwards everything but maps and enums, whose tags are wrapped.
+        impl<'de, V: Visitor<'de>> Visitor<'de> for Capture<'_, V> {
+            type Value = V::Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.inner.expecting(f)
+            }
+
+            forward_visit! {
+                visit_bool(bool);
+                visit_i8(i8);
+                visit_i16(i16);
+                visit_i32(i32);
+                visit_i64(i64);
+                visit_i128(i128);
+                visit_u8(u8);
+                visit_u16(u16);
+                visit_u32(u32);
+                visit_u64(u64);
+                visit_u128(u128);
+                visit_f32(f32);
+                visit_f64(f64);
+                visit_char(char);
+                visit_str(&str);
+                visit_borrowed_str(&'de str);
+                visit_string(String);
+                visit_bytes(&[u8]);
+                visit_borrowed_bytes(&'de [u8]);
+                visit_byte_buf(Vec<u8>);
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+                self.inner.visit_none()
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+                self.inner.visit_unit()
+            }
+
+            fn visit_some<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<V::Value, D::Error> {
+                self.inner.visit_some(deserializer)
+            }
+
+            fn visit_newtype_struct<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<V::Value, D::Error> {
+                self.inner.visit_newtype_struct(deserializer)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+                self.inner.visit_seq(seq)
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+                self.inner.visit_map(Capture::new(map, self.unknown))
+            }
+
+            fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+                self.inner.visit_enum(Capture::new(data, self.unknown))
+            }
+        }
+
+        /// Wraps the values, where internally and adjacently tagged enums
+        /// keep their tag.
+        impl<'de, A: de::MapAccess<'de>> de::MapAccess<'de> for Capture<'_, A> {
+            type Error = A::Error;
+
+            fn next_key_seed<K: DeserializeSeed<'de>>(
+                &mut self,
+                seed: K,
+            ) -> Result<Option<K::Value>, A::Error> {
+                self.inner.next_key_seed(seed)
+            }
+
+            fn next_value_seed<T: DeserializeSeed<'de>>(
+                &mut self,
+                seed: T,
+            ) -> Result<T::Value, A::Error> {
+                self.inner.next_value_seed(Tag::new(seed, self.unknown))
+            }
+
+            fn size_hint(&self) -> Option<usize> {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<'de, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Capture<'_, A> {
+            type Error = A::Error;
+            type Variant = A::Variant;
+
+            fn variant_seed<T: DeserializeSeed<'de>>(
+                self,
+                seed: T,
+            ) -> Result<(T::Value, A::Variant), A::Error> {
+                self.inner.variant_seed(Tag::new(seed, self.unknown))
+            }
+        }
+
+        impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Tag<'_, S> {
+            type Value = S::Value;
+
+            fn deserialize<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<S::Value, D::Error> {
+                self.inner.deserialize(Tag::new(deserializer, self.unknown))
+            }
+        }
+
+        /// Only identifiers are tags; any other value is left alone.
+        impl<'de, D: de::Deserializer<'de>> de::Deserializer<'de> for Tag<'_, D> {
+            type Error = D::Error;
+
+            forward_deserialize! { unwrapped:
+                deserialize_any();
+                deserialize_bool();
+                deserialize_i8();
+                deserialize_i16();
+                deserialize_i32();
+                deserialize_i64();
+                deserialize_i128();
+                deserialize_u8();
+                deserialize_u16();
+                deserialize_u32();
+                deserialize_u64();
+                deserialize_u128();
+                deserialize_f32();
+                deserialize_f64();
+                deserialize_char();
+                deserialize_str();
+                deserialize_string();
+                deserialize_bytes();
+                deserialize_byte_buf();
+                deserialize_option();
+                deserialize_unit();
+                deserialize_unit_struct(name: &'static str);
+                deserialize_newtype_struct(name: &'static str);
+                deserialize_seq();
+                deserialize_tuple(len: usize);
+                deserialize_tuple_struct(name: &'static str, len: usize);
+                deserialize_map();
+                deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+                deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+                deserialize_ignored_any();
+            }
+
+            fn deserialize_identifier<V: Visitor<'de>>(
+                self,
+                visitor: V,
+            ) -> Result<V::Value, D::Error> {
+                self.inner
+                    .deserialize_identifier(Tag::new(visitor, self.unknown))
+            }
+
+            fn is_human_readable(&self) -> bool {
+                self.inner.is_human_readable()
+            }
+        }
+
+        /// Records the tag if the variant visitor rejected it.
+        fn note<T, E>(
+            unknown: &Cell<Option<String>>,
+            res: Result<T, E>,
+            tag: impl FnOnce() -> String,
+        ) -> Result<T, E> {
+            if res.is_err() {
+                unknown.set(Some(tag()));
+            }
+            res
+        }
+
+        macro_rules! note_visit {
+            ($($method:ident($v:ident: $ty:ty) => $tag:expr;)*) => {$(
+                fn $method<E: de::Error>(self, $v: $ty) -> Result<V::Value, E> {
+                    let unknown = self.unknown;
+                    note(unknown, self.inner.$method($v), || $tag)
+                }
+            )*};
+        }
+
+        impl<'de, V: Visitor<'de>> Visitor<'de> for Tag<'_, V> {
+            type Value = V::Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.inner.expecting(f)
+            }
+
+            note_visit! {
+                visit_u64(v: u64) => v.to_string();
+                visit_str(v: &str) => v.to_owned();
+                visit_borrowed_str(v: &'de str) => v.to_owned();
+                visit_bytes(v: &[u8]) => String::from_utf8_lossy(v).into_owned();
+                visit_borrowed_bytes(v: &'de [u8]) => String::from_utf8_lossy(v).into_owned();
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<V::Value, E> {
+                self.visit_str(&v)
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<V::Value, E> {
+                self.visit_bytes(&v)
+            }
+        }
+    }
+
+    #[cfg(feature = "padded")]
+    mod padded {
+        use super::*;
+        use bytes::{Buf, BufMut};
+        use std::convert::TryFrom;
+
+        const LEN_LEN: usize = 4;
+
+        /// Pads the frames written by the inner codec to one of a few fixed
+        /// sizes.
+        ///
+        /// Each frame is grown to the smallest bucket in `sizes` able to hold
+        /// it, so an observer only learns which bucket a message fell in
+        /// rather than its exact length. The frame starts with the payload's
+        /// true length as a big-endian `u32`, followed by the payload and
+        /// random padding. Payloads too large for the biggest bucket or for
+        /// the `u32` length are rejected with [`io::ErrorKind::InvalidInput`].
+        ///
+        /// The length is sent in the clear, so this only hides anything when
+        /// the frames are encrypted afterwards, e.g. by TLS.
+        #[cfg_attr(docsrs, doc(cfg(feature = "padded")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Padded<Inner> {
+            #[pin]
+            inner: Inner,
+            sizes: Vec<usize>,
+        }
+
+        impl<Inner> Padded<Inner> {
+            /// Creates a new `Padded` codec padding frames to the given bucket
+            /// sizes, which include the 4-byte length.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `sizes` is empty.
+            pub fn new(inner: Inner, mut sizes: Vec<usize>) -> Self {
+                assert!(!sizes.is_empty(), "at least one bucket size is required");
+                sizes.sort_unstable();
+                sizes.dedup();
+                Self { inner, sizes }
+            }
+
+            /// Returns the bucket sizes, in increasing order.
+            pub fn sizes(&self) -> &[usize] {
+                &self.sizes
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Padded`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for Padded<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<Item, Self::Error> {
+                if src.len() < LEN_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame is too short to carry a length",
+                    ));
+                }
+
+                let len = src.get_u32() as usize;
+                if len > src.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "payload length exceeds the frame",
+                    ));
+                }
+
+                src.truncate(len);
+                self.project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Padded<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.serialize_mut(item, 0).map(BytesMut::freeze)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let this = self.project();
+                let mut frame = this
+                    .inner
+                    .serialize_mut(item, headroom + LEN_LEN)
+                    .map_err(Into::into)?;
+                let len = frame.len() - headroom - LEN_LEN;
+
+                let size = this
+                    .sizes
+                    .iter()
+                    .copied()
+                    .find(|size| *size >= LEN_LEN + len)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "frame is larger than the largest bucket",
+                        )
+                    })?;
+
+                let prefix = u32::try_from(len).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "frame is too long for its length prefix",
+                    )
+                })?;
+                (&mut frame[headroom..headroom + LEN_LEN]).put_u32(prefix);
+                let start = frame.len();
+                frame.resize(headroom + size, 0);
+                getrandom::getrandom(&mut frame[start..]).map_err(io::Error::from)?;
+                Ok(frame)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+
+    #[cfg(feature = "partially_encrypted")]
+    mod partially_encrypted {
+        use super::*;
+        use bytes::{Buf, BufMut};
+        use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+        use chacha20poly1305::aead::{Aead, NewAead, Payload};
+        use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+        use educe::Educe;
+        use std::convert::TryFrom;
+
+        const LEN_LEN: usize = 4;
+        const NONCE_LEN: usize = 24;
+
+        /// Sends a cleartext header alongside an encrypted body.
+        ///
+        /// Values are `(header, body)` pairs, each written by its own codec.
+        /// A frame holds the header's length as a big-endian `u32`, the
+        /// header, a random nonce and the body sealed with XChaCha20-Poly1305.
+        /// The header is passed to the cipher as associated data, so it can be
+        /// read by anyone, e.g. a router that uses
+        /// [`header_bytes`](Self::header_bytes) without knowing the key, but
+        /// not changed without the body failing to authenticate. Frames that
+        /// are truncated or fail to authenticate are rejected with
+        /// [`io::ErrorKind::InvalidData`].
+        #[cfg_attr(docsrs, doc(cfg(feature = "partially_encrypted")))]
+        #[pin_project]
+        #[derive(Educe)]
+        #[educe(Debug(bound))]
+        pub struct PartiallyEncrypted<HeaderCodec, BodyCodec> {
+            #[pin]
+            header: HeaderCodec,
+            #[pin]
+            body: BodyCodec,
+            #[educe(Debug(ignore))]
+            cipher: XChaCha20Poly1305,
+        }
+
+        impl<HeaderCodec, BodyCodec> PartiallyEncrypted<HeaderCodec, BodyCodec> {
+            /// Creates a new `PartiallyEncrypted` encrypting bodies with
+            /// `key`.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `key` is not 32 bytes long.
+            pub fn new(header: HeaderCodec, body: BodyCodec, key: impl AsRef<[u8]>) -> Self {
+                Self {
+                    header,
+                    body,
+                    cipher: XChaCha20Poly1305::new(Key::from_slice(key.as_ref())),
+                }
+            }
+
+            /// Returns the encoded header of `frame` without decrypting
+            /// anything.
+            ///
+            /// The header hasn't been authenticated at this point, so it
+            /// should only be trusted for routing.
+            pub fn header_bytes(frame: &[u8]) -> io::Result<&[u8]> {
+                split_header(frame).map(|(header, _)| header)
+            }
+
+            /// Returns a reference to the header codec.
+            pub fn header_ref(&self) -> &HeaderCodec {
+                &self.header
+            }
+
+            /// Returns a reference to the body codec.
+            pub fn body_ref(&self) -> &BodyCodec {
+                &self.body
+            }
+
+            /// Consumes the `PartiallyEncrypted`, returning the header and
+            /// body codecs.
+            pub fn into_inner(self) -> (HeaderCodec, BodyCodec) {
+                (self.header, self.body)
+            }
+        }
+
+        fn split_header(mut frame: &[u8]) -> io::Result<(&[u8], &[u8])> {
+            let truncated = || io::Error::new(io::ErrorKind::InvalidData, "truncated frame");
+
+            if frame.len() < LEN_LEN {
+                return Err(truncated());
+            }
+            let len = frame.get_u32() as usize;
+            if frame.len() < len + NONCE_LEN {
+                return Err(truncated());
+            }
+
+            Ok(frame.split_at(len))
+        }
+
+        impl<HeaderCodec, BodyCodec, H, B> Deserializer<(H, B)>
+            for PartiallyEncrypted<HeaderCodec, BodyCodec>
+        where
+            HeaderCodec: Deserializer<H>,
+            HeaderCodec::Error: Into<io::Error>,
+            BodyCodec: Deserializer<B>,
+            BodyCodec::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<(H, B), Self::Error> {
+                let this = self.project();
+                let (header, sealed) = split_header(src)?;
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+                let payload = Payload {
+                    msg: ciphertext,
+                    aad: header,
+                };
+                let body = this
+                    .cipher
+                    .decrypt(XNonce::from_slice(nonce), payload)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                let header = this
+                    .header
+                    .deserialize_owned(BytesMut::from(header))
+                    .map_err(Into::into)?;
+                let body = this
+                    .body
+                    .deserialize_owned(BytesMut::from(&body[..]))
+                    .map_err(Into::into)?;
+
+                Ok((header, body))
+            }
+        }
+
+        impl<HeaderCodec, BodyCodec, H, B> Serializer<(H, B)> for PartiallyEncrypted<HeaderCodec, BodyCodec>
+        where
+            HeaderCodec: Serializer<H>,
+            HeaderCodec::Error: Into<io::Error>,
+            BodyCodec: Serializer<B>,
+            BodyCodec::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &(H, B)) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let header = this.header.serialize(&item.0).map_err(Into::into)?;
+                let body = this.body.serialize(&item.1).map_err(Into::into)?;
+
+                let len = u32::try_from(header.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "header is too long")
+                })?;
+
+                let mut nonce = XNonce::default();
+                OsRng.fill_bytes(&mut nonce);
+                let payload = Payload {
+                    msg: &body,
+                    aad: &header,
+                };
+                let ciphertext = this
+                    .cipher
+                    .encrypt(&nonce, payload)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                let mut frame =
+                    BytesMut::with_capacity(LEN_LEN + header.len() + NONCE_LEN + ciphertext.len());
+                frame.put_u32(len);
+                frame.put_slice(&header);
+                frame.put_slice(&nonce);
+                frame.put_slice(&ciphertext);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    mod pointer {
+        use super::*;
+        use std::sync::Arc;
+
+        /// Lets a codec for `T` handle `Arc<T>` and `Box<T>` values.
+        ///
+        /// Serializing goes through the pointer to the inner codec, so shared
+        /// messages are sent without being cloned out of their `Arc`. Decoded
+        /// values are moved straight into a new `Arc` or `Box`. Errors from
+        /// the inner codec are returned unchanged.
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Pointer<Inner> {
+            #[pin]
+            inner: Inner,
+        }
+
+        impl<Inner> Pointer<Inner> {
+            /// Creates a new `Pointer` wrapping `inner`.
+            pub fn new(inner: Inner) -> Self {
+                Self { inner }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Pointer`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, T> Serializer<Arc<T>> for Pointer<Inner>
+        where
+            Inner: Serializer<T>,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &Arc<T>) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &Arc<T>,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project().inner.serialize_mut(item, headroom)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &Arc<T>,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                self.project().inner.serialize_to_buf(item, buf)
+            }
+        }
+
+        impl<Inner, T> Serializer<Box<T>> for Pointer<Inner>
+        where
+            Inner: Serializer<T>,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &Box<T>) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &Box<T>,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project().inner.serialize_mut(item, headroom)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &Box<T>,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                self.project().inner.serialize_to_buf(item, buf)
+            }
+        }
+
+        impl<Inner, T> Deserializer<Arc<T>> for Pointer<Inner>
+        where
+            Inner: Deserializer<T>,
+        {
+            type Error = Inner::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Arc<T>, Self::Error> {
+                self.project().inner.deserialize(src).map(Arc::new)
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                src: BytesMut,
+            ) -> Result<Arc<T>, Self::Error> {
+                self.project().inner.deserialize_owned(src).map(Arc::new)
+            }
+        }
+
+        impl<Inner, T> Deserializer<Box<T>> for Pointer<Inner>
+        where
+            Inner: Deserializer<T>,
+        {
+            type Error = Inner::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Box<T>, Self::Error> {
+                self.project().inner.deserialize(src).map(Box::new)
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                src: BytesMut,
+            ) -> Result<Box<T>, Self::Error> {
+                self.project().inner.deserialize_owned(src).map(Box::new)
+            }
+        }
+    }
+
+    mod reassembler {
+        use super::*;
+        use bytes::Buf;
+        use std::{
+            collections::HashMap,
+            time::{Duration, Instant},
+        };
+
+        const HEADER_LEN: usize = 12;
+
+        #[derive(Debug)]
+        struct Partial {
+            parts: Vec<Option<BytesMut>>,
+            received: usize,
+            len: usize,
+            started: Instant,
+            last_update: u64,
+        }
+
+        /// Reassembles values that the sender split across several frames.
+        ///
+        /// Every frame starts with a 12-byte header: the message id as a
+        /// big-endian `u64`, then the index of the part and the total number
+        /// of parts, both big-endian `u16`s. Parts are buffered per message id
+        /// in any order, and once all of them arrived their payloads are
+        /// concatenated and decoded with the inner codec. Until then
+        /// deserializing yields `Ok(None)`. Repeated parts are ignored.
+        ///
+        /// At most `max_in_flight` messages are buffered. A part starting a
+        /// new message when the limit is reached evicts the partial message
+        /// that was updated least recently, whose parts are dropped. The same
+        /// happens when the payloads buffered for all messages would exceed
+        /// [`with_max_buffered_len`](Self::with_max_buffered_len), 64 MiB by
+        /// default. A message whose parts add up to more than
+        /// [`with_max_message_len`](Self::with_max_message_len), 8 MiB by
+        /// default, is dropped with an error. Messages still incomplete after
+        /// [`with_timeout`](Self::with_timeout), if set, are dropped as well.
+        ///
+        /// Malformed headers, parts disagreeing on a message's total and
+        /// messages over the length limit are rejected with
+        /// [`io::ErrorKind::InvalidData`]; errors from the inner codec are
+        /// converted into [`io::Error`].
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Reassembler<Inner> {
+            #[pin]
+            inner: Inner,
+            max_in_flight: usize,
+            max_message_len: usize,
+            max_buffered_len: usize,
+            timeout: Option<Duration>,
+            partial: HashMap<u64, Partial>,
+            buffered: usize,
+            clock: u64,
+        }
+
+        impl<Inner> Reassembler<Inner> {
+            /// Creates a new `Reassembler` buffering up to `max_in_flight`
+            /// incomplete messages.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `max_in_flight` is zero.
+            pub fn new(inner: Inner, max_in_flight: usize) -> Self {
+                assert!(max_in_flight > 0, "max_in_flight must be at least 1");
+                Self {
+                    inner,
+                    max_in_flight,
+                    max_message_len: 8 * 1024 * 1024,
+                    max_buffered_len: 64 * 1024 * 1024,
+                    timeout: None,
+                    partial: HashMap::new(),
+                    buffered: 0,
+                    clock: 0,
+                }
+            }
+
+            /// Sets the largest message, summed over its parts, that is
+            /// reassembled.
+            pub fn with_max_message_len(mut self, max: usize) -> Self {
+                self.max_message_len = max;
+                self
+            }
+
+            /// Sets how many payload bytes may be buffered for all incomplete
+            /// messages together.
+            pub fn with_max_buffered_len(mut self, max: usize) -> Self {
+                self.max_buffered_len = max;
+                self
+            }
+
+            /// Drops messages still incomplete `timeout` after their first
+            /// part arrived.
+            pub fn with_timeout(mut self, timeout: Duration) -> Self {
+                self.timeout = Some(timeout);
+                self
+            }
+
+            /// Returns the number of messages waiting for more parts.
+            pub fn in_flight(&self) -> usize {
+                self.partial.len()
+            }
+
+            /// Returns the number of payload bytes buffered for messages
+            /// waiting for more parts.
+            pub fn buffered_len(&self) -> usize {
+                self.buffered
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Reassembler`, returning the inner codec.
+            ///
+            /// Buffered parts are dropped.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        fn invalid(msg: &'static str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+
+        /// Drops the partial message that was updated least recently, other
+        /// than `keep`. Returns `false` if there is none.
+        fn evict_stale(
+            partial: &mut HashMap<u64, Partial>,
+            buffered: &mut usize,
+            keep: u64,
+        ) -> bool {
+            let stale = partial
+                .iter()
+                .filter(|(id, _)| **id != keep)
+                .min_by_key(|(_, partial)| partial.last_update)
+                .map(|(id, _)| *id);
+
+            match stale.and_then(|id| partial.remove(&id)) {
+                Some(stale) => {
+                    *buffered -= stale.len;
+                    true
+                }
+                None => false,
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Option<Item>> for Reassembler<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<Option<Item>, Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<Option<Item>, Self::Error> {
+                if src.len() < HEADER_LEN {
+                    return Err(invalid("frame is too short to carry a part header"));
+                }
+
+                let id = src.get_u64();
+                let part = usize::from(src.get_u16());
+                let total = usize::from(src.get_u16());
+                if part >= total {
+                    return Err(invalid("part index is out of range"));
+                }
+
+                let this = self.project();
+
+                if let Some(timeout) = *this.timeout {
+                    let buffered = &mut *this.buffered;
+                    this.partial.retain(|_, partial| {
+                        let keep = partial.started.elapsed() < timeout;
+                        if !keep {
+                            *buffered -= partial.len;
+                        }
+                        keep
+                    });
+                }
+
+                if src.len() > *this.max_message_len {
+                    if let Some(partial) = this.partial.remove(&id) {
+                        *this.buffered -= partial.len;
+                    }
+                    return Err(invalid("message exceeds the maximum length"));
+                }
+
+                if total == 1 {
+                    return this
+                        .inner
+                        .deserialize_owned(src)
+                        .map(Some)
+                        .map_err(Into::into);
+                }
+
+                *this.clock += 1;
+                if !this.partial.contains_key(&id) && this.partial.len() >= *this.max_in_flight {
+                    evict_stale(this.partial, this.buffered, id);
+                }
+
+                let entry = this.partial.entry(id).or_insert_with(|| Partial {
+                    parts: vec![None; total],
+                    received: 0,
+                    len: 0,
+                    started: Instant::now(),
+                    last_update: 0,
+                });
+                if entry.parts.len() != total {
+                    return Err(invalid("parts disagree on the total number of parts"));
+                }
+
+                entry.last_update = *this.clock;
+                if entry.parts[part].is_some() {
+                    return Ok(None);
+                }
+                if entry.len + src.len() > *this.max_message_len {
+                    *this.buffered -= entry.len;
+                    this.partial.remove(&id);
+                    return Err(invalid("message exceeds the maximum length"));
+                }
+
+                while *this.buffered + src.len() > *this.max_buffered_len {
+                    if !evict_stale(this.partial, this.buffered, id) {
+                        if let Some(partial) = this.partial.remove(&id) {
+                            *this.buffered -= partial.len;
+                        }
+                        return Err(invalid("message exceeds the buffer limit"));
+                    }
+                }
+
+                let entry = this.partial.get_mut(&id).expect("entry was just inserted");
+                *this.buffered += src.len();
+                entry.len += src.len();
+                entry.parts[part] = Some(src);
+                entry.received += 1;
+                if entry.received < total {
+                    return Ok(None);
+                }
+
+                let parts = match this.partial.remove(&id) {
+                    Some(partial) => {
+                        *this.buffered -= partial.len;
+                        partial.parts
+                    }
+                    None => Vec::new(),
+                };
+                let len = parts.iter().flatten().map(BytesMut::len).sum();
+                let mut payload = BytesMut::with_capacity(len);
+                for part in parts.iter().flatten() {
+                    payload.extend_from_slice(part);
+                }
+
+                this.inner
+                    .deserialize_owned(payload)
+                    .map(Some)
+                    .map_err(Into::into)
+            }
+        }
+    }
+
+    mod require_version {
+        use super::*;
+        use std::ops::RangeInclusive;
+
+        /// The `schema_version` field of a message.
+        ///
+        /// Deserializing a message into `SchemaVersion` reads its
+        /// `schema_version` field as a `u16` and skips every other field.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct SchemaVersion(pub u16);
+
+        #[cfg(feature = "serde")]
+        mod de {
+            use super::SchemaVersion;
+            use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+            use std::fmt;
+
+            const FIELD: &str = "schema_version";
+
+            enum Field {
+                Version,
+                Other,
+            }
+
+            impl<'de> Deserialize<'de> for Field {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    struct FieldVisitor;
+
+                    impl<'de> Visitor<'de> for FieldVisitor {
+                        type Value = Field;
+
+                        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                            f.write_str("a field name")
+                        }
+
+                        fn visit_str<E: de::Error>(self, v: &str) -> Result<Field, E> {
+                            Ok(if v == FIELD {
+                                Field::Version
+                            } else {
+                                Field::Other
+                            })
+                        }
+
+                        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Field, E> {
+                            Ok(if v == FIELD.as_bytes() {
+                                Field::Version
+                            } else {
+                                Field::Other
+                            })
+                        }
+                    }
+
+                    deserializer.deserialize_identifier(FieldVisitor)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for SchemaVersion {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    struct SchemaVersionVisitor;
+
+                    impl<'de> Visitor<'de> for SchemaVersionVisitor {
+                        type Value = SchemaVersion;
+
+                        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                            f.write_str("a message with a schema_version field")
+                        }
+
+                        fn visit_map<A: MapAccess<'de>>(
+                            self,
+                            mut map: A,
+                        ) -> Result<SchemaVersion, A::Error> {
+                            let mut version = None;
+
+                            while let Some(field) = map.next_key()? {
+                                match field {
+                                    Field::Version if version.is_some() => {
+                                        return Err(de::Error::duplicate_field(FIELD));
+                                    }
+                                    Field::Version => version = Some(map.next_value()?),
+                                    Field::Other => {
+                                        map.next_value::<IgnoredAny>()?;
+                                    }
+                                }
+                            }
+
+                            version
+                                .map(SchemaVersion)
+                                .ok_or_else(|| de::Error::missing_field(FIELD))
+                        }
+                    }
+
+                    deserializer.deserialize_map(SchemaVersionVisitor)
+                }
+            }
+        }
+
+        /// Rejects frames whose schema version falls outside a range.
+        ///
+        /// Every frame is first decoded by the `peek` codec into a
+        /// [`SchemaVersion`], which only reads the `schema_version` field.
+        /// Frames outside the accepted range fail with
+        /// [`io::ErrorKind::InvalidData`] before the full decode by the inner
+        /// codec runs. The two-pass decode relies on a self-describing format
+        /// such as JSON, CBOR or MessagePack in compatible mode.
+        ///
+        /// Serialization is forwarded to the inner codec unchanged.
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct RequireVersion<Inner, Peek> {
+            #[pin]
+            inner: Inner,
+            #[pin]
+            peek: Peek,
+            versions: RangeInclusive<u16>,
+        }
+
+        impl<Inner, Peek> RequireVersion<Inner, Peek> {
+            /// Creates a new `RequireVersion` accepting frames whose version,
+            /// as read by `peek`, lies in `versions`.
+            pub fn new(inner: Inner, peek: Peek, versions: RangeInclusive<u16>) -> Self {
+                Self {
+                    inner,
+                    peek,
+                    versions,
+                }
+            }
+
+            /// Returns the range of accepted schema versions.
+            pub fn versions(&self) -> &RangeInclusive<u16> {
+                &self.versions
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `RequireVersion`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Peek, Item> Deserializer<Item> for RequireVersion<Inner, Peek>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+            Peek: Deserializer<SchemaVersion>,
+            Peek::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                check_version(this.peek, this.versions, src)?;
+                this.inner.deserialize(src).map_err(Into::into)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                check_version(this.peek, this.versions, &src)?;
+                this.inner.deserialize_owned(src).map_err(Into::into)
+            }
+        }
+
+        fn check_version<Peek>(
+            peek: Pin<&mut Peek>,
+            versions: &RangeInclusive<u16>,
+            src: &BytesMut,
+        ) -> io::Result<()>
+        where
+            Peek: Deserializer<SchemaVersion>,
+            Peek::Error: Into<io::Error>,
+        {
+            let SchemaVersion(version) = peek.deserialize(src).map_err(Into::into)?;
+
+            if !versions.contains(&version) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "unsupported schema version {}, expected {} to {}",
+                        version,
+                        versions.start(),
+                        versions.end()
+                    ),
+                ));
+            }
+
+            Ok(())
+        }
+
+        impl<Inner, Peek, SinkItem> Serializer<SinkItem> for RequireVersion<Inner, Peek>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item).map_err(Into::into)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project()
+                    .inner
+                    .serialize_mut(item, headroom)
+                    .map_err(Into::into)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+
+    mod result {
+        use super::*;
+        use bytes::BufMut;
+
+        const OK: u8 = 0;
+        const ERR: u8 = 1;
+
+        /// Encodes `Result` values as a one-byte tag followed by either arm.
+        ///
+        /// `Ok` values are written by the `OkCodec` after a `0` byte and `Err`
+        /// values by the `ErrCodec` after a `1` byte. This is more compact
+        /// than serde's enum encoding in most formats, e.g. `{"Ok":...}` in
+        /// JSON. Frames with any other tag are rejected with
+        /// [`io::ErrorKind::InvalidData`].
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct ResultCodec<OkCodec, ErrCodec> {
+            #[pin]
+            ok: OkCodec,
+            #[pin]
+            err: ErrCodec,
+        }
+
+        impl<OkCodec, ErrCodec> ResultCodec<OkCodec, ErrCodec> {
+            /// Creates a new `ResultCodec` encoding `Ok` values with `ok` and
+            /// `Err` values with `err`.
+            pub fn new(ok: OkCodec, err: ErrCodec) -> Self {
+                Self { ok, err }
+            }
+
+            /// Returns a reference to the codec for `Ok` values.
+            pub fn ok_ref(&self) -> &OkCodec {
+                &self.ok
+            }
+
+            /// Returns a reference to the codec for `Err` values.
+            pub fn err_ref(&self) -> &ErrCodec {
+                &self.err
+            }
+
+            /// Consumes the `ResultCodec`, returning the codecs for `Ok` and
+            /// `Err` values.
+            pub fn into_inner(self) -> (OkCodec, ErrCodec) {
+                (self.ok, self.err)
+            }
+        }
+
+        impl<OkCodec, ErrCodec, T, E> Deserializer<Result<T, E>> for ResultCodec<OkCodec, ErrCodec>
+        where
+            OkCodec: Deserializer<T>,
+            OkCodec::Error: Into<io::Error>,
+            ErrCodec: Deserializer<E>,
+            ErrCodec::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<Result<T, E>, Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<Result<T, E>, Self::Error> {
+                let this = self.project();
+                let tag = src.first().copied();
+                let payload = src.split_off(src.len().min(1));
+
+                match tag {
+                    Some(OK) => this
+                        .ok
+                        .deserialize_owned(payload)
+                        .map(Ok)
+                        .map_err(Into::into),
+                    Some(ERR) => this
+                        .err
+                        .deserialize_owned(payload)
+                        .map(Err)
+                        .map_err(Into::into),
+                    Some(_) => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid result tag",
+                    )),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "missing result tag",
+                    )),
+                }
+            }
+        }
+
+        impl<OkCodec, ErrCodec, T, E> Serializer<Result<T, E>> for ResultCodec<OkCodec, ErrCodec>
+        where
+            OkCodec: Serializer<T>,
+            OkCodec::Error: Into<io::Error>,
+            ErrCodec: Serializer<E>,
+            ErrCodec::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &Result<T, E>) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let (tag, payload) = match item {
+                    Ok(value) => (OK, this.ok.serialize(value).map_err(Into::into)?),
+                    Err(value) => (ERR, this.err.serialize(value).map_err(Into::into)?),
+                };
+
+                let mut frame = BytesMut::with_capacity(1 + payload.len());
+                frame.put_u8(tag);
+                frame.put_slice(&payload);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    #[cfg(feature = "secure_stack")]
+    mod secure_stack {
+        use super::*;
+        use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+        use chacha20poly1305::aead::{Aead, NewAead};
+        use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+        use educe::Educe;
+
+        const NONCE_LEN: usize = 24;
+
+        /// Compresses, then encrypts, the frames written by the inner codec.
+        ///
+        /// Stacking separate compression and encryption layers makes it easy
+        /// to get the order wrong: ciphertext doesn't compress, so compressing
+        /// after encrypting only costs CPU. This codec bakes in the useful
+        /// order. Each payload is compressed with zstd and then sealed with
+        /// XChaCha20-Poly1305 under a random nonce, which is sent in front of
+        /// the ciphertext. Frames that fail to authenticate or decompress are
+        /// rejected with [`io::ErrorKind::InvalidData`], as are payloads
+        /// decompressing to more than
+        /// [`with_max_decompressed_len`](Self::with_max_decompressed_len)
+        /// bytes, 8 MiB by default.
+        ///
+        /// # Security
+        ///
+        /// Compressing before encrypting makes the ciphertext length depend on
+        /// the content. If an attacker can both inject data into messages that
+        /// also carry a secret and observe frame sizes, they can recover the
+        /// secret one guess at a time, as in the CRIME and BREACH attacks. Do
+        /// not use this codec for such messages; encrypt them without
+        /// compression instead.
+        #[cfg_attr(docsrs, doc(cfg(feature = "secure_stack")))]
+        #[pin_project]
+        #[derive(Educe)]
+        #[educe(Debug(bound))]
+        pub struct SecureStack<Inner> {
+            #[pin]
+            inner: Inner,
+            #[educe(Debug(ignore))]
+            cipher: XChaCha20Poly1305,
+            level: i32,
+            max_decompressed_len: usize,
+        }
+
+        impl<Inner> SecureStack<Inner> {
+            /// Creates a new `SecureStack` encrypting with `key`, using zstd's
+            /// default compression level.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `key` is not 32 bytes long.
+            pub fn new(inner: Inner, key: impl AsRef<[u8]>) -> Self {
+                Self {
+                    inner,
+                    cipher: XChaCha20Poly1305::new(Key::from_slice(key.as_ref())),
+                    level: zstd::DEFAULT_COMPRESSION_LEVEL,
+                    max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+                }
+            }
+
+            /// Sets the zstd compression level, from `1` to `22`.
+            pub fn with_level(mut self, level: i32) -> Self {
+                self.level = level;
+                self
+            }
+
+            /// Sets the largest payload a frame may decompress to.
+            pub fn with_max_decompressed_len(mut self, max: usize) -> Self {
+                self.max_decompressed_len = max;
+                self
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `SecureStack`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for SecureStack<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+
+                if src.len() < NONCE_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame is too short to carry a nonce",
+                    ));
+                }
+
+                let nonce = XNonce::from_slice(&src[..NONCE_LEN]);
+                let compressed = this
+                    .cipher
+                    .decrypt(nonce, &src[NONCE_LEN..])
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let payload = zstd::bulk::decompress(&compressed, *this.max_decompressed_len)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                this.inner
+                    .deserialize_owned(BytesMut::from(&payload[..]))
+                    .map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for SecureStack<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+                let compressed = zstd::bulk::compress(&payload, *this.level)?;
+
+                let mut nonce = XNonce::default();
+                OsRng.fill_bytes(&mut nonce);
+                let ciphertext = this
+                    .cipher
+                    .encrypt(&nonce, &compressed[..])
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                let mut frame = BytesMut::with_capacity(NONCE_LEN + ciphertext.len());
+                frame.extend_from_slice(&nonce);
+                frame.extend_from_slice(&ciphertext);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    mod sequenced {
+        use super::*;
+        use bytes::{Buf, BufMut};
+
+        const SEQ_LEN: usize = 8;
+
+        /// Numbers the frames written by the inner codec.
+        ///
+        /// Every frame starts with a big-endian `u64` sequence number, counting
+        /// up from zero for each frame serialized by this codec and wrapping
+        /// around on overflow. Decoding yields the sequence number along with
+        /// the value, which lets the receiver notice lost or reordered frames,
+        /// e.g. with [`GapDetecting`](crate::GapDetecting). Frames shorter than
+        /// a sequence number are rejected with [`io::ErrorKind::InvalidData`].
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Sequenced<Inner> {
+            #[pin]
+            inner: Inner,
+            next: u64,
+        }
+
+        impl<Inner> Sequenced<Inner> {
+            /// Creates a new `Sequenced` codec numbering frames from zero.
+            pub fn new(inner: Inner) -> Self {
+                Self { inner, next: 0 }
+            }
+
+            /// Returns the sequence number of the next serialized frame.
+            pub fn next_seq(&self) -> u64 {
+                self.next
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Sequenced`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<(u64, Item)> for Sequenced<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<(u64, Item), Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<(u64, Item), Self::Error> {
+                if src.len() < SEQ_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame is too short to carry a sequence number",
+                    ));
+                }
+
+                let seq = src.get_u64();
+                let item = self
+                    .project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)?;
+                Ok((seq, item))
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Sequenced<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.serialize_mut(item, 0).map(BytesMut::freeze)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let this = self.project();
+                let mut frame = this
+                    .inner
+                    .serialize_mut(item, headroom + SEQ_LEN)
+                    .map_err(Into::into)?;
+
+                (&mut frame[headroom..headroom + SEQ_LEN]).put_u64(*this.next);
+                *this.next = this.next.wrapping_add(1);
+                Ok(frame)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod strict_json {
+        use super::*;
+        use serde::de::IgnoredAny;
+        use std::{borrow::Cow, collections::HashMap};
+
+        /// Rejects JSON frames missing any of a set of required top-level
+        /// keys before decoding them with the inner codec.
+        ///
+        /// serde fills fields marked `#[serde(default)]` in silently, so the
+        /// type alone can't tell whether the peer sent them. `StrictJson`
+        /// first scans the frame's top-level object for the required keys,
+        /// skipping over the values, and fails with
+        /// [`io::ErrorKind::InvalidData`] listing every missing key. Frames
+        /// that aren't JSON objects are rejected the same way. Serializing is
+        /// passed through to the inner codec.
+        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct StrictJson<Inner> {
+            #[pin]
+            inner: Inner,
+            required: Vec<String>,
+        }
+
+        impl<Inner> StrictJson<Inner> {
+            /// Creates a new `StrictJson` requiring the given keys.
+            pub fn new<I>(inner: Inner, required: I) -> Self
+            where
+                I: IntoIterator,
+                I::Item: Into<String>,
+            {
+                Self {
+                    inner,
+                    required: required.into_iter().map(Into::into).collect(),
+                }
+            }
+
+            /// Returns the required keys.
+            pub fn required(&self) -> &[String] {
+                &self.required
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `StrictJson`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+
+            fn check(&self, src: &[u8]) -> io::Result<()> {
+                let src = crate::formats::strip_bom(src);
+                let keys: HashMap<Cow<'_, str>, IgnoredAny> = serde_json::from_slice(src)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+                let missing: Vec<_> = self
+                    .required
+                    .iter()
+                    .filter(|key| !keys.contains_key(key.as_str()))
+                    .map(String::as_str)
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("missing required keys: {}", missing.join(", ")),
+                    ));
+                }
+
+                Ok(())
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for StrictJson<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                self.check(src)?;
+                self.project().inner.deserialize(src).map_err(Into::into)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                self.check(&src)?;
+                self.project()
+                    .inner
+                    .deserialize_owned(src)
+                    .map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for StrictJson<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item).map_err(Into::into)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project()
+                    .inner
+                    .serialize_mut(item, headroom)
+                    .map_err(Into::into)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+
+    mod tagged {
+        use super::*;
+        use bytes::BufMut;
+
+        /// A value of one of two types, as encoded by [`Tagged2`].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Variant2<A, B> {
+            /// A value encoded by the first codec.
+            A(A),
+            /// A value encoded by the second codec.
+            B(B),
+        }
+
+        /// Encodes each variant of a two-variant enum with its own codec.
+        ///
+        /// Frames start with a one-byte tag, `0` for [`Variant2::A`] and `1`
+        /// for [`Variant2::B`], followed by the value as written by the
+        /// variant's codec. This skips the enum representation of the
+        /// underlying format. Frames with any other tag are rejected with
+        /// [`io::ErrorKind::InvalidData`].
+        ///
+        /// Protocol enums such as `enum Command { Start(Start), Stop(Stop) }`
+        /// can be sent by converting them to and from [`Variant2`].
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct Tagged2<CodecA, CodecB> {
+            #[pin]
+            a: CodecA,
+            #[pin]
+            b: CodecB,
+        }
+
+        impl<CodecA, CodecB> Tagged2<CodecA, CodecB> {
+            /// Creates a new `Tagged2` encoding [`Variant2::A`] values with
+            /// `a` and [`Variant2::B`] values with `b`.
+            pub fn new(a: CodecA, b: CodecB) -> Self {
+                Self { a, b }
+            }
+
+            /// Consumes the `Tagged2`, returning the codecs of both variants.
+            pub fn into_inner(self) -> (CodecA, CodecB) {
+                (self.a, self.b)
+            }
+        }
+
+        impl<CodecA, CodecB, A, B> Deserializer<Variant2<A, B>> for Tagged2<CodecA, CodecB>
+        where
+            CodecA: Deserializer<A>,
+            CodecA::Error: Into<io::Error>,
+            CodecB: Deserializer<B>,
+            CodecB::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(
+                self: Pin<&mut Self>,
+                src: &BytesMut,
+            ) -> Result<Variant2<A, B>, Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<Variant2<A, B>, Self::Error> {
+                let this = self.project();
+                let tag = src.first().copied();
+                let payload = src.split_off(src.len().min(1));
+
+                match tag {
+                    Some(0) => this
+                        .a
+                        .deserialize_owned(payload)
+                        .map(Variant2::A)
+                        .map_err(Into::into),
+                    Some(1) => this
+                        .b
+                        .deserialize_owned(payload)
+                        .map(Variant2::B)
+                        .map_err(Into::into),
+                    Some(_) => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid variant tag",
+                    )),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "missing variant tag",
+                    )),
+                }
+            }
+        }
+
+        impl<CodecA, CodecB, A, B> Serializer<Variant2<A, B>> for Tagged2<CodecA, CodecB>
+        where
+            CodecA: Serializer<A>,
+            CodecA::Error: Into<io::Error>,
+            CodecB: Serializer<B>,
+            CodecB::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(
+                self: Pin<&mut Self>,
+                item: &Variant2<A, B>,
+            ) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let (tag, payload) = match item {
+                    Variant2::A(value) => (0, this.a.serialize(value).map_err(Into::into)?),
+                    Variant2::B(value) => (1, this.b.serialize(value).map_err(Into::into)?),
+                };
+
+                let mut frame = BytesMut::with_capacity(1 + payload.len());
+                frame.put_u8(tag);
+                frame.put_slice(&payload);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    mod smart_compress {
+        use super::*;
+        use bytes::BufMut;
+        use std::fmt;
+        use zstd::bulk::{Compressor, Decompressor};
+
+        const RAW: u8 = 0;
+        const ZSTD: u8 = 1;
+
+        /// Compresses the frames written by the inner codec with zstd, but
+        /// only when that makes them smaller.
+        ///
+        /// Every frame starts with a flag byte, `0` if the payload follows as
+        /// written by the inner codec and `1` if it is zstd-compressed.
+        /// Payloads that don't shrink are sent uncompressed, so a frame is
+        /// never more than one byte larger than the inner codec's output.
+        ///
+        /// Compressed frames are decompressed before being handed to the
+        /// inner codec, so a small frame can expand to a much larger payload.
+        /// Payloads longer than
+        /// [`with_max_decompressed_len`](Self::with_max_decompressed_len), 8
+        /// MiB by default, are rejected with [`io::ErrorKind::InvalidData`].
+        ///
+        /// Small frames sharing a lot of structure compress much better with
+        /// a dictionary, see [`with_dictionary`](Self::with_dictionary).
+        #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct SmartCompress<Inner> {
+            #[pin]
+            inner: Inner,
+            level: i32,
+            max_decompressed_len: usize,
+            dictionary: Option<Dictionary>,
+            contexts: Option<Contexts>,
+        }
+
+        /// The raw bytes of a zstd dictionary.
+        struct Dictionary(Vec<u8>);
+
+        impl fmt::Debug for Dictionary {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Dictionary")
+                    .field("len", &self.0.len())
+                    .finish()
+            }
+        }
+
+        /// The zstd contexts reused across frames, with the dictionary
+        /// loaded once.
+        struct Contexts {
+            compressor: Compressor<'static>,
+            decompressor: Decompressor<'static>,
+        }
+
+        impl Contexts {
+            /// Returns the contexts in `slot`, creating them on first use.
+            fn get<'a>(
+                slot: &'a mut Option<Self>,
+                level: i32,
+                dictionary: Option<&Dictionary>,
+            ) -> io::Result<&'a mut Self> {
+                let contexts = match (slot.take(), dictionary) {
+                    (Some(contexts), _) => contexts,
+                    (None, Some(Dictionary(raw))) => Self {
+                        compressor: Compressor::with_dictionary(level, raw)?,
+                        decompressor: Decompressor::with_dictionary(raw)?,
+                    },
+                    (None, None) => Self {
+                        compressor: Compressor::new(level)?,
+                        decompressor: Decompressor::new()?,
+                    },
+                };
+
+                Ok(slot.insert(contexts))
+            }
+        }
+
+        impl fmt::Debug for Contexts {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Contexts").finish_non_exhaustive()
+            }
+        }
+
+        impl<Inner> SmartCompress<Inner> {
+            /// Creates a new `SmartCompress` codec using zstd's default
+            /// compression level.
+            pub fn new(inner: Inner) -> Self {
+                Self {
+                    inner,
+                    level: zstd::DEFAULT_COMPRESSION_LEVEL,
+                    max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+                    dictionary: None,
+                    contexts: None,
+                }
+            }
+
+            /// Creates a new `SmartCompress` codec compressing with `level`
+            /// and the zstd dictionary `dictionary`.
+            ///
+            /// The dictionary is typically trained with `zstd --train` on
+            /// sample frames, but any bytes resembling the frames work as a
+            /// raw content dictionary. Both peers must use the same
+            /// dictionary; frames compressed with another one, or none, fail
+            /// to decompress.
+            pub fn with_dictionary(inner: Inner, level: i32, dictionary: Vec<u8>) -> Self {
+                Self {
+                    inner,
+                    level,
+                    max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+                    dictionary: Some(Dictionary(dictionary)),
+                    contexts: None,
+                }
+            }
+
+            /// Sets the zstd compression level, from `1` to `22`.
+            pub fn with_level(mut self, level: i32) -> Self {
+                self.level = level;
+                self.contexts = None;
+                self
+            }
+
+            /// Sets the largest payload a compressed frame may decompress to.
+            pub fn with_max_decompressed_len(mut self, max: usize) -> Self {
+                self.max_decompressed_len = max;
+                self
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `SmartCompress`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for SmartCompress<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let payload = match src.first() {
+                    Some(&RAW) => BytesMut::from(&src[1..]),
+                    Some(&ZSTD) => {
+                        let contexts =
+                            Contexts::get(this.contexts, *this.level, this.dictionary.as_ref())?;
+                        let payload = contexts
+                            .decompressor
+                            .decompress(&src[1..], *this.max_decompressed_len)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                        BytesMut::from(&payload[..])
+                    }
+                    Some(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid compression flag",
+                        ))
+                    }
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "missing compression flag",
+                        ))
+                    }
+                };
+
+                this.inner.deserialize_owned(payload).map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for SmartCompress<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+                let contexts = Contexts::get(this.contexts, *this.level, this.dictionary.as_ref())?;
+                let compressed = contexts.compressor.compress(&payload)?;
+
+                let (flag, body) = if compressed.len() < payload.len() {
+                    (ZSTD, &compressed[..])
+                } else {
+                    (RAW, &payload[..])
+                };
+
+                let mut frame = BytesMut::with_capacity(1 + body.len());
+                frame.put_u8(flag);
+                frame.put_slice(body);
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    #[cfg(feature = "hmac")]
+    mod signed {
+        use super::*;
+        use bytes::BufMut;
+        use educe::Educe;
+        use hmac_crate::{Hmac, Mac};
+        use sha2::Sha256;
+
+        type HmacSha256 = Hmac<Sha256>;
+
+        const TAG_LEN: usize = 32;
+
+        /// Authenticates the frames of an inner codec with an HMAC-SHA256 tag.
+        ///
+        /// On serialize the tag computed over the inner codec's output is
+        /// appended to the frame. On deserialize the trailing tag is verified
+        /// in constant time and stripped before the payload is handed to the
+        /// inner codec; frames that are too short or carry a bad tag are
+        /// rejected with [`io::ErrorKind::InvalidData`].
+        ///
+        /// This provides integrity and authenticity only, the payload is sent
+        /// in the clear.
+        #[cfg_attr(docsrs, doc(cfg(feature = "hmac")))]
+        #[pin_project]
+        #[derive(Educe)]
+        #[educe(Debug(bound))]
+        pub struct Signed<Inner> {
+            #[pin]
+            inner: Inner,
+            #[educe(Debug(ignore))]
+            mac: HmacSha256,
+        }
+
+        impl<Inner> Signed<Inner> {
+            /// Creates a new `Signed` codec keyed with `key`.
+            pub fn new(inner: Inner, key: impl AsRef<[u8]>) -> Self {
+                Self {
+                    inner,
+                    mac: HmacSha256::new_from_slice(key.as_ref())
+                        .expect("HMAC accepts keys of any length"),
+                }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Signed`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner, Item> Deserializer<Item> for Signed<Inner>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                self.deserialize_owned(src.clone())
+            }
+
+            fn deserialize_owned(
+                self: Pin<&mut Self>,
+                mut src: BytesMut,
+            ) -> Result<Item, Self::Error> {
+                let this = self.project();
+
+                if src.len() < TAG_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "frame is too short to carry an HMAC tag",
+                    ));
+                }
+
+                let tag = src.split_off(src.len() - TAG_LEN);
+                let mut mac = this.mac.clone();
+                mac.update(&src);
+                mac.verify_slice(&tag)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid HMAC tag"))?;
+
+                this.inner.deserialize_owned(src).map_err(Into::into)
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for Signed<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let payload = this.inner.serialize(item).map_err(Into::into)?;
+
+                let mut mac = this.mac.clone();
+                mac.update(&payload);
+
+                let mut frame = BytesMut::with_capacity(payload.len() + TAG_LEN);
+                frame.put_slice(&payload);
+                frame.put_slice(&mac.finalize().into_bytes());
+                Ok(frame.freeze())
+            }
+        }
+    }
+
+    #[cfg(feature = "timed")]
+    mod timed {
+        use super::*;
+        use hdrhistogram::Histogram;
+        use std::time::Instant;
+
+        /// Records how long the inner codec takes to serialize and
+        /// deserialize each frame.
+        ///
+        /// Durations are measured with [`Instant::now`] around every call,
+        /// failed ones included, and recorded in nanoseconds into one
+        /// [`Histogram`] per direction. The histograms keep three significant
+        /// digits and grow as needed. Errors from the inner codec are
+        /// returned unchanged.
+        #[cfg_attr(docsrs, doc(cfg(feature = "timed")))]
+        #[pin_project]
+        #[derive(Debug)]
+        pub struct TimedCodec<Inner> {
+            #[pin]
+            inner: Inner,
+            serialize: Histogram<u64>,
+            deserialize: Histogram<u64>,
+        }
+
+        impl<Inner> TimedCodec<Inner> {
+            /// Creates a new `TimedCodec` wrapping `inner`.
+            pub fn new(inner: Inner) -> Self {
+                Self {
+                    inner,
+                    serialize: new_histogram(),
+                    deserialize: new_histogram(),
+                }
+            }
+
+            /// Returns the serialize latencies recorded so far, in
+            /// nanoseconds.
+            pub fn serialize_histogram(&self) -> &Histogram<u64> {
+                &self.serialize
+            }
+
+            /// Returns the deserialize latencies recorded so far, in
+            /// nanoseconds.
+            pub fn deserialize_histogram(&self) -> &Histogram<u64> {
+                &self.deserialize
+            }
+
+            /// Clears both histograms, e.g. at the start of a reporting
+            /// interval.
+            pub fn reset(&mut self) {
+                self.serialize.reset();
+                self.deserialize.reset();
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `TimedCodec`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        fn new_histogram() -> Histogram<u64> {
+            Histogram::new(3).expect("3 significant digits are supported")
+        }
+
+        fn record(histogram: &mut Histogram<u64>, start: Instant) {
+            let nanos = start.elapsed().as_nanos();
+            histogram.saturating_record(nanos.min(u128::from(u64::MAX)) as u64);
+        }
+
+        impl<Inner, Item> Deserializer<Item> for TimedCodec<Inner>
+        where
+            Inner: Deserializer<Item>,
+        {
+            type Error = Inner::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let start = Instant::now();
+                let res = this.inner.deserialize(src);
+                record(this.deserialize, start);
+                res
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let start = Instant::now();
+                let res = this.inner.deserialize_owned(src);
+                record(this.deserialize, start);
+                res
+            }
+        }
+
+        impl<Inner, SinkItem> Serializer<SinkItem> for TimedCodec<Inner>
+        where
+            Inner: Serializer<SinkItem>,
+        {
+            type Error = Inner::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                let this = self.project();
+                let start = Instant::now();
+                let res = this.inner.serialize(item);
+                record(this.serialize, start);
+                res
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                let this = self.project();
+                let start = Instant::now();
+                let res = this.inner.serialize_mut(item, headroom);
+                record(this.serialize, start);
+                res
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error>
+            where
+                Self::Error: From<io::Error>,
+            {
+                let this = self.project();
+                let start = Instant::now();
+                let res = this.inner.serialize_to_buf(item, buf);
+                record(this.serialize, start);
+                res
+            }
+        }
+    }
+
+    mod validated {
+        use super::*;
+        use std::{borrow::Cow, error::Error, fmt};
+
+        /// The reason a decoded value was rejected by [`Validated`].
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct ValidationError {
+            message: Cow<'static, str>,
+        }
+
+        impl ValidationError {
+            /// Creates a new `ValidationError` with the given message.
+            pub fn new(message: impl Into<Cow<'static, str>>) -> Self {
+                Self {
+                    message: message.into(),
+                }
+            }
+
+            /// Returns the message describing the failure.
+            pub fn message(&self) -> &str {
+                &self.message
+            }
+        }
+
+        impl fmt::Display for ValidationError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "validation failed: {}", self.message)
+            }
+        }
+
+        impl Error for ValidationError {}
+
+        /// Checks every decoded value with a predicate before yielding it.
+        ///
+        /// The predicate runs after the inner codec decoded a frame
+        /// successfully. Values it rejects are dropped and reported as
+        /// [`io::ErrorKind::InvalidData`] wrapping the [`ValidationError`], so
+        /// invalid input never reaches the rest of the application. Errors
+        /// from the inner codec are converted into [`io::Error`]. Serializing
+        /// is passed through to the inner codec without validation.
+        #[pin_project]
+        pub struct Validated<Inner, F> {
+            #[pin]
+            inner: Inner,
+            validate: F,
+        }
+
+        impl<Inner, F> Validated<Inner, F> {
+            /// Creates a new `Validated` checking values decoded by `inner`
+            /// with `validate`.
+            pub fn new(inner: Inner, validate: F) -> Self {
+                Self { inner, validate }
+            }
+
+            /// Returns a reference to the inner codec.
+            pub fn get_ref(&self) -> &Inner {
+                &self.inner
+            }
+
+            /// Returns a mutable reference to the inner codec.
+            pub fn get_mut(&mut self) -> &mut Inner {
+                &mut self.inner
+            }
+
+            /// Consumes the `Validated`, returning the inner codec.
+            pub fn into_inner(self) -> Inner {
+                self.inner
+            }
+        }
+
+        impl<Inner: fmt::Debug, F> fmt::Debug for Validated<Inner, F> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Validated")
+                    .field("inner", &self.inner)
+                    .finish_non_exhaustive()
+            }
+        }
+
+        fn check<T, F>(validate: &F, item: T) -> io::Result<T>
+        where
+            F: Fn(&T) -> Result<(), ValidationError>,
+        {
+            validate(&item).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Ok(item)
+        }
+
+        impl<Inner, F, Item> Deserializer<Item> for Validated<Inner, F>
+        where
+            Inner: Deserializer<Item>,
+            Inner::Error: Into<io::Error>,
+            F: Fn(&Item) -> Result<(), ValidationError>,
+        {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let item = this.inner.deserialize(src).map_err(Into::into)?;
+                check(this.validate, item)
+            }
+
+            fn deserialize_owned(self: Pin<&mut Self>, src: BytesMut) -> Result<Item, Self::Error> {
+                let this = self.project();
+                let item = this.inner.deserialize_owned(src).map_err(Into::into)?;
+                check(this.validate, item)
+            }
+        }
+
+        impl<Inner, F, SinkItem> Serializer<SinkItem> for Validated<Inner, F>
+        where
+            Inner: Serializer<SinkItem>,
+            Inner::Error: Into<io::Error>,
+        {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
+                self.project().inner.serialize(item).map_err(Into::into)
+            }
+
+            fn serialize_mut(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                headroom: usize,
+            ) -> Result<BytesMut, Self::Error> {
+                self.project()
+                    .inner
+                    .serialize_mut(item, headroom)
+                    .map_err(Into::into)
+            }
+
+            fn serialize_to_buf<B: BufMut>(
+                self: Pin<&mut Self>,
+                item: &SinkItem,
+                buf: &mut B,
+            ) -> Result<(), Self::Error> {
+                let frame = self.serialize_mut(item, 0)?;
+                put_frame(buf, &frame)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Deserializer, Serializer};
+    use bytes::{Bytes, BytesMut};
+    use futures_sink::Sink;
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// A sink that buffers frames until it is explicitly flushed.
+    #[derive(Debug, Default)]
+    pub(crate) struct FlushSink {
+        pub(crate) buffered: Vec<Bytes>,
+        pub(crate) written: Vec<Bytes>,
+        pub(crate) flushes: usize,
+        pub(crate) stall_flush: bool,
+    }
+
+    impl Sink<Bytes> for FlushSink {
+        type Error = io::Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), io::Error> {
+            self.buffered.push(item);
+            Ok(())
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<(), io::Error>> {
+            if self.stall_flush {
+                return Poll::Pending;
+            }
+
+            let this = &mut *self;
+            this.flushes += 1;
+            this.written.append(&mut this.buffered);
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+            self.poll_flush(cx)
+        }
+    }
+
+    /// A codec passing frames through unchanged.
+    #[derive(Debug, Default)]
+    pub(crate) struct Passthrough;
+
+    impl Serializer<Bytes> for Passthrough {
+        type Error = io::Error;
+
+        fn serialize(self: Pin<&mut Self>, item: &Bytes) -> Result<Bytes, io::Error> {
+            Ok(item.clone())
+        }
+    }
+
+    impl Deserializer<Bytes> for Passthrough {
+        type Error = io::Error;
+
+        fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Bytes, io::Error> {
+            Ok(src.clone().freeze())
+        }
+    }
+
+    /// Builds a [`Reassembler`](crate::combinators::Reassembler) frame holding
+    /// part `index` of the `total` making up message `id`.
+    pub(crate) fn part(id: u64, index: u16, total: u16, payload: &[u8]) -> BytesMut {
+        use bytes::BufMut;
+
+        let mut frame = BytesMut::new();
+        frame.put_u64(id);
+        frame.put_u16(index);
+        frame.put_u16(total);
+        frame.put_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn ping_round_trip() {
+        use crate::Ping;
+
+        let buf = Pin::new(&mut Ping).serialize(&()).unwrap();
+        assert!(buf.is_empty());
+
+        Pin::new(&mut Ping)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+
+        let err = Pin::new(&mut Ping)
+            .deserialize(&BytesMut::from(&b"null"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn framed_has_pending() {
+        use crate::SymmetricallyFramed;
+        use futures::{executor::block_on, SinkExt};
+
+        let mut framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        assert!(!framed.has_pending());
+
+        block_on(framed.feed(Bytes::from_static(b"frame"))).unwrap();
+        assert!(framed.has_pending());
+        assert!(framed.get_ref().written.is_empty());
+
+        block_on(framed.flush()).unwrap();
+        assert!(!framed.has_pending());
+        assert_eq!(framed.get_ref().written.len(), 1);
+    }
+
+    #[test]
+    fn framed_replace_transport() {
+        use crate::SymmetricallyFramed;
+        use futures::{SinkExt, TryStreamExt};
+        use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (first, first_peer) = tokio::io::duplex(1024);
+            let (second, second_peer) = tokio::io::duplex(1024);
+            let mut first_peer = Framed::new(first_peer, LengthDelimitedCodec::new());
+            let mut second_peer = Framed::new(second_peer, LengthDelimitedCodec::new());
+
+            let mut framed = SymmetricallyFramed::new(
+                Framed::new(first, LengthDelimitedCodec::new()),
+                Passthrough,
+            );
+
+            framed.send(Bytes::from_static(b"one")).await.unwrap();
+            assert_eq!(&first_peer.try_next().await.unwrap().unwrap()[..], b"one");
+
+            let old = framed.replace_transport(Framed::new(second, LengthDelimitedCodec::new()));
+            drop(old);
+
+            framed.send(Bytes::from_static(b"two")).await.unwrap();
+            assert_eq!(&second_peer.try_next().await.unwrap().unwrap()[..], b"two");
+            assert!(first_peer.try_next().await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn framed_flush_skips_idle_transport() {
+        use crate::SymmetricallyFramed;
+        use futures::{executor::block_on, SinkExt};
+
+        let mut framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        block_on(framed.flush()).unwrap();
+        assert_eq!(framed.get_ref().flushes, 0);
+
+        block_on(framed.send(Bytes::from_static(b"frame"))).unwrap();
+        assert_eq!(framed.get_ref().flushes, 1);
+
+        block_on(framed.flush()).unwrap();
+        block_on(framed.flush()).unwrap();
+        assert_eq!(framed.get_ref().flushes, 1);
+
+        block_on(futures::future::poll_fn(|cx| {
+            Pin::new(&mut framed).poll_transport_flush(cx)
+        }))
+        .unwrap();
+        assert_eq!(framed.get_ref().flushes, 2);
+    }
+
+    #[test]
+    fn framed_poll_ready_unpin() {
+        use crate::{MaxInFlight, SymmetricallyFramed};
+        use futures::task::noop_waker_ref;
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let sink = FlushSink {
+            stall_flush: true,
+            ..FlushSink::default()
+        };
+        let mut framed = SymmetricallyFramed::new(MaxInFlight::new(sink, 0), Passthrough);
+
+        assert!(matches!(
+            framed.poll_ready_unpin(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        Pin::new(&mut framed)
+            .start_send(Bytes::from_static(b"frame"))
+            .unwrap();
+        assert!(framed.poll_ready_unpin(&mut cx).is_pending());
+
+        framed.get_mut().get_mut().stall_flush = false;
+        assert!(matches!(
+            framed.poll_ready_unpin(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+    }
+
+    #[tokio::test]
+    async fn framed_error_on_unexpected_close() {
+        use crate::{CheckedFramed, SymmetricallyFramed};
+        use futures::{stream, TryStreamExt};
+
+        let truncated = || {
+            let frames: Vec<io::Result<BytesMut>> = vec![
+                Ok(BytesMut::from(&b"part 1"[..])),
+                Ok(BytesMut::from(&b"part 2"[..])),
+            ];
+            stream::iter(frames)
+        };
+
+        // A close is clean unless the flag is enabled and more is expected.
+        let mut framed = CheckedFramed::new(SymmetricallyFramed::new(truncated(), Passthrough));
+        framed.set_expecting_more(true);
+        assert_eq!(framed.try_next().await.unwrap().unwrap(), &b"part 1"[..]);
+        assert_eq!(framed.try_next().await.unwrap().unwrap(), &b"part 2"[..]);
+        assert!(framed.try_next().await.unwrap().is_none());
+
+        let mut framed = CheckedFramed::new(SymmetricallyFramed::new(truncated(), Passthrough));
+        framed.error_on_unexpected_close(true);
+        assert_eq!(framed.try_next().await.unwrap().unwrap(), &b"part 1"[..]);
+        assert_eq!(framed.try_next().await.unwrap().unwrap(), &b"part 2"[..]);
+        assert!(framed.try_next().await.unwrap().is_none());
+
+        let mut framed = CheckedFramed::new(SymmetricallyFramed::new(truncated(), Passthrough));
+        framed.error_on_unexpected_close(true);
+        framed.try_next().await.unwrap();
+        framed.set_expecting_more(true);
+        framed.try_next().await.unwrap();
+        let err = framed.try_next().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(!framed.is_expecting_more());
+        assert!(framed.try_next().await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "json-ordered")]
+    #[test]
+    fn json_ordered_preserves_key_order() {
+        use crate::formats::SymmetricalJson;
+        use serde_json::{Map, Value};
+
+        let frame = BytesMut::from(&br#"{"zeta":1,"alpha":{"y":true,"b":null},"mid":[3,2,1]}"#[..]);
+
+        let mut codec = SymmetricalJson::<Map<String, Value>>::default();
+        let map = Pin::new(&mut codec).deserialize(&frame).unwrap();
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["zeta", "alpha", "mid"]);
+        let buf = Pin::new(&mut codec).serialize(&map).unwrap();
+        assert_eq!(buf, frame);
+
+        let mut codec = SymmetricalJson::<Value>::default();
+        let value = Pin::new(&mut codec).deserialize(&frame).unwrap();
+        let buf = Pin::new(&mut codec).serialize(&value).unwrap();
+        assert_eq!(buf, frame);
+    }
+
+    #[cfg(feature = "kx")]
+    #[tokio::test]
+    async fn key_exchange_derives_same_secret() {
+        use crate::key_exchange::handshake;
+
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let (a_key, b_key) = tokio::join!(handshake(&mut a, true), handshake(&mut b, false));
+        let (a_key, b_key) = (a_key.unwrap(), b_key.unwrap());
+        assert_eq!(a_key.len(), 32);
+        assert_eq!(a_key, b_key);
+
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let (other, _) = tokio::join!(handshake(&mut a, true), handshake(&mut b, false));
+        assert_ne!(other.unwrap(), a_key);
+
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let low_order = async {
+            let mut peer = [0; 32];
+            tokio::io::AsyncReadExt::read_exact(&mut b, &mut peer).await?;
+            tokio::io::AsyncWriteExt::write_all(&mut b, &[0; 32]).await
+        };
+        let (res, _) = tokio::join!(handshake(&mut a, true), low_order);
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "json-raw")]
+    #[test]
+    fn json_raw_value_routing() {
+        use crate::formats::{get_field, Json};
+        use serde_json::value::RawValue;
+
+        let body: Vec<_> = (0..1000)
+            .map(|i| format!(r#""k{}":[{},"x"]"#, i, i))
+            .collect();
+        let frame = format!(
+            r#"{{"payload":{{{}}},"route":"orders/eu","ttl":3}}"#,
+            body.join(",")
+        );
+        let frame = BytesMut::from(frame.as_bytes());
+
+        let mut codec = Json::<Box<RawValue>, Box<RawValue>>::default();
+        let raw = codec.deserialize_raw(&frame).unwrap();
+        let route = get_field(&raw, "route").unwrap().unwrap();
+        assert_eq!(route.get(), r#""orders/eu""#);
+        let route: String = serde_json::from_str(route.get()).unwrap();
+        assert_eq!(route, "orders/eu");
+        assert!(get_field(&raw, "missing").unwrap().is_none());
+        assert!(get_field(&RawValue::from_string("[1]".into()).unwrap(), "route").is_err());
+
+        let forwarded = Pin::new(&mut codec).serialize(&raw).unwrap();
+        assert_eq!(forwarded, frame);
+        let decoded = Pin::new(&mut codec).deserialize(&frame).unwrap();
+        assert_eq!(decoded.get().as_bytes(), &frame[..]);
+    }
+
+    #[tokio::test]
+    async fn coalesced_write_merges_frames() {
+        use crate::{CoalescedWrite, SymmetricallyFramed};
+        use futures::SinkExt;
+
+        let frames = [&b"\x00\x02hi"[..], b"\x00\x03abc", b"\x00\x01z"];
+
+        let mut framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        for frame in frames.iter() {
+            framed.feed(Bytes::from_static(frame)).await.unwrap();
+        }
+        framed.flush().await.unwrap();
+        assert_eq!(framed.get_ref().written.len(), 3);
+
+        let sink = CoalescedWrite::new(FlushSink::default());
+        let mut framed = SymmetricallyFramed::new(sink, Passthrough);
+        for frame in frames.iter() {
+            framed.feed(Bytes::from_static(frame)).await.unwrap();
+        }
+        assert_eq!(framed.get_ref().buffered_bytes(), 12);
+        assert!(framed.get_ref().get_ref().buffered.is_empty());
+
+        framed.flush().await.unwrap();
+        let sink = framed.get_ref();
+        assert_eq!(sink.buffered_bytes(), 0);
+        assert_eq!(
+            sink.get_ref().written,
+            [&b"\x00\x02hi\x00\x03abc\x00\x01z"[..]]
+        );
+        assert_eq!(sink.get_ref().flushes, 1);
+
+        framed.send(Bytes::from_static(b"\x00\x01!")).await.unwrap();
+        assert_eq!(framed.get_ref().get_ref().written.len(), 2);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn strict_json_requires_keys() {
+        use crate::{combinators::StrictJson, formats::SymmetricalJson};
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            name: String,
+            #[serde(default)]
+            retries: u32,
+            #[serde(default)]
+            verbose: bool,
+        }
+
+        let mut lenient = SymmetricalJson::<Config>::default();
+        let mut strict = StrictJson::new(SymmetricalJson::<Config>::default(), ["name", "retries"]);
+
+        let full = BytesMut::from(&br#"{"name":"a","retries":3}"#[..]);
+        let config = Pin::new(&mut strict).deserialize(&full).unwrap();
+        assert_eq!(config.retries, 3);
+
+        let partial = BytesMut::from(&br#"{"name":"a","verbose":true}"#[..]);
+        assert_eq!(
+            Pin::new(&mut lenient)
+                .deserialize(&partial)
+                .unwrap()
+                .retries,
+            0
+        );
+        let err = Pin::new(&mut strict).deserialize(&partial).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "missing required keys: retries");
+
+        let err = Pin::new(&mut strict)
+            .deserialize_owned(BytesMut::from(&b"{}"[..]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "missing required keys: name, retries");
+
+        let res = Pin::new(&mut strict).deserialize(&BytesMut::from(&b"[1]"[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "timed")]
+    #[test]
+    fn timed_codec_records_latencies() {
+        use crate::combinators::TimedCodec;
+
+        let mut codec = TimedCodec::new(Passthrough);
+        for i in 0..5u8 {
+            let buf = Pin::new(&mut codec)
+                .serialize(&Bytes::from(vec![i; 16]))
+                .unwrap();
+            if i % 2 == 0 {
+                let _: Bytes = Pin::new(&mut codec)
+                    .deserialize(&BytesMut::from(&buf[..]))
+                    .unwrap();
+            }
+        }
+        assert_eq!(codec.serialize_histogram().len(), 5);
+        assert_eq!(codec.deserialize_histogram().len(), 3);
+
+        codec.reset();
+        assert!(codec.serialize_histogram().is_empty());
+        assert!(codec.deserialize_histogram().is_empty());
+    }
+
+    #[cfg(feature = "bincode2")]
+    #[test]
+    fn bincode2_round_trip() {
+        use crate::formats::{Bincode2, SymmetricalBincode2};
+        use serde::{Deserialize, Serialize};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Message {
+            id: u64,
+            name: String,
+            tags: BTreeMap<String, Vec<i32>>,
+            reply: Option<Box<Message>>,
+        }
+
+        let message = Message {
+            id: 300,
+            name: "hello".into(),
+            tags: vec![("a".into(), vec![-1, 2])].into_iter().collect(),
+            reply: Some(Box::new(Message {
+                id: 1,
+                name: String::new(),
+                tags: BTreeMap::new(),
+                reply: None,
+            })),
+        };
+
+        let mut codec = SymmetricalBincode2::<Message>::default();
+        let buf = Pin::new(&mut codec).serialize(&message).unwrap();
+        let decoded = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, message);
+
+        let mut fixed = Bincode2::<u32, u32, _>::from(
+            bincode2_crate::config::standard()
+                .with_big_endian()
+                .with_fixed_int_encoding(),
+        );
+        let buf = Pin::new(&mut fixed).serialize(&5).unwrap();
+        assert_eq!(buf, &b"\x00\x00\x00\x05"[..]);
+        assert_eq!(
+            Pin::new(&mut fixed)
+                .deserialize(&BytesMut::from(&buf[..]))
+                .unwrap(),
+            5
+        );
+
+        let mut trailing = BytesMut::from(&buf[..]);
+        trailing.extend_from_slice(b"!");
+        let res = Pin::new(&mut fixed).deserialize(&trailing);
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reassembler_parts() {
+        use crate::combinators::Reassembler;
+
+        let mut codec = Reassembler::new(Passthrough, 2);
+        let mut feed = |frame: BytesMut| -> io::Result<Option<Bytes>> {
+            Pin::new(&mut codec).deserialize(&frame)
+        };
+
+        // In order, and a message fitting in a single part.
+        assert_eq!(feed(part(1, 0, 2, b"hel")).unwrap(), None);
+        assert_eq!(feed(part(1, 1, 2, b"lo")).unwrap().unwrap(), &b"hello"[..]);
+        assert_eq!(feed(part(9, 0, 1, b"one")).unwrap().unwrap(), &b"one"[..]);
+
+        // Out of order and interleaved with another message.
+        assert_eq!(feed(part(2, 2, 3, b"c")).unwrap(), None);
+        assert_eq!(feed(part(3, 1, 2, b"y")).unwrap(), None);
+        assert_eq!(feed(part(2, 0, 3, b"a")).unwrap(), None);
+        assert_eq!(feed(part(3, 0, 2, b"x")).unwrap().unwrap(), &b"xy"[..]);
+        assert_eq!(feed(part(2, 1, 3, b"b")).unwrap().unwrap(), &b"abc"[..]);
+
+        // Duplicates are ignored.
+        assert_eq!(feed(part(4, 0, 2, b"1")).unwrap(), None);
+        assert_eq!(feed(part(4, 0, 2, b"1")).unwrap(), None);
+        assert_eq!(feed(part(4, 1, 2, b"2")).unwrap().unwrap(), &b"12"[..]);
+
+        let err = feed(part(5, 2, 2, b"")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(feed(part(5, 0, 2, b"")).unwrap(), None);
+        let err = feed(part(5, 1, 3, b"")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(feed(BytesMut::from(&b"short"[..])).is_err());
+        assert_eq!(codec.in_flight(), 1);
+    }
+
+    #[test]
+    fn reassembler_evicts_stale_messages() {
+        use crate::combinators::Reassembler;
+
+        let mut codec = Reassembler::new(Passthrough, 2);
+        let mut feed = |frame: BytesMut| -> io::Result<Option<Bytes>> {
+            Pin::new(&mut codec).deserialize_owned(frame)
+        };
+
+        assert_eq!(feed(part(1, 0, 2, b"a")).unwrap(), None);
+        assert_eq!(feed(part(2, 0, 2, b"b")).unwrap(), None);
+        // Starting a third message evicts message 1, the stalest.
+        assert_eq!(feed(part(3, 0, 2, b"c")).unwrap(), None);
+        assert_eq!(feed(part(1, 1, 2, b"A")).unwrap(), None);
+        assert_eq!(feed(part(3, 1, 2, b"C")).unwrap().unwrap(), &b"cC"[..]);
+        assert_eq!(codec.in_flight(), 1);
+    }
+
+    #[test]
+    fn reassembler_limits_buffered_bytes() {
+        use crate::combinators::Reassembler;
+        use std::time::Duration;
+
+        fn feed(
+            codec: &mut Reassembler<Passthrough>,
+            frame: BytesMut,
+        ) -> io::Result<Option<Bytes>> {
+            Pin::new(codec).deserialize_owned(frame)
+        }
+
+        let mut codec = Reassembler::new(Passthrough, 8)
+            .with_max_message_len(6)
+            .with_max_buffered_len(8);
+
+        // A message growing past its limit is dropped.
+        assert_eq!(feed(&mut codec, part(1, 0, 3, b"abcd")).unwrap(), None);
+        let err = feed(&mut codec, part(1, 1, 3, b"efg")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(feed(&mut codec, part(1, 2, 3, b"h")).unwrap(), None);
+        assert!(feed(&mut codec, part(2, 0, 3, b"toolong")).is_err());
+
+        // Going over the total evicts the stalest message.
+        assert_eq!(feed(&mut codec, part(3, 0, 3, b"1234")).unwrap(), None);
+        assert_eq!(feed(&mut codec, part(4, 0, 3, b"5678")).unwrap(), None);
+        assert_eq!(feed(&mut codec, part(5, 0, 3, b"9")).unwrap(), None);
+        assert_eq!(codec.buffered_len(), 5);
+        assert_eq!(codec.in_flight(), 2);
+        assert_eq!(feed(&mut codec, part(4, 1, 3, b"x")).unwrap(), None);
+        assert_eq!(
+            feed(&mut codec, part(4, 2, 3, b"y")).unwrap().unwrap(),
+            &b"5678xy"[..]
+        );
+        assert_eq!(codec.buffered_len(), 1);
+
+        // Parts of expired messages are dropped.
+        let mut codec = Reassembler::new(Passthrough, 8).with_timeout(Duration::ZERO);
+        assert_eq!(feed(&mut codec, part(1, 0, 3, b"a")).unwrap(), None);
+        assert_eq!(feed(&mut codec, part(1, 1, 3, b"b")).unwrap(), None);
+        assert_eq!(feed(&mut codec, part(1, 2, 3, b"c")).unwrap(), None);
+        assert_eq!(codec.in_flight(), 1);
+        assert_eq!(codec.buffered_len(), 1);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_canonical_encoding() {
+        use crate::formats::{Cbor, SymmetricalCbor};
+        use serde::{Deserialize, Serialize};
+        use std::collections::BTreeMap;
+
+        struct Entries(Vec<(&'static str, u64)>);
+
+        impl Serialize for Entries {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+            }
+        }
+
+        let entries = [("b", 1), ("a", 2), ("ccc", 70000), ("dd", 0)];
+        let mut reversed = entries.to_vec();
+        reversed.reverse();
+
+        let mut codec = Cbor::<BTreeMap<String, u64>, Entries>::canonical();
+        let forward = Pin::new(&mut codec)
+            .serialize(&Entries(entries.to_vec()))
+            .unwrap();
+        let backward = Pin::new(&mut codec).serialize(&Entries(reversed)).unwrap();
+        assert_eq!(forward, backward);
+        assert_eq!(
+            forward,
+            &b"\xa4\x61a\x02\x61b\x01\x62dd\x00\x63ccc\x1a\x00\x01\x11\x70"[..]
+        );
+
+        let decoded = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&forward[..]))
+            .unwrap();
+        let expected: BTreeMap<_, _> = entries.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        assert_eq!(decoded, expected);
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Message {
+            zeta: Vec<f64>,
+            alpha: Option<i64>,
+        }
+
+        let message = Message {
+            zeta: vec![1.5, 0.1],
+            alpha: Some(-500),
+        };
+        let mut codec = SymmetricalCbor::<Message>::canonical();
+        let buf = Pin::new(&mut codec).serialize(&message).unwrap();
+        // Shorter keys sort first, as their encoded length is part of the key.
+        assert_eq!(&buf[..8], b"\xa2\x64zeta\x82\xf9");
+        let decoded = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test]
+    async fn framed_shutdown_with_timeout() {
+        use crate::SymmetricallyFramed;
+        use futures::SinkExt;
+        use std::time::Duration;
+
+        let mut framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        framed.feed(Bytes::from_static(b"queued")).await.unwrap();
+        let sink = FlushSink {
+            stall_flush: true,
+            ..FlushSink::default()
+        };
+        let stalled = SymmetricallyFramed::<_, Bytes, _>::new(sink, Passthrough);
+
+        let err = stalled
+            .shutdown_with_timeout(Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        framed
+            .shutdown_with_timeout(Duration::from_secs(5))
+            .await
+            .unwrap();
+    }
+
+    #[cfg(all(feature = "cbor", feature = "messagepack"))]
+    #[test]
+    fn either_dispatches_to_active_codec() {
+        use crate::combinators::Either;
+        use crate::formats::{SymmetricalCbor, SymmetricalMessagePack};
+
+        type Codec = Either<SymmetricalCbor<Vec<u16>>, SymmetricalMessagePack<Vec<u16>>>;
+
+        let value = vec![1, 300, 65535];
+        let mut left: Codec = Either::Left(SymmetricalCbor::default());
+        let mut right: Codec = Either::Right(SymmetricalMessagePack::default());
+        assert!(left.is_left() && right.is_right());
+
+        let left_buf = Pin::new(&mut left).serialize(&value).unwrap();
+        let right_buf = Pin::new(&mut right).serialize(&value).unwrap();
+        assert_eq!(left_buf, &b"\x83\x01\x19\x01\x2c\x19\xff\xff"[..]);
+        assert_eq!(right_buf, &b"\x93\x01\xcd\x01\x2c\xcd\xff\xff"[..]);
+
+        let decoded = Pin::new(&mut left)
+            .deserialize(&BytesMut::from(&left_buf[..]))
+            .unwrap();
+        assert_eq!(decoded, value);
+        let decoded = Pin::new(&mut right)
+            .deserialize_owned(BytesMut::from(&right_buf[..]))
+            .unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn map_err_converts_codec_errors() {
+        use crate::{combinators::MapErr, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        #[derive(Debug)]
+        enum AppError {
+            Codec(io::ErrorKind),
+            Transport,
+        }
+
+        // Needed by `Framed` for the errors it raises itself.
+        impl From<io::Error> for AppError {
+            fn from(_: io::Error) -> Self {
+                AppError::Transport
+            }
+        }
+
+        struct Failing;
+
+        impl Deserializer<Bytes> for Failing {
+            type Error = io::Error;
+
+            fn deserialize(self: Pin<&mut Self>, _: &BytesMut) -> io::Result<Bytes> {
+                Err(io::ErrorKind::InvalidData.into())
+            }
+        }
+
+        impl Serializer<Bytes> for Failing {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, _: &Bytes) -> io::Result<Bytes> {
+                Err(io::ErrorKind::InvalidInput.into())
+            }
+        }
+
+        let mut codec = MapErr::new(Failing, |err: io::Error| AppError::Codec(err.kind()));
+        let err = Pin::new(&mut codec).serialize(&Bytes::new()).unwrap_err();
+        assert!(matches!(err, AppError::Codec(io::ErrorKind::InvalidInput)));
+        let err = Pin::new(&mut codec)
+            .serialize_mut(&Bytes::new(), 4)
+            .unwrap_err();
+        assert!(matches!(err, AppError::Codec(io::ErrorKind::InvalidInput)));
+        let err = Pin::new(&mut codec)
+            .deserialize_in_place(&BytesMut::new(), &mut Bytes::new())
+            .unwrap_err();
+        assert!(matches!(err, AppError::Codec(io::ErrorKind::InvalidData)));
+
+        let frames = stream::iter(vec![Ok::<_, AppError>(BytesMut::from(&b"x"[..]))]);
+        let mut framed = SymmetricallyFramed::<_, Bytes, _>::new(frames, codec);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert!(matches!(err, AppError::Codec(io::ErrorKind::InvalidData)));
+    }
+
+    #[test]
+    fn cached_reuses_frames() {
+        use crate::combinators::Cached;
+
+        #[derive(Default)]
+        struct Counting {
+            calls: usize,
+        }
+
+        impl Serializer<String> for Counting {
+            type Error = io::Error;
+
+            fn serialize(mut self: Pin<&mut Self>, item: &String) -> io::Result<Bytes> {
+                self.calls += 1;
+                Ok(Bytes::from(item.clone()))
+            }
+        }
+
+        let mut codec = Cached::new(Counting::default(), 2);
+        let hello = "hello".to_owned();
+
+        let first = Pin::new(&mut codec).serialize(&hello).unwrap();
+        let second = Pin::new(&mut codec).serialize(&hello).unwrap();
+        assert_eq!(codec.get_ref().calls, 1);
+        assert_eq!(first, second);
+        assert_eq!(first.as_ptr(), second.as_ptr());
+
+        // "hello" is used more recently than "a", so "a" is evicted.
+        for value in &["a", "hello", "b", "hello", "a"] {
+            Pin::new(&mut codec).serialize(&value.to_string()).unwrap();
+        }
+        assert_eq!(codec.get_ref().calls, 4);
+    }
+
+    #[test]
+    fn validated_rejects_invalid_values() {
+        use crate::combinators::{Validated, ValidationError};
+
+        let mut codec = Validated::new(Passthrough, |frame: &Bytes| {
+            if frame.len() <= 4 {
+                Ok(())
+            } else {
+                Err(ValidationError::new(format!(
+                    "{} bytes is too long",
+                    frame.len()
+                )))
+            }
+        });
+
+        let decoded: Bytes = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&b"okay"[..]))
+            .unwrap();
+        assert_eq!(decoded, &b"okay"[..]);
+
+        let res: io::Result<Bytes> =
+            Pin::new(&mut codec).deserialize_owned(BytesMut::from(&b"too long"[..]));
+        let err = res.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let cause = err.get_ref().unwrap().downcast_ref::<ValidationError>();
+        assert_eq!(cause.unwrap().message(), "8 bytes is too long");
+        assert_eq!(err.to_string(), "validation failed: 8 bytes is too long");
+
+        let buf = Pin::new(&mut codec)
+            .serialize(&Bytes::from_static(b"unchecked"))
+            .unwrap();
+        assert_eq!(buf, &b"unchecked"[..]);
+    }
+
+    #[test]
+    fn hybrid_envelope_round_trip() {
+        use crate::combinators::{FixedHeader, HybridEnvelope};
+        use bytes::{Buf, BufMut};
+
+        #[derive(Debug, PartialEq)]
+        struct Header {
+            type_id: u32,
+            len: u64,
+        }
+
+        impl FixedHeader for Header {
+            const LEN: usize = 12;
+
+            fn write(&self, dst: &mut BytesMut) {
+                dst.put_u32(self.type_id);
+                dst.put_u64(self.len);
+            }
+
+            fn read(src: &mut &[u8]) -> io::Result<Self> {
+                Ok(Header {
+                    type_id: src.get_u32(),
+                    len: src.get_u64(),
+                })
+            }
+        }
+
+        let mut codec = HybridEnvelope::<Header, _>::new(Passthrough);
+        let body = Bytes::from_static(b"body");
+        let header = Header {
+            type_id: 7,
+            len: body.len() as u64,
+        };
+
+        let buf = Pin::new(&mut codec).serialize(&(header, body)).unwrap();
+        assert_eq!(
+            &buf[..],
+            b"\x00\x00\x00\x07\x00\x00\x00\x00\x00\x00\x00\x04body"
+        );
+
+        let (header, body): (Header, Bytes) = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(header, Header { type_id: 7, len: 4 });
+        assert_eq!(body, &b"body"[..]);
+
+        let res: io::Result<(Header, Bytes)> =
+            Pin::new(&mut codec).deserialize(&BytesMut::from(&buf[..11]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        struct Short;
+
+        impl FixedHeader for Short {
+            const LEN: usize = 4;
+
+            fn write(&self, dst: &mut BytesMut) {
+                dst.put_u16(1);
+            }
+
+            fn read(src: &mut &[u8]) -> io::Result<Self> {
+                src.advance(Self::LEN);
+                Ok(Short)
+            }
+        }
+
+        let mut codec = HybridEnvelope::<Short, _>::new(Passthrough);
+        let err = Pin::new(&mut codec)
+            .serialize(&(Short, Bytes::from_static(b"body")))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn slice_yields_frame_bytes() {
+        use crate::{Slice, SymmetricallyFramed};
+        use futures::{stream, SinkExt, TryStreamExt};
+
+        let frame = BytesMut::from(&b"\x01custom\xff"[..]);
+        let ptr = frame.as_ptr();
+        let frames: Vec<io::Result<BytesMut>> = vec![Ok(frame)];
+        let mut framed = SymmetricallyFramed::<_, Bytes, _>::new(stream::iter(frames), Slice);
+
+        let bytes = framed.try_next().await.unwrap().unwrap();
+        assert_eq!(bytes, &b"\x01custom\xff"[..]);
+        assert_eq!(bytes.as_ptr(), ptr);
+        assert_eq!(bytes.clone().slice(1..7), &b"custom"[..]);
+
+        let copied: Bytes = Pin::new(&mut Slice)
+            .deserialize(&BytesMut::from(&b"abc"[..]))
+            .unwrap();
+        assert_eq!(copied, &b"abc"[..]);
+
+        let mut framed = SymmetricallyFramed::new(FlushSink::default(), Slice);
+        framed.send(bytes).await.unwrap();
+        assert_eq!(framed.get_ref().written, [&b"\x01custom\xff"[..]]);
+    }
+
+    #[tokio::test]
+    async fn framed_coalesce_decode_errors() {
+        use crate::{CheckedFramed, DecodeErrors, Framed, Ping};
+        use futures::{stream, TryStreamExt};
+
+        let frames = || {
+            let frames: Vec<io::Result<BytesMut>> = vec![
+                Ok(BytesMut::from(&b"bad 1"[..])),
+                Ok(BytesMut::from(&b"bad 2"[..])),
+                Ok(BytesMut::from(&b"bad 3"[..])),
+                Ok(BytesMut::new()),
+                Ok(BytesMut::from(&b"bad 4"[..])),
+            ];
+            stream::iter(frames)
+        };
+
+        let mut framed = CheckedFramed::new(Framed::<_, (), (), _>::new(frames(), Ping));
+        framed.coalesce_decode_errors(true);
+
+        let skipped = |err: io::Error| {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            err.get_ref()
+                .and_then(|err| err.downcast_ref::<DecodeErrors>())
+                .map(DecodeErrors::count)
+        };
+
+        let err = framed.try_next().await.unwrap_err();
+        assert_eq!(skipped(err), Some(3));
+        assert_eq!(framed.try_next().await.unwrap(), Some(()));
+        let err = framed.try_next().await.unwrap_err();
+        assert_eq!(skipped(err), Some(1));
+        assert_eq!(framed.try_next().await.unwrap(), None);
+
+        // Without the mode every corrupt frame is its own error.
+        let mut framed = Framed::<_, (), (), _>::new(frames(), Ping);
+        for _ in 0..3 {
+            let err = framed.try_next().await.unwrap_err();
+            assert_eq!(skipped(err), None);
+        }
+        assert_eq!(framed.try_next().await.unwrap(), Some(()));
+    }
+
+    #[tokio::test]
+    async fn checked_framed_stops_polling_ended_transport() {
+        use crate::{CheckedFramed, DecodeErrors, Framed, Ping};
+        use futures::{Stream, TryStreamExt};
+        use std::collections::VecDeque;
+
+        /// A transport that panics if polled after it has ended.
+        struct Unfused(Option<VecDeque<io::Result<BytesMut>>>);
+
+        impl Stream for Unfused {
+            type Item = io::Result<BytesMut>;
+
+            fn poll_next(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<Option<Self::Item>> {
+                let frames = self.0.as_mut().expect("polled after the end");
+                let frame = frames.pop_front();
+                if frame.is_none() {
+                    self.0 = None;
+                }
+                Poll::Ready(frame)
+            }
+        }
+
+        let frames = |frame: &'static [u8]| Unfused(Some(vec![Ok(BytesMut::from(frame))].into()));
+
+        let mut framed = CheckedFramed::new(Framed::<_, (), (), _>::new(frames(b"bad"), Ping));
+        framed.coalesce_decode_errors(true);
+
+        let err = framed.try_next().await.unwrap_err();
+        let skipped = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<DecodeErrors>());
+        assert_eq!(skipped.map(DecodeErrors::count), Some(1));
+        assert_eq!(framed.try_next().await.unwrap(), None);
+        assert_eq!(framed.try_next().await.unwrap(), None);
+
+        // A new transport is read from again.
+        framed.replace_transport(frames(b"bad"));
+        let err = framed.try_next().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(framed.try_next().await.unwrap(), None);
+
+        let mut framed = CheckedFramed::new(Framed::<_, (), (), _>::new(frames(b""), Ping));
+        framed.error_on_unexpected_close(true);
+        framed.set_expecting_more(true);
+
+        assert_eq!(framed.try_next().await.unwrap(), Some(()));
+        let err = framed.try_next().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(framed.try_next().await.unwrap(), None);
+        assert_eq!(framed.try_next().await.unwrap(), None);
+    }
+
+    #[test]
+    fn slice_cow_round_trip() {
+        use crate::Slice;
+        use std::borrow::Cow;
+
+        let payload = b"forwarded".to_vec();
+        let inputs: [Cow<'_, [u8]>; 2] = [Cow::Borrowed(&payload), Cow::Owned(payload.clone())];
+
+        for input in inputs.iter() {
+            let buf = Pin::new(&mut Slice).serialize(input).unwrap();
+            assert_eq!(buf, &payload[..]);
+
+            let decoded: Cow<'static, [u8]> = Pin::new(&mut Slice)
+                .deserialize(&BytesMut::from(&buf[..]))
+                .unwrap();
+            assert!(matches!(decoded, Cow::Owned(_)));
+            assert_eq!(&decoded, input);
+
+            let decoded: Cow<'static, [u8]> = Pin::new(&mut Slice)
+                .deserialize_owned(BytesMut::from(&buf[..]))
+                .unwrap();
+            assert_eq!(decoded, payload);
+        }
+    }
+
+    #[test]
+    fn framed_skips_serialize_errors() {
+        use crate::{SkipOrFail, SymmetricallyFramed};
+        use futures::{executor::block_on, SinkExt};
+
+        struct RejectEmpty;
+
+        impl Serializer<Bytes> for RejectEmpty {
+            type Error = io::Error;
+
+            fn serialize(self: Pin<&mut Self>, item: &Bytes) -> Result<Bytes, io::Error> {
+                if item.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty"));
+                }
+                Ok(item.clone())
+            }
+        }
+
+        let mut framed: SymmetricallyFramed<_, Bytes, _> =
+            SymmetricallyFramed::new(FlushSink::default(), RejectEmpty);
+
+        let err = block_on(framed.send(Bytes::new())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        framed.on_serialize_error(SkipOrFail::Skip);
+        block_on(framed.send(Bytes::from_static(b"one"))).unwrap();
+        block_on(framed.send(Bytes::new())).unwrap();
+        block_on(framed.send(Bytes::from_static(b"two"))).unwrap();
+
+        assert_eq!(
+            framed.get_ref().written,
+            [Bytes::from_static(b"one"), Bytes::from_static(b"two")]
+        );
+    }
+
+    #[test]
+    fn framed_max_frame_length() {
+        use crate::{CheckedFramed, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        let frames: Vec<io::Result<BytesMut>> = vec![
+            Ok(BytesMut::from(&b"ok"[..])),
+            Ok(BytesMut::from(&b"too long"[..])),
+            Ok(BytesMut::from(&b"fine"[..])),
+        ];
+
+        let framed: SymmetricallyFramed<_, Bytes, _> =
+            SymmetricallyFramed::new(stream::iter(frames), Passthrough);
+        let mut framed = CheckedFramed::new(framed);
+        framed.max_frame_length(Some(4));
+
+        assert_eq!(block_on(framed.try_next()).unwrap().unwrap(), &b"ok"[..]);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(block_on(framed.try_next()).unwrap().unwrap(), &b"fine"[..]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn framed_send_raw_forwards() {
+        use crate::{formats::SymmetricalJson, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, SinkExt, TryStreamExt};
+
+        let raw = Bytes::from_static(br#"{"id":7}"#);
+
+        let mut framed: SymmetricallyFramed<_, serde_json::Value, _> =
+            SymmetricallyFramed::new(FlushSink::default(), SymmetricalJson::default());
+        block_on(futures::future::poll_fn(|cx| {
+            Pin::new(&mut framed).poll_ready(cx)
+        }))
+        .unwrap();
+        Pin::new(&mut framed).send_raw(raw.clone()).unwrap();
+        assert!(framed.has_pending());
+        block_on(framed.flush()).unwrap();
+        assert_eq!(framed.get_ref().written, [raw]);
+
+        let frames: Vec<io::Result<BytesMut>> = framed
+            .get_ref()
+            .written
+            .iter()
+            .map(|frame| Ok(BytesMut::from(&frame[..])))
+            .collect();
+        let mut peer: SymmetricallyFramed<_, serde_json::Value, _> =
+            SymmetricallyFramed::new(stream::iter(frames), SymmetricalJson::default());
+        let value = block_on(peer.try_next()).unwrap().unwrap();
+        assert_eq!(value, serde_json::json!({ "id": 7 }));
+    }
+
+    #[cfg(feature = "fingerprint")]
+    #[tokio::test]
+    async fn framed_negotiate_fingerprints() {
+        use crate::{fingerprint, FingerprintMismatch, SymmetricallyFramed};
+        use serde::Deserialize;
+        use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+        #[derive(Deserialize)]
+        struct Order {
+            _id: u64,
+        }
+
+        #[derive(Deserialize)]
+        struct OrderV2 {
+            _id: u64,
+            _note: String,
+        }
+
+        let pair = || {
+            let (a, b) = tokio::io::duplex(64);
+            let a: SymmetricallyFramed<_, Bytes, _> =
+                SymmetricallyFramed::new(Framed::new(a, LengthDelimitedCodec::new()), Passthrough);
+            let b: SymmetricallyFramed<_, Bytes, _> =
+                SymmetricallyFramed::new(Framed::new(b, LengthDelimitedCodec::new()), Passthrough);
+            (a, b)
+        };
+
+        let (mut a, mut b) = pair();
+        let (ra, rb) = tokio::join!(
+            a.negotiate(fingerprint::of::<Order>()),
+            b.negotiate(fingerprint::of::<Order>()),
+        );
+        ra.unwrap();
+        rb.unwrap();
+
+        let (mut a, mut b) = pair();
+        let (ra, rb) = tokio::join!(
+            a.negotiate(fingerprint::of::<Order>()),
+            b.negotiate(fingerprint::of::<OrderV2>()),
+        );
+        for (err, local, remote) in [
+            (
+                ra.unwrap_err(),
+                fingerprint::of::<Order>(),
+                fingerprint::of::<OrderV2>(),
+            ),
+            (
+                rb.unwrap_err(),
+                fingerprint::of::<OrderV2>(),
+                fingerprint::of::<Order>(),
+            ),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let mismatch = err
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<FingerprintMismatch>())
+                .unwrap();
+            assert_eq!((mismatch.local(), mismatch.remote()), (local, remote));
+        }
+    }
+
+    #[test]
+    fn framed_poll_drain() {
+        use crate::SymmetricallyFramed;
+        use futures::{stream, task::noop_waker_ref, StreamExt};
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let frames: Vec<io::Result<BytesMut>> = vec![
+            Ok(BytesMut::from(&b"a"[..])),
+            Ok(BytesMut::from(&b"b"[..])),
+            Ok(BytesMut::from(&b"c"[..])),
+        ];
+        let ready = stream::iter(frames);
+        let mut framed = SymmetricallyFramed::new(ready.chain(stream::pending()), Passthrough);
+
+        let mut out = Vec::new();
+        let res = Pin::new(&mut framed).poll_drain(&mut cx, &mut out);
+        assert!(matches!(res, Poll::Ready(Ok(3))));
+        assert_eq!(out, [&b"a"[..], b"b", b"c"]);
+        assert!(Pin::new(&mut framed)
+            .poll_drain(&mut cx, &mut out)
+            .is_pending());
+
+        let frames: Vec<io::Result<BytesMut>> = vec![
+            Ok(BytesMut::from(&b"d"[..])),
+            Err(io::ErrorKind::InvalidData.into()),
+            Ok(BytesMut::from(&b"e"[..])),
+        ];
+        let mut framed = SymmetricallyFramed::new(stream::iter(frames), Passthrough);
+
+        let err = match Pin::new(&mut framed).poll_drain(&mut cx, &mut out) {
+            Poll::Ready(Err(err)) => err,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(err.decoded(), 1);
+        assert_eq!(err.into_inner().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(out.len(), 4);
+
+        let res = Pin::new(&mut framed).poll_drain(&mut cx, &mut out);
+        assert!(matches!(res, Poll::Ready(Ok(1))));
+        assert!(matches!(
+            Pin::new(&mut framed).poll_drain(&mut cx, &mut out),
+            Poll::Ready(Ok(0))
+        ));
+        assert_eq!(out.len(), 5);
+    }
+
+    #[test]
+    fn framed_poll_transport_forwarders() {
+        use crate::SymmetricallyFramed;
+        use futures::task::noop_waker_ref;
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+
+        Pin::new(&mut framed)
+            .start_send(Bytes::from_static(b"frame"))
+            .unwrap();
+        framed
+            .get_mut()
+            .buffered
+            .push(Bytes::from_static(b"control"));
+        assert!(framed.has_pending());
+
+        assert!(matches!(
+            Pin::new(&mut framed).poll_transport_ready(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert!(matches!(
+            Pin::new(&mut framed).poll_transport_flush(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert!(!framed.has_pending());
+        assert_eq!(framed.get_ref().flushes, 1);
+        assert_eq!(
+            framed.get_ref().written,
+            [Bytes::from_static(b"frame"), Bytes::from_static(b"control")]
+        );
+
+        framed.get_mut().stall_flush = true;
+        assert!(Pin::new(&mut framed)
+            .poll_transport_close(&mut cx)
+            .is_pending());
+    }
+
+    #[test]
+    fn delimited_splits_records() {
+        use crate::{Delimited, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, SinkExt, TryStreamExt};
+
+        let chunks: Vec<io::Result<BytesMut>> = vec![
+            Ok(BytesMut::from(&b"one\0two\0thr"[..])),
+            Ok(BytesMut::from(&b"ee\0"[..])),
+        ];
+        let framed = SymmetricallyFramed::new(Delimited::new(stream::iter(chunks), 0), Passthrough);
+        let records: Vec<Bytes> = block_on(framed.try_collect()).unwrap();
+        assert_eq!(records, [&b"one"[..], b"two", b"three"]);
+
+        let chunks: Vec<io::Result<BytesMut>> = vec![Ok(BytesMut::from(&b"one\0tw"[..]))];
+        let mut framed =
+            SymmetricallyFramed::new(Delimited::new(stream::iter(chunks), 0), Passthrough);
+        assert_eq!(block_on(framed.try_next()).unwrap().unwrap(), &b"one"[..]);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut framed =
+            SymmetricallyFramed::new(Delimited::new(FlushSink::default(), 0), Passthrough);
+        block_on(framed.send(Bytes::from_static(b"one"))).unwrap();
+        block_on(framed.send(Bytes::from_static(b"two"))).unwrap();
+        assert_eq!(
+            framed.get_ref().get_ref().written,
+            [&b"one\0"[..], b"two\0"]
+        );
+
+        let err = block_on(framed.send(Bytes::from_static(b"t\0o"))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Long records are reported once, then skipped up to the delimiter.
+        let chunks: Vec<io::Result<BytesMut>> = vec![
+            Ok(BytesMut::from(&b"one\0too lo"[..])),
+            Ok(BytesMut::from(&b"ng\0fine\0very"[..])),
+            Ok(BytesMut::from(&b" long\0two\0"[..])),
+        ];
+        let mut transport = Delimited::new(stream::iter(chunks), 0);
+        transport.set_max_record_length(4);
+        let mut framed = SymmetricallyFramed::new(transport, Passthrough);
+        assert_eq!(block_on(framed.try_next()).unwrap().unwrap(), &b"one"[..]);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(block_on(framed.try_next()).unwrap().unwrap(), &b"fine"[..]);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(block_on(framed.try_next()).unwrap().unwrap(), &b"two"[..]);
+        assert!(block_on(framed.try_next()).unwrap().is_none());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn length_newline_round_trip() {
+        use crate::{formats::SymmetricalJson, LengthNewline, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, SinkExt, TryStreamExt};
+
+        let mut framed = SymmetricallyFramed::new(
+            LengthNewline::new(FlushSink::default()),
+            SymmetricalJson::<Vec<u32>>::default(),
+        );
+        block_on(framed.send(vec![1, 2])).unwrap();
+        block_on(framed.send(vec![])).unwrap();
+        let written = &framed.get_ref().get_ref().written;
+        assert_eq!(written, &[&b"5\n[1,2]"[..], b"2\n[]"]);
+
+        let mut stream: Vec<u8> = written.iter().flat_map(|frame| frame.to_vec()).collect();
+        stream.extend_from_slice(b"3\n[3]");
+        let chunks: Vec<io::Result<BytesMut>> = stream
+            .chunks(3)
+            .map(|chunk| Ok(BytesMut::from(chunk)))
+            .collect();
+        let framed = SymmetricallyFramed::new(
+            LengthNewline::new(stream::iter(chunks)),
+            SymmetricalJson::<Vec<u32>>::default(),
+        );
+        let records: Vec<Vec<u32>> = block_on(framed.try_collect()).unwrap();
+        assert_eq!(records, [vec![1, 2], vec![], vec![3]]);
+    }
+
+    #[test]
+    fn length_newline_rejects_bad_prefix() {
+        use crate::{LengthNewline, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        for input in [
+            &b"1x\nab"[..],
+            b"\nab",
+            b"-1\na",
+            b"123456789012345678901234",
+        ] {
+            let chunks: Vec<io::Result<BytesMut>> = vec![Ok(BytesMut::from(input))];
+            let mut framed =
+                SymmetricallyFramed::new(LengthNewline::new(stream::iter(chunks)), Passthrough);
+            let err = block_on(framed.try_next()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let chunks: Vec<io::Result<BytesMut>> = vec![Ok(BytesMut::from(&b"5\nab"[..]))];
+        let mut framed =
+            SymmetricallyFramed::new(LengthNewline::new(stream::iter(chunks)), Passthrough);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // The announced length is rejected before the payload is read.
+        let chunks: Vec<io::Result<BytesMut>> = vec![Ok(BytesMut::from(&b"5\n"[..]))];
+        let mut transport = LengthNewline::new(stream::iter(chunks));
+        transport.set_max_frame_length(4);
+        let mut framed = SymmetricallyFramed::new(transport, Passthrough);
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn varint_round_trips_at_boundaries() {
+        use crate::byteorder_helpers::{get_varint, put_varint, MAX_VARINT_LEN};
+
+        for &(n, len) in &[(0, 1), (127, 1), (128, 2), (u64::MAX, MAX_VARINT_LEN)] {
+            let mut buf = BytesMut::new();
+            put_varint(&mut buf, n);
+            assert_eq!(buf.len(), len, "{}", n);
+            let mut frame = buf.freeze();
+            assert_eq!(get_varint(&mut frame).unwrap(), n);
+            assert!(frame.is_empty());
+        }
+
+        let mut truncated = &[0x80u8][..];
+        assert_eq!(
+            get_varint(&mut truncated).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        let mut overflow = &[0xffu8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02][..];
+        assert_eq!(
+            get_varint(&mut overflow).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn blobs_round_trip_in_either_byte_order() {
+        use crate::byteorder_helpers::*;
+
+        let mut buf = BytesMut::new();
+        put_blob::<BigEndian, _>(&mut buf, b"big");
+        put_blob::<LittleEndian, _>(&mut buf, b"little");
+        put_varint_blob(&mut buf, b"varint");
+        assert_eq!(&buf[..4], &[0, 0, 0, 3]);
+
+        let mut frame = buf.freeze();
+        assert_eq!(&get_blob::<BigEndian, _>(&mut frame).unwrap()[..], b"big");
+        assert_eq!(
+            &get_blob::<LittleEndian, _>(&mut frame).unwrap()[..],
+            b"little"
+        );
+        assert_eq!(&get_varint_blob(&mut frame).unwrap()[..], b"varint");
+        assert!(frame.is_empty());
+
+        let mut short = &[0, 0, 0, 9, b'x'][..];
+        assert_eq!(
+            get_blob::<BigEndian, _>(&mut short).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn with_sizes_reports_frame_len() {
+        use crate::{SymmetricallyFramed, WithSizes};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        let values = [Bytes::from_static(b"a"), Bytes::from_static(b"longer")];
+        let frames: Vec<io::Result<BytesMut>> = values
+            .iter()
+            .map(|value| {
+                let buf = Pin::new(&mut Passthrough).serialize(value).unwrap();
+                Ok(BytesMut::from(&buf[..]))
+            })
+            .collect();
+
+        let framed = SymmetricallyFramed::new(stream::iter(frames), Passthrough);
+        let decoded: Vec<(Bytes, usize)> = block_on(WithSizes::new(framed).try_collect()).unwrap();
+
+        assert_eq!(
+            decoded,
+            vec![
+                (Bytes::from_static(b"a"), 1),
+                (Bytes::from_static(b"longer"), 6),
+            ]
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn split_errors_diverts_decode_errors() {
+        use crate::{formats::SymmetricalJson, SplitErrors, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, StreamExt, TryStreamExt};
+
+        let frames = || {
+            let frames = [&b"1"[..], b"oops", b"2", b"{", b"3"];
+            stream::iter(frames.map(|frame| Ok::<_, io::Error>(BytesMut::from(frame))))
+        };
+
+        let framed = SymmetricallyFramed::new(frames(), SymmetricalJson::<u32>::default());
+        let (values, errors) = SplitErrors::new(framed, 4);
+        let decoded: Vec<u32> = block_on(values.try_collect()).unwrap();
+
+        assert_eq!(decoded, [1, 2, 3]);
+        assert_eq!(block_on(errors.count()), 2);
+
+        // Errors the receiver has no room for are dropped.
+        let framed = SymmetricallyFramed::new(frames(), SymmetricalJson::<u32>::default());
+        let (values, errors) = SplitErrors::new(framed, 0);
+        let decoded: Vec<u32> = block_on(values.try_collect()).unwrap();
+
+        assert_eq!(decoded, [1, 2, 3]);
+        assert_eq!(block_on(errors.count()), 1);
+    }
+
+    #[test]
+    fn recover_errors_skips_classified_errors() {
+        use crate::{ErrorAction, RecoverErrors, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        let frames: Vec<io::Result<BytesMut>> = vec![
+            Err(io::ErrorKind::InvalidData.into()),
+            Ok(BytesMut::from(&b"good"[..])),
+            Err(io::ErrorKind::ConnectionReset.into()),
+            Ok(BytesMut::from(&b"unreachable"[..])),
+        ];
+        let classify = |err: &io::Error| match err.kind() {
+            io::ErrorKind::InvalidData => ErrorAction::Skip,
+            _ => ErrorAction::Fatal,
+        };
+
+        let framed = SymmetricallyFramed::new(stream::iter(frames), Passthrough);
+        let mut framed = RecoverErrors::new(framed, classify);
+
+        assert_eq!(
+            block_on(framed.try_next()).unwrap(),
+            Some(Bytes::from_static(b"good"))
+        );
+        let err = block_on(framed.try_next()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn gap_detecting_reports_skipped_frames() {
+        use crate::{combinators::Sequenced, Framed, GapDetecting, StreamEvent};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        let mut codec = Sequenced::new(Passthrough);
+        let mut frames: Vec<io::Result<BytesMut>> = [&b"a"[..], b"b", b"c", b"d"]
+            .iter()
+            .map(|value| {
+                let buf = Pin::new(&mut codec)
+                    .serialize(&Bytes::from(*value))
+                    .unwrap();
+                Ok(BytesMut::from(&buf[..]))
+            })
+            .collect();
+        // Lose the frame numbered 1.
+        frames.remove(1).unwrap();
+
+        let framed: Framed<_, (u64, Bytes), Bytes, _> =
+            Framed::new(stream::iter(frames), Sequenced::new(Passthrough));
+        let events: Vec<StreamEvent<Bytes>> =
+            block_on(GapDetecting::new(framed).try_collect()).unwrap();
+
+        assert_eq!(
+            events,
+            [
+                StreamEvent::Item(Bytes::from_static(b"a")),
+                StreamEvent::Gap {
+                    expected: 1,
+                    got: 2
+                },
+                StreamEvent::Item(Bytes::from_static(b"c")),
+                StreamEvent::Item(Bytes::from_static(b"d")),
+            ]
+        );
+    }
+
+    #[test]
+    fn priority_framed_jumps_queue() {
+        use crate::{PriorityFramed, SymmetricallyFramed};
+        use futures::{executor::block_on, SinkExt};
+
+        let framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        let mut framed = PriorityFramed::new(framed);
+
+        block_on(framed.feed(Bytes::from_static(b"data 1"))).unwrap();
+        block_on(framed.feed(Bytes::from_static(b"data 2"))).unwrap();
+        Pin::new(&mut framed)
+            .start_send_priority(Bytes::from_static(b"urgent 1"))
+            .unwrap();
+        assert_eq!(framed.queued(), 3);
+        assert!(framed.get_ref().get_ref().buffered.is_empty());
+
+        block_on(framed.send_priority(Bytes::from_static(b"urgent 2"))).unwrap();
+        assert_eq!(framed.queued(), 0);
+        assert_eq!(
+            framed.get_ref().get_ref().written,
+            [
+                Bytes::from_static(b"urgent 1"),
+                Bytes::from_static(b"urgent 2"),
+                Bytes::from_static(b"data 1"),
+                Bytes::from_static(b"data 2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn vectored_framed_flushes_once() {
+        use crate::{SymmetricallyFramed, VectoredFramed};
+        use futures::{executor::block_on, SinkExt};
+
+        let framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        let mut framed = VectoredFramed::new(framed);
+
+        let batch: Vec<Bytes> = (0..5u8).map(|i| Bytes::from(vec![i])).collect();
+        block_on(framed.send(batch.clone())).unwrap();
+
+        assert_eq!(framed.queued(), 0);
+        let sink = framed.get_ref().get_ref();
+        assert_eq!(sink.written, batch);
+        assert_eq!(sink.flushes, 1);
+    }
+
+    #[test]
+    fn chunks_batches() {
+        use crate::{Chunks, SymmetricallyFramed};
+        use futures::{executor::block_on, stream, TryStreamExt};
+
+        let frames: Vec<io::Result<BytesMut>> =
+            (0..7u8).map(|i| Ok(BytesMut::from(&[i][..]))).collect();
+
+        let framed = SymmetricallyFramed::new(stream::iter(frames), Passthrough);
+        let batches: Vec<Vec<Bytes>> = block_on(Chunks::new(framed, 3).try_collect()).unwrap();
+
+        let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [3, 3, 1]);
+        assert_eq!(batches[2], [Bytes::from_static(&[6])]);
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test]
+    async fn chunks_timeout() {
+        use crate::{Chunks, SymmetricallyFramed};
+        use futures::{channel::mpsc, SinkExt, TryStreamExt};
+        use std::time::Duration;
+
+        let (mut tx, rx) = mpsc::channel::<io::Result<BytesMut>>(8);
+        let framed = SymmetricallyFramed::new(rx, Passthrough);
+        let mut chunks = Chunks::with_timeout(framed, 3, Duration::from_millis(20));
+
+        tx.send(Ok(BytesMut::from(&b"a"[..]))).await.unwrap();
+        let batch = chunks.try_next().await.unwrap().unwrap();
+        assert_eq!(batch, [Bytes::from_static(b"a")]);
+
+        for frame in [&b"b"[..], b"c", b"d"] {
+            tx.send(Ok(BytesMut::from(frame))).await.unwrap();
+        }
+        let batch = chunks.try_next().await.unwrap().unwrap();
+        assert_eq!(batch.len(), 3);
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test(start_paused = true)]
+    async fn rate_limited_throttles() {
+        use crate::RateLimited;
+        use futures::SinkExt;
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        let mut sink = RateLimited::new(FlushSink::default(), 2, Duration::from_secs(1), 2);
+
+        let start = Instant::now();
+        sink.send(Bytes::from_static(b"1")).await.unwrap();
+        sink.send(Bytes::from_static(b"2")).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        for _ in 0..4 {
+            sink.send(Bytes::from_static(b"n")).await.unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        assert_eq!(sink.get_ref().written.len(), 6);
+
+        let mut sink = RateLimited::bytes(FlushSink::default(), 10, Duration::from_secs(1), 10);
+        let start = Instant::now();
+        sink.send(Bytes::from_static(&[0; 15])).await.unwrap();
+        sink.send(Bytes::from_static(b"x")).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(600));
+    }
+
+    #[cfg(feature = "throttle")]
+    #[test]
+    fn throttled_parks_without_permits() {
+        use crate::Throttled;
+        use futures::{stream, task::noop_waker_ref, StreamExt};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use tokio::sync::Semaphore;
+
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let counter = pulled.clone();
+        let frames = stream::iter(0..3)
+            .inspect(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .map(Ok::<_, io::Error>);
+
+        let semaphore = Arc::new(Semaphore::new(2));
+        let mut throttled = Throttled::new(frames, semaphore.clone());
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        let mut items = Vec::new();
+        for _ in 0..2 {
+            match throttled.poll_next_unpin(&mut cx) {
+                Poll::Ready(Some(Ok(item))) => items.push(item),
+                other => panic!("unexpected {:?}", other.map(|_| ())),
+            }
+        }
+        assert_eq!(semaphore.available_permits(), 0);
+
+        assert!(throttled.poll_next_unpin(&mut cx).is_pending());
+        assert_eq!(pulled.load(Ordering::SeqCst), 2);
+
+        drop(items.remove(0));
+        match throttled.poll_next_unpin(&mut cx) {
+            Poll::Ready(Some(Ok((2, _)))) => {}
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        assert_eq!(pulled.load(Ordering::SeqCst), 3);
+
+        semaphore.close();
+        assert!(matches!(
+            throttled.poll_next_unpin(&mut cx),
+            Poll::Ready(None)
+        ));
+    }
+
+    #[cfg(feature = "flush-on-drop")]
+    #[test]
+    fn flush_on_drop_flushes_buffered_frames() {
+        use crate::{FlushOnDrop, SymmetricallyFramed};
+        use futures::{SinkExt, TryStreamExt};
+        use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (socket, peer) = tokio::io::duplex(1024);
+            let mut peer = Framed::new(peer, LengthDelimitedCodec::new());
+
+            let framed = SymmetricallyFramed::new(
+                Framed::new(socket, LengthDelimitedCodec::new()),
+                Passthrough,
+            );
+            let mut sink = FlushOnDrop::new(framed);
+            sink.feed(Bytes::from_static(b"one")).await.unwrap();
+            sink.feed(Bytes::from_static(b"two")).await.unwrap();
+            assert!(sink.get_ref().has_pending());
+            drop(sink);
+
+            assert_eq!(&peer.try_next().await.unwrap().unwrap()[..], b"one");
+            assert_eq!(&peer.try_next().await.unwrap().unwrap()[..], b"two");
+            assert!(peer.try_next().await.unwrap().is_none());
+        });
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test(start_paused = true)]
+    async fn reconnecting_resumes_after_failure() {
+        use crate::{Reconnecting, SymmetricallyFramed};
+        use futures::{SinkExt, StreamExt, TryStreamExt};
+        use std::{
+            collections::VecDeque,
+            sync::{Arc, Mutex},
+            time::Duration,
+        };
+        use tokio::io::DuplexStream;
+        use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+        type Transport = Framed<DuplexStream, LengthDelimitedCodec>;
+
+        fn connection() -> (Transport, Transport) {
+            let (socket, peer) = tokio::io::duplex(1024);
+            (
+                Framed::new(socket, LengthDelimitedCodec::new()),
+                Framed::new(peer, LengthDelimitedCodec::new()),
+            )
+        }
+
+        let (first, first_peer) = connection();
+        let (second, mut second_peer) = connection();
+
+        // The first attempt to reconnect fails, the second one succeeds.
+        let attempts = Arc::new(Mutex::new(VecDeque::from(vec![
+            Err(io::ErrorKind::ConnectionRefused.into()),
+            Ok(second),
+        ])));
+        let calls = attempts.clone();
+        let connect = move || {
+            let next = calls.lock().unwrap().pop_front().unwrap();
+            async move { next }
+        };
+
+        let mut framed = Reconnecting::new(SymmetricallyFramed::new(first, Passthrough), connect)
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(5))
+            .resend_unflushed(true);
+
+        drop(first_peer);
+        framed.send(Bytes::from_static(b"resumed")).await.unwrap();
+        assert_eq!(framed.reconnects(), 1);
+        assert!(attempts.lock().unwrap().is_empty());
+
+        assert_eq!(
+            &second_peer.try_next().await.unwrap().unwrap()[..],
+            b"resumed"
+        );
+
+        second_peer
+            .send(Bytes::from_static(b"reply"))
+            .await
+            .unwrap();
+        assert_eq!(&framed.next().await.unwrap().unwrap()[..], b"reply");
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test]
+    async fn reconnecting_keeps_codec_errors_and_caps_resent_frames() {
+        use crate::{Ping, Reconnecting, SymmetricallyFramed};
+        use futures::{SinkExt, StreamExt, TryStreamExt};
+        use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+        let (socket, peer) = tokio::io::duplex(1024);
+        let mut peer = Framed::new(peer, LengthDelimitedCodec::new());
+        let transport = Framed::new(socket, LengthDelimitedCodec::new());
+        let connect = || async { Err(io::ErrorKind::ConnectionRefused.into()) };
+
+        // A frame the codec rejects is returned without reconnecting.
+        let mut framed = Reconnecting::new(SymmetricallyFramed::new(transport, Ping), connect)
+            .reconnect_if(|_| true);
+        peer.send(Bytes::from_static(b"not a ping")).await.unwrap();
+        peer.send(Bytes::new()).await.unwrap();
+        let err = framed.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        framed.next().await.unwrap().unwrap();
+        assert_eq!(framed.reconnects(), 0);
+
+        let (first, first_peer) = tokio::io::duplex(1024);
+        let (second, second_peer) = tokio::io::duplex(1024);
+        let mut second = Some(Framed::new(second, LengthDelimitedCodec::new()));
+        let second_peer = Framed::new(second_peer, LengthDelimitedCodec::new());
+        let connect = move || {
+            let next = second
+                .take()
+                .ok_or_else(|| io::ErrorKind::NotConnected.into());
+            async move { next }
+        };
+
+        // Only the last two unflushed frames are kept to be sent again.
+        let first = Framed::new(first, LengthDelimitedCodec::new());
+        let mut framed = Reconnecting::new(SymmetricallyFramed::new(first, Passthrough), connect)
+            .resend_unflushed(true)
+            .with_max_unflushed(2);
+        for frame in [&b"one"[..], b"two", b"three"] {
+            framed.feed(Bytes::from_static(frame)).await.unwrap();
+        }
+        drop(first_peer);
+        framed.flush().await.unwrap();
+        assert_eq!(framed.reconnects(), 1);
+
+        drop(framed);
+        let frames: Vec<BytesMut> = second_peer.try_collect().await.unwrap();
+        assert_eq!(frames, [&b"two"[..], b"three"]);
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_after_idle_interval() {
+        use crate::{Keepalive, SymmetricallyFramed};
+        use futures::SinkExt;
+        use std::time::Duration;
+
+        let framed = SymmetricallyFramed::new(FlushSink::default(), Passthrough);
+        let mut sink = Keepalive::new(framed, Bytes::from_static(b"ping"), Duration::from_secs(10));
+
+        sink.send(Bytes::from_static(b"data")).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        sink.send(Bytes::from_static(b"more")).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        sink.flush().await.unwrap();
+        assert_eq!(sink.get_ref().get_ref().written, [&b"data"[..], b"more"]);
+
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        sink.flush().await.unwrap();
+        assert_eq!(
+            sink.get_ref().get_ref().written,
+            [&b"data"[..], b"more", b"ping"]
+        );
+
+        // A task waiting on the sink is woken up to send the next one.
+        let flushed = tokio::time::timeout(
+            Duration::from_secs(11),
+            futures::future::poll_fn(|cx| {
+                let _ = Pin::new(&mut sink).poll_flush(cx);
+                if sink.get_ref().get_ref().written.len() == 4 {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            }),
+        )
+        .await;
+        assert!(flushed.is_ok());
+        assert_eq!(sink.get_ref().get_ref().written[3], &b"ping"[..]);
+    }
+
+    #[test]
+    fn max_in_flight_back_pressure() {
+        use crate::MaxInFlight;
+        use futures::task::noop_waker_ref;
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut sink = MaxInFlight::new(
+            FlushSink {
+                stall_flush: true,
+                ..FlushSink::default()
+            },
+            10,
+        );
+
+        for _ in 0..3 {
+            assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+            Pin::new(&mut sink)
+                .start_send(Bytes::from_static(b"four"))
+                .unwrap();
+        }
+        assert_eq!(sink.in_flight_bytes(), 12);
+
+        // Over the limit: readiness waits on a flush of the inner sink.
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_pending());
+        assert_eq!(sink.get_ref().buffered.len(), 3);
+        assert!(sink.get_ref().written.is_empty());
+
+        sink.get_mut().stall_flush = false;
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+        assert_eq!(sink.in_flight_bytes(), 0);
+        assert_eq!(sink.get_ref().written.len(), 3);
+        assert_eq!(sink.get_ref().flushes, 1);
+    }
+
+    #[cfg(feature = "encrypted_bincode")]
+    #[test]
+    fn enc_bincode_impls() {
+        use impls::impls;
+        use std::fmt::Debug;
+
+        struct Nothing;
+        type T = crate::formats::EncryptedBincode<Nothing, Nothing>;
+
+        assert!(impls!(T: Debug));
+        assert!(impls!(T: Default));
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    mod on_empty {
+        use crate::{
+            formats::{SymmetricalBincode, SymmetricalJson},
+            Deserializer, EmptyFrame, OnEmpty, Serializer, SymmetricallyFramed,
+        };
+        use bytes::BytesMut;
+        use futures::{executor::block_on, stream, StreamExt};
+        use std::{io, pin::Pin};
+
+        /// Decodes an empty frame followed by `value` under `policy`.
+        fn decode<C, T>(mut codec: C, value: T, policy: EmptyFrame<T>) -> Vec<io::Result<T>>
+        where
+            C: Serializer<T> + Deserializer<T> + Unpin,
+            <C as Serializer<T>>::Error: std::fmt::Debug,
+            io::Error: From<<C as Deserializer<T>>::Error>,
+            T: Clone,
+        {
+            let buf = Pin::new(&mut codec).serialize(&value).unwrap();
+            let frames: Vec<io::Result<BytesMut>> =
+                vec![Ok(BytesMut::new()), Ok(BytesMut::from(&buf[..]))];
+
+            let framed = SymmetricallyFramed::new(stream::iter(frames), codec);
+            block_on(OnEmpty::new(framed, policy).collect())
+        }
+
+        fn json() -> SymmetricalJson<String> {
+            SymmetricalJson::default()
+        }
+
+        fn bincode() -> SymmetricalBincode<u32> {
+            SymmetricalBincode::default()
+        }
+
+        #[test]
+        fn error() {
+            let res = decode(json(), "hi".to_owned(), EmptyFrame::Error);
+            assert_eq!(res.len(), 2);
+            assert_eq!(
+                res[0].as_ref().unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+            assert_eq!(res[1].as_ref().unwrap(), "hi");
+
+            let res = decode(bincode(), 5, EmptyFrame::Error);
+            assert_eq!(res.len(), 2);
+            assert_eq!(
+                res[0].as_ref().unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+            assert_eq!(*res[1].as_ref().unwrap(), 5);
+        }
+
+        #[test]
+        fn skip() {
+            let res = decode(json(), "hi".to_owned(), EmptyFrame::Skip);
+            let values: Vec<String> = res.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, ["hi"]);
+
+            let res = decode(bincode(), 5, EmptyFrame::Skip);
+            let values: Vec<u32> = res.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, [5]);
+        }
+
+        #[test]
+        fn value() {
+            let res = decode(json(), "hi".to_owned(), EmptyFrame::Value(String::new()));
+            let values: Vec<String> = res.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, ["", "hi"]);
+
+            let res = decode(bincode(), 5, EmptyFrame::Value(0));
+            let values: Vec<u32> = res.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, [0, 5]);
+        }
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[test]
+    fn with_human_readable_override() {
+        use crate::formats::{SymmetricalBincode, SymmetricalJson};
+        use serde::{Deserialize, Serialize};
+        use std::net::IpAddr;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Peer {
+            addr: Option<IpAddr>,
+        }
+
+        let peer = Peer {
+            addr: Some(IpAddr::from([127, 0, 0, 1])),
+        };
+
+        let mut json = SymmetricalJson::<Peer>::default().with_human_readable(false);
+        let buf = Pin::new(&mut json).serialize(&peer).unwrap();
+        assert_eq!(&buf[..], br#"{"addr":{"V4":[127,0,0,1]}}"#);
+        let decoded = Pin::new(&mut json)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, peer);
+
+        let buf = Pin::new(&mut SymmetricalBincode::<Peer>::default())
+            .serialize(&peer)
+            .unwrap();
+        assert!(!buf.windows(9).any(|w| w == b"127.0.0.1"));
+
+        let mut bincode = SymmetricalBincode::<Peer>::default().with_human_readable(true);
+        let buf = Pin::new(&mut bincode).serialize(&peer).unwrap();
+        assert!(buf.windows(9).any(|w| w == b"127.0.0.1"));
+        let decoded = Pin::new(&mut bincode)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, peer);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_frame_bytes_zero_copy() {
+        use crate::{
+            combinators::{Interleaved, Lane},
+            formats::{FrameBytes, SymmetricalBincode},
+        };
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Upload {
+            id: u32,
+            blob: FrameBytes,
+        }
+
+        let upload = Upload {
+            id: 7,
+            blob: Bytes::from(vec![0xab; 1024 * 1024]).into(),
+        };
+
+        let mut codec = SymmetricalBincode::<Upload>::default();
+        let buf = Pin::new(&mut codec).serialize(&upload).unwrap();
+
+        let copied = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(copied, upload);
+
+        let frame = BytesMut::from(&buf[..]);
+        let frame_range = frame.as_ptr() as usize..frame.as_ptr() as usize + frame.len();
+        let decoded = Pin::new(&mut codec).deserialize_owned(frame).unwrap();
+        assert_eq!(decoded, upload);
+        assert!(frame_range.contains(&(decoded.blob.as_ptr() as usize)));
+        assert!(frame_range.contains(&(decoded.blob.as_ptr() as usize + decoded.blob.len() - 1)));
+
+        let mut codec = Interleaved::new(codec);
+        let buf = Pin::new(&mut codec).serialize(&(Lane(3), upload)).unwrap();
+        let frame = BytesMut::from(&buf[..]);
+        let frame_range = frame.as_ptr() as usize..frame.as_ptr() as usize + frame.len();
+        let (lane, decoded) = Pin::new(&mut codec).deserialize_owned(frame).unwrap();
+        assert_eq!(lane, Lane(3));
+        assert!(frame_range.contains(&(decoded.blob.as_ptr() as usize)));
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[test]
+    fn serialize_mut_prepends_header() {
+        use crate::formats::{SymmetricalBincode, SymmetricalJson};
+        use bytes::{Buf, BufMut};
+
+        let value = ("header".to_owned(), 42u32);
+
+        let mut codec = SymmetricalBincode::<(String, u32)>::default();
+        let mut frame = Pin::new(&mut codec).serialize_mut(&value, 4).unwrap();
+        assert_eq!(frame.capacity(), frame.len());
+        assert_eq!(&frame[..4], [0; 4]);
+        assert_eq!(frame[4..], Pin::new(&mut codec).serialize(&value).unwrap());
+
+        let len = frame.len() - 4;
+        (&mut frame[..4]).put_u32(len as u32);
+        assert_eq!(frame.get_u32() as usize, frame.len());
+        let decoded: (String, u32) = Pin::new(&mut codec).deserialize(&frame).unwrap();
+        assert_eq!(decoded, value);
+
+        let mut codec = SymmetricalJson::<(String, u32)>::default();
+        let payload = Pin::new(&mut codec).serialize_mut(&value, 0).unwrap();
+        assert_eq!(&payload[..], br#"["header",42]"#);
+
+        let payload = Pin::new(&mut Passthrough)
+            .serialize_mut(&Bytes::from_static(b"raw"), 2)
+            .unwrap();
+        assert_eq!(&payload[..], b"\0\0raw");
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn wrappers_forward_serialize_mut() {
+        use crate::combinators::{MapErr, Named, Sequenced};
+        use crate::formats::SymmetricalBincode;
+
+        let value = 7u32;
+        let codec = SymmetricalBincode::<u32>::default();
+        let mut codec = MapErr::new(Sequenced::new(Named::new(codec, "n")), |e| e);
+
+        let frame = Pin::new(&mut codec).serialize_mut(&value, 3).unwrap();
+        assert_eq!(frame.capacity(), frame.len());
+        assert_eq!(&frame[..], b"\0\0\0\0\0\0\0\0\0\0\0\x01n\x07");
+
+        let mut buf = b"pre".to_vec();
+        Pin::new(&mut codec)
+            .serialize_to_buf(&value, &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], b"pre\0\0\0\0\0\0\0\x01\x01n\x07");
+        assert_eq!(
+            Pin::new(&mut codec).serialize(&value).unwrap(),
+            &b"\0\0\0\0\0\0\0\x02\x01n\x07"[..]
+        );
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode", feature = "cbor"))]
+    #[test]
+    fn serialize_to_buf_appends() {
+        use crate::formats::{SymmetricalBincode, SymmetricalCbor};
+
+        fn check<C: Serializer<(String, u32)> + Default + Unpin>(value: &(String, u32))
+        where
+            C::Error: std::fmt::Debug + From<io::Error>,
+        {
+            let mut codec = C::default();
+            let mut buf = b"prefix".to_vec();
+            Pin::new(&mut codec)
+                .serialize_to_buf(value, &mut buf)
+                .unwrap();
+
+            let frame = Pin::new(&mut codec).serialize(value).unwrap();
+            assert_eq!(&buf[..6], b"prefix");
+            assert_eq!(&buf[6..], &frame[..]);
+        }
+
+        fn check_full<C: Serializer<(String, u32), Error = io::Error> + Default + Unpin>(
+            value: &(String, u32),
+        ) {
+            let mut storage = [0; 4];
+            let err = Pin::new(&mut C::default())
+                .serialize_to_buf(value, &mut &mut storage[..])
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        }
+
+        let value = ("pooled".to_owned(), 42u32);
+        // serde_json's own error can't be created from an `io::Error`.
+        #[cfg(feature = "unified-error")]
+        {
+            check::<crate::formats::SymmetricalJson<(String, u32)>>(&value);
+            check_full::<crate::formats::SymmetricalJson<(String, u32)>>(&value);
+        }
+        check::<SymmetricalBincode<(String, u32)>>(&value);
+        check_full::<SymmetricalBincode<(String, u32)>>(&value);
+        check::<SymmetricalCbor<(String, u32)>>(&value);
+        check_full::<SymmetricalCbor<(String, u32)>>(&value);
+
+        let mut buf = Vec::new();
+        Pin::new(&mut Passthrough)
+            .serialize_to_buf(&Bytes::from_static(b"raw"), &mut buf)
+            .unwrap();
+        assert_eq!(buf, b"raw");
+
+        let mut storage = [0; 2];
+        let err = Pin::new(&mut Passthrough)
+            .serialize_to_buf(&Bytes::from_static(b"raw"), &mut &mut storage[..])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_deserialize_in_place_reuses_capacity() {
+        use crate::formats::SymmetricalJson;
+
+        let mut codec = SymmetricalJson::<Vec<u32>>::default();
+        let mut place = Vec::new();
+
+        let long =
+            BytesMut::from(&serde_json::to_vec(&(0..100).collect::<Vec<u32>>()).unwrap()[..]);
+        Pin::new(&mut codec)
+            .deserialize_in_place(&long, &mut place)
+            .unwrap();
+        assert_eq!(place.len(), 100);
+        let (ptr, capacity) = (place.as_ptr(), place.capacity());
+
+        let short = BytesMut::from(&b"[7, 8, 9]"[..]);
+        Pin::new(&mut codec)
+            .deserialize_in_place(&short, &mut place)
+            .unwrap();
+        assert_eq!(place, [7, 8, 9]);
+        assert_eq!(place.as_ptr(), ptr);
+        assert_eq!(place.capacity(), capacity);
+
+        let trailing = BytesMut::from(&b"[1] 2"[..]);
+        assert!(Pin::new(&mut codec)
+            .deserialize_in_place(&trailing, &mut place)
+            .is_err());
+    }
+
+    #[cfg(all(
+        feature = "path-error",
+        feature = "json",
+        feature = "cbor",
+        feature = "bincode"
+    ))]
+    #[test]
+    fn path_error_reports_field_path() {
+        use crate::formats::{SymmetricalBincode, SymmetricalCbor, SymmetricalJson};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Config {
+            servers: Vec<Server>,
+        }
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Serialize)]
+        struct BadServer {
+            host: String,
+            port: serde_json::Value,
+        }
+
+        #[derive(Serialize)]
+        struct BadConfig {
+            servers: Vec<BadServer>,
+        }
+
+        let bad = BadConfig {
+            servers: (0..3)
+                .map(|i| BadServer {
+                    host: format!("s{}", i),
+                    port: if i == 2 { "http".into() } else { 1.into() },
+                })
+                .collect(),
+        };
+
+        let frame = BytesMut::from(&serde_json::to_vec(&bad).unwrap()[..]);
+        let err = Pin::new(&mut SymmetricalJson::<Config>::default())
+            .deserialize(&frame)
+            .unwrap_err();
+        // With `unified-error`, the message is behind the source of the
+        // `io::Error`; the debug output covers both cases.
+        assert!(
+            format!("{:?}", err).contains("servers[2].port: "),
+            "{:?}",
+            err
+        );
+
+        let frame = BytesMut::from(&serde_cbor::to_vec(&bad).unwrap()[..]);
+        let err = Pin::new(&mut SymmetricalCbor::<Config>::default())
+            .deserialize(&frame)
+            .unwrap_err();
+        assert!(err.to_string().contains("servers[2].port: "), "{}", err);
+
+        // bincode isn't self-describing, so a short frame stands in for a
+        // wrong type. It decodes structs as tuples, so fields show up by
+        // index.
+        let good = Config {
+            servers: vec![Server {
+                host: "s0".into(),
+                port: 1,
+            }],
+        };
+        let mut codec = SymmetricalBincode::<Config>::default();
+        let mut frame = BytesMut::from(&Pin::new(&mut codec).serialize(&good).unwrap()[..]);
+        frame.truncate(frame.len() - 1);
+        let err = Pin::new(&mut codec).deserialize(&frame).unwrap_err();
+        assert!(err.to_string().starts_with("[0][0][1]: "), "{}", err);
+
+        let frame = BytesMut::from(&b"[1"[..]);
+        let err = Pin::new(&mut SymmetricalJson::<Vec<u8>>::default())
+            .deserialize(&frame)
+            .unwrap_err();
+        assert!(!format!("{:?}", err).contains(": EOF"), "{:?}", err);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_strips_bom() {
+        use crate::formats::SymmetricalJson;
+
+        let mut codec = SymmetricalJson::<Vec<u32>>::default();
+        let frame = BytesMut::from(&b"\xef\xbb\xbf[1,2]"[..]);
+        assert_eq!(Pin::new(&mut codec).deserialize(&frame).unwrap(), [1, 2]);
+
+        let mut place = Vec::new();
+        Pin::new(&mut codec)
+            .deserialize_in_place(&frame, &mut place)
+            .unwrap();
+        assert_eq!(place, [1, 2]);
+
+        let buf = Pin::new(&mut codec).serialize(&vec![1, 2]).unwrap();
+        assert_eq!(&buf[..], b"[1,2]");
+
+        let frame = BytesMut::from(&b"[1]\xef\xbb\xbf"[..]);
+        assert!(Pin::new(&mut codec).deserialize(&frame).is_err());
+    }
+
+    #[cfg(feature = "encrypted_bincode")]
+    #[test]
+    fn enc_bincode_round_trip() {
+        use crate::formats::SymmetricalEncryptedBincode;
+
+        let mut codec = SymmetricalEncryptedBincode::<String>::new(vec![7; 32], None);
+
+        let buf = Pin::new(&mut codec)
+            .serialize(&"secret".to_owned())
+            .unwrap();
+        assert!(!buf.windows(6).any(|w| w == b"secret"));
+
+        let value = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(value, "secret");
+
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..10]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "encrypted_bincode")]
+    #[test]
+    fn enc_bincode_rotate_key() {
+        use crate::formats::SymmetricalEncryptedBincode;
+
+        let mut sender = SymmetricalEncryptedBincode::<String>::new(vec![1; 32], None);
+        let mut receiver = SymmetricalEncryptedBincode::<String>::new(vec![2; 32], None);
+
+        let old = Pin::new(&mut sender).serialize(&"old".to_owned()).unwrap();
+        assert!(Pin::new(&mut receiver)
+            .deserialize(&BytesMut::from(&old[..]))
+            .is_err());
+
+        sender.rotate_key(vec![2; 32]);
+        let new = Pin::new(&mut sender).serialize(&"new".to_owned()).unwrap();
+        let value = Pin::new(&mut receiver)
+            .deserialize(&BytesMut::from(&new[..]))
+            .unwrap();
+        assert_eq!(value, "new");
+
+        let err = Pin::new(&mut sender)
+            .deserialize(&BytesMut::from(&old[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "encrypted_bincode")]
+    #[test]
+    fn enc_bincode_new_random() {
+        use crate::formats::SymmetricalEncryptedBincode;
+        use bincode_crate::Options;
+
+        let opts = bincode_crate::DefaultOptions::new().with_fixint_encoding();
+        let mut sender = SymmetricalEncryptedBincode::<u64, _>::new_random(opts);
+        assert_eq!(sender.key().len(), 32);
+
+        let mut receiver = SymmetricalEncryptedBincode::<u64, _>::new_random(opts);
+        receiver.rotate_key(sender.key().to_vec());
+        let buf = Pin::new(&mut sender).serialize(&7).unwrap();
+        // Nonce, eight fixint bytes and the authentication tag.
+        assert_eq!(buf.len(), 24 + 8 + 16);
+
+        let value = Pin::new(&mut receiver)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(value, 7);
+
+        let other = SymmetricalEncryptedBincode::<u64, _>::new_random(opts);
+        assert_ne!(other.key(), sender.key());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_impls() {
+        use impls::impls;
+        use std::fmt::Debug;
+
+        struct Nothing;
+        type T = crate::formats::Bincode<Nothing, Nothing>;
+
+        assert!(impls!(T: Debug));
+        assert!(impls!(T: Default));
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[tokio::test]
+    async fn bi_framed_json_in_bincode_out() {
+        use crate::{
+            formats::{SymmetricalBincode, SymmetricalJson},
+            BiFramed,
+        };
+        use futures::{SinkExt, TryStreamExt};
+        use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+        let (a, b) = tokio::io::duplex(1024);
+
+        // `a` reads JSON and writes bincode, `b` does the opposite.
+        let mut a = BiFramed::new(
+            Framed::new(a, LengthDelimitedCodec::new()),
+            SymmetricalJson::<String>::default(),
+            SymmetricalBincode::<u32>::default(),
+        );
+        let mut b = BiFramed::new(
+            Framed::new(b, LengthDelimitedCodec::new()),
+            SymmetricalBincode::<u32>::default(),
+            SymmetricalJson::<String>::default(),
+        );
+
+        a.send(42).await.unwrap();
+        assert_eq!(b.try_next().await.unwrap(), Some(42));
+
+        b.send("hello".to_owned()).await.unwrap();
+        assert_eq!(a.try_next().await.unwrap(), Some("hello".to_owned()));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_byte_limit() {
+        use crate::{formats::SymmetricalBincode, Deserializer, Serializer};
+        use bytes::BytesMut;
+
+        let mut codec = SymmetricalBincode::<Vec<u64>>::default().with_byte_limit(64);
+
+        // A `Vec` claiming one billion elements (varint encoded length),
+        // followed by a little data.
+        let mut frame = BytesMut::from(&[253][..]);
+        frame.extend_from_slice(&1_000_000_000u64.to_le_bytes());
+        frame.extend_from_slice(&[0; 128]);
+
+        let err = Pin::new(&mut codec).deserialize(&frame).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("size limit"));
+
+        // Values within the limit still round-trip.
+        let buf = Pin::new(&mut codec).serialize(&vec![1, 2, 3]).unwrap();
+        let value = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(value, vec![1, 2, 3]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_impls() {
+        use impls::impls;
+        use std::fmt::Debug;
+
+        struct Nothing;
+        type T = crate::formats::Json<Nothing, Nothing>;
+
+        assert!(impls!(T: Debug));
+        assert!(impls!(T: Default));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_deserialize_cow() {
+        use crate::formats::SymmetricalJson;
+        use bytes::BytesMut;
+        use serde::Deserialize;
+        use std::borrow::Cow;
+
+        #[derive(Deserialize)]
+        struct Message<'a> {
+            #[serde(borrow)]
+            name: Cow<'a, str>,
+        }
+
+        let codec = SymmetricalJson::<()>::default();
+
+        let plain = BytesMut::from(&br#"{"name":"plain"}"#[..]);
+        let msg: Message = codec.deserialize_cow(&plain).unwrap();
+        assert!(matches!(msg.name, Cow::Borrowed("plain")));
+
+        let escaped = BytesMut::from(&br#"{"name":"esc\"aped"}"#[..]);
+        let msg: Message = codec.deserialize_cow(&escaped).unwrap();
+        assert!(matches!(msg.name, Cow::Owned(ref name) if name == "esc\"aped"));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_deserialize_iter_decodes_lazily() {
+        use crate::formats::SymmetricalBincode;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            id: u32,
+            name: String,
+        }
+
+        let mut codec = SymmetricalBincode::<Record>::default();
+        let mut batch = BytesMut::new();
+        for id in 0..5 {
+            let record = Record {
+                id,
+                name: format!("record-{}", id),
+            };
+            batch.extend_from_slice(&Pin::new(&mut codec).serialize(&record).unwrap());
+        }
+
+        let mut records = codec.deserialize_iter::<Record>(&batch);
+        assert_eq!(records.next().unwrap().unwrap().id, 0);
+        let rest = records.collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(rest.iter().map(|r| r.id).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(rest[3].name, "record-4");
+
+        batch.truncate(batch.len() - 1);
+        let results = codec.deserialize_iter::<Record>(&batch).collect::<Vec<_>>();
+        assert_eq!(results.len(), 5);
+        assert_eq!(
+            results[4].as_ref().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[cfg(all(feature = "cbor", feature = "messagepack"))]
+    #[test]
+    fn byte_strings_use_native_representation() {
+        use crate::formats::{SymmetricalCbor, SymmetricalMessagePack};
+        use serde::{Deserialize, Serialize};
+        use serde_bytes::ByteBuf;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Blob {
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+            buf: ByteBuf,
+        }
+
+        let blob = Blob {
+            data: vec![0xff; 4],
+            buf: ByteBuf::from(vec![0x80; 3]),
+        };
+
+        let mut cbor = SymmetricalCbor::<Blob>::default();
+        let frame = Pin::new(&mut cbor).serialize(&blob).unwrap();
+        // Major type 2, byte string of length 4, then 3.
+        let data = frame.windows(5).position(|w| w == b"\x44\xff\xff\xff\xff");
+        assert!(data.is_some(), "{:x?}", frame);
+        assert!(frame.ends_with(b"\x43\x80\x80\x80"), "{:x?}", frame);
+        let decoded = Pin::new(&mut cbor)
+            .deserialize(&BytesMut::from(&frame[..]))
+            .unwrap();
+        assert_eq!(decoded, blob);
+
+        let mut msgpack = SymmetricalMessagePack::<Blob>::default();
+        let frame = Pin::new(&mut msgpack).serialize(&blob).unwrap();
+        // A fixarray of two bin 8 values.
+        assert_eq!(
+            &frame[..],
+            b"\x92\xc4\x04\xff\xff\xff\xff\xc4\x03\x80\x80\x80"
+        );
+        let decoded = Pin::new(&mut msgpack)
+            .deserialize(&BytesMut::from(&frame[..]))
+            .unwrap();
+        assert_eq!(decoded, blob);
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[test]
+    fn seed_codec_resolves_interned_names() {
+        use crate::formats::{SeedCodec, SymmetricalBincode, SymmetricalJson};
+        use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+        use std::{fmt, sync::Arc};
+
+        /// Decodes a list of name ids into the interned names.
+        #[derive(Clone)]
+        struct Names(Arc<Vec<String>>);
+
+        impl<'de> DeserializeSeed<'de> for Names {
+            type Value = Vec<String>;
+
+            fn deserialize<D: de::Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+                de.deserialize_seq(self)
+            }
+        }
+
+        impl<'de> Visitor<'de> for Names {
+            type Value = Vec<String>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a list of name ids")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut names = Vec::new();
+                while let Some(id) = seq.next_element::<usize>()? {
+                    let name = self
+                        .0
+                        .get(id)
+                        .ok_or_else(|| de::Error::custom(format_args!("unknown name id {}", id)))?;
+                    names.push(name.clone());
+                }
+                Ok(names)
+            }
+        }
+
+        let names = Names(Arc::new(vec!["alice".to_owned(), "bob".to_owned()]));
+
+        let mut json = SeedCodec::new(SymmetricalJson::<Vec<String>>::default(), names.clone());
+        let decoded = Pin::new(&mut json)
+            .deserialize(&BytesMut::from(&b"[1,0,1]"[..]))
+            .unwrap();
+        assert_eq!(decoded, ["bob", "alice", "bob"]);
+        assert!(Pin::new(&mut json)
+            .deserialize(&BytesMut::from(&b"[2]"[..]))
+            .is_err());
+
+        let mut bincode = SeedCodec::new(SymmetricalBincode::<Vec<String>>::default(), names);
+        let frame = Pin::new(&mut SymmetricalBincode::<Vec<u64>>::default())
+            .serialize(&vec![0, 1])
+            .unwrap();
+        let decoded = Pin::new(&mut bincode)
+            .deserialize(&BytesMut::from(&frame[..]))
+            .unwrap();
+        assert_eq!(decoded, ["alice", "bob"]);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn plain_round_trip() {
+        use crate::formats::Plain;
+
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        #[repr(C)]
+        struct Tick {
+            price: f64,
+            volume: u32,
+            venue: u16,
+            flags: u16,
+        }
+
+        // SAFETY: `Tick` is `repr(C)`, has no padding and only holds `Pod`
+        // fields.
+        unsafe impl bytemuck_crate::Zeroable for Tick {}
+        unsafe impl bytemuck_crate::Pod for Tick {}
+
+        let tick = Tick {
+            price: 101.25,
+            volume: 300,
+            venue: 7,
+            flags: 0b101,
+        };
+
+        let mut codec = Plain::<Tick>::default();
+        let buf = Pin::new(&mut codec).serialize(&tick).unwrap();
+        assert_eq!(buf.len(), 16);
+
+        // Decoding works from any offset in memory.
+        let mut unaligned = BytesMut::from(&b"x"[..]);
+        unaligned.extend_from_slice(&buf);
+        let unaligned = unaligned.split_off(1);
+        let decoded = Pin::new(&mut codec).deserialize(&unaligned).unwrap();
+        assert_eq!(decoded, tick);
+
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..15]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn protobuf_round_trip() {
+        use crate::formats::SymmetricalProtobuf;
+        use protobuf_crate::well_known_types::timestamp::Timestamp;
+
+        let mut codec = SymmetricalProtobuf::<Timestamp>::default();
+
+        let mut ts = Timestamp::new();
+        ts.seconds = 1_700_000_000;
+        ts.nanos = 250;
+
+        let buf = Pin::new(&mut codec).serialize(&ts).unwrap();
+        // Field 1, `seconds`, as a varint.
+        assert_eq!(buf[0], 0x08);
+        let decoded = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, ts);
+
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&b"\x08"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(all(feature = "cbor", feature = "json"))]
+    #[test]
+    fn value_cbor_round_trip() {
+        use crate::formats::ValueCbor;
+        use serde_json::json;
+
+        let value = json!({
+            "name": "sensor",
+            "tags": ["a", "b", null],
+            "reading": { "celsius": 21.5, "count": u64::MAX, "delta": -3 },
+            "calibrated": false,
+            "owner": null,
+        });
+
+        let mut codec = ValueCbor::default();
+        let buf = Pin::new(&mut codec).serialize(&value).unwrap();
+        assert!(buf.len() < serde_json::to_vec(&value).unwrap().len());
+
+        let decoded = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn finite_floats_rejects_infinity() {
+        use crate::{combinators::FiniteFloats, formats::Cbor};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Reading {
+            sensor: String,
+            samples: Vec<f64>,
+        }
+
+        let mut codec = FiniteFloats::new(Cbor::<serde_cbor::Value, Reading>::default());
+
+        let finite = Reading {
+            sensor: "a".to_owned(),
+            samples: vec![0.5, -1.0, 1e300],
+        };
+        let buf = Pin::new(&mut codec).serialize(&finite).unwrap();
+        let decoded: Reading = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, finite);
+
+        for bad in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+            let reading = Reading {
+                sensor: "b".to_owned(),
+                samples: vec![1.0, bad],
+            };
+            let buf = Pin::new(&mut codec).serialize(&reading).unwrap();
+            let err = Deserializer::<Reading>::deserialize(
+                Pin::new(&mut codec),
+                &BytesMut::from(&buf[..]),
+            )
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_concatenated_decodes_values_in_turn() {
+        use crate::formats::SymmetricalJson;
+        use serde::Deserialize;
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Event {
+            id: u32,
+        }
+
+        let stream = BytesMut::from(&b"{\"id\":1} {\"id\":2}\n\t{\"id\":3}\n"[..]);
+
+        let mut codec = SymmetricalJson::<Event>::concatenated();
+        let first = Pin::new(&mut codec).deserialize(&stream).unwrap();
+        assert_eq!(first, Event { id: 1 });
+
+        let mut rest = &stream[..];
+        let mut events = Vec::new();
+        while !rest.is_empty() {
+            let (event, consumed) = codec.deserialize_with_remainder::<Event>(rest).unwrap();
+            events.push(event);
+            rest = &rest[consumed..];
+        }
+        assert_eq!(
+            events,
+            vec![Event { id: 1 }, Event { id: 2 }, Event { id: 3 }]
+        );
+
+        let mut codec = SymmetricalJson::<Event>::default();
+        assert!(Pin::new(&mut codec).deserialize(&stream).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_serialize_array_streams_elements() {
+        use crate::formats::{Json, SymmetricalJson};
+
+        let codec = Json::<(), ()>::default();
+        let buf = codec
+            .serialize_array((0..10_000u32).map(|i| (i, i % 7 == 0)))
+            .unwrap();
 
-        fn gen_key() -> Secret<Vec<u8>> {
-            let mut res = Key::default();
-            let mut rng = OsRng::default();
-            rng.fill_bytes(&mut res);
-            Secret::new(res.to_vec())
+        let mut codec = SymmetricalJson::<Vec<(u32, bool)>>::default();
+        let decoded = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded.len(), 10_000);
+        assert!(decoded
+            .iter()
+            .enumerate()
+            .all(|(i, &(n, flag))| n as usize == i && flag == (n % 7 == 0)));
+
+        let empty = Json::<(), ()>::default()
+            .serialize_array(std::iter::empty::<u32>())
+            .unwrap();
+        assert_eq!(&empty[..], b"[]");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_merge_patch_sends_diffs() {
+        use crate::formats::SymmetricalJsonMergePatch;
+        use serde_json::{json, Value};
+
+        let mut sender = SymmetricalJsonMergePatch::<Value>::default();
+        let mut receiver = SymmetricalJsonMergePatch::<Value>::default();
+        let mut sync = |state: &Value| {
+            let patch = Pin::new(&mut sender).serialize(state).unwrap();
+            let rebuilt = Pin::new(&mut receiver)
+                .deserialize(&BytesMut::from(&patch[..]))
+                .unwrap();
+            assert_eq!(&rebuilt, state);
+            serde_json::from_slice::<Value>(&patch).unwrap()
+        };
+
+        let first = json!({ "name": "node", "tags": ["a"], "config": { "x": 1, "y": 2 } });
+        assert_eq!(sync(&first), first);
+
+        let second = json!({ "name": "node", "tags": ["a", "b"], "config": { "x": 3 } });
+        assert_eq!(
+            sync(&second),
+            json!({ "tags": ["a", "b"], "config": { "x": 3, "y": null } })
+        );
+
+        let third = json!({ "name": "renamed", "tags": ["a", "b"], "config": { "x": 3 } });
+        assert_eq!(sync(&third), json!({ "name": "renamed" }));
+    }
+
+    #[cfg(all(feature = "simd-json", feature = "json"))]
+    #[test]
+    fn simd_json_round_trip() {
+        use crate::formats::{Json, SimdJson};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Message {
+            id: u32,
+            tags: Vec<String>,
+            parent: Option<Box<Message>>,
         }
-        /// Encrypted bincode codec using [bincode](https://docs.rs/bincode) crate
-        /// for serialization and [chacha20poly1305](https://docs.rs/chacha20poly1305) for encryption.
-        #[cfg_attr(docsrs, doc(cfg(feature = "encrypted_bincode")))]
-        #[derive(Educe)]
-        #[educe(Debug)]
-        pub struct EncryptedBincode<Item, SinkItem, O = bincode_crate::DefaultOptions> {
-            #[educe(Debug(ignore))]
-            options: O,
-            #[educe(Debug(ignore))]
-            ghost: PhantomData<(Item, SinkItem)>,
-            #[educe(Debug(ignore))]
-            key: Secret<Vec<u8>>,
+
+        let msg = Message {
+            id: 1,
+            tags: vec!["a".to_owned(), "b\"c".to_owned()],
+            parent: Some(Box::new(Message {
+                id: 0,
+                tags: vec![],
+                parent: None,
+            })),
+        };
+
+        let mut simd = SimdJson::<Message, Message>::default();
+        let mut json = Json::<Message, Message>::default();
+
+        let simd_buf = Pin::new(&mut simd).serialize(&msg).unwrap();
+        let json_buf = Pin::new(&mut json).serialize(&msg).unwrap();
+        assert_eq!(simd_buf, json_buf);
+
+        let frame = BytesMut::from(&json_buf[..]);
+        assert_eq!(Pin::new(&mut simd).deserialize(&frame).unwrap(), msg);
+        // The scratch buffer is reused for the next frame.
+        assert_eq!(Pin::new(&mut simd).deserialize(&frame).unwrap(), msg);
+        assert_eq!(
+            Pin::new(&mut json)
+                .deserialize(&BytesMut::from(&simd_buf[..]))
+                .unwrap(),
+            msg
+        );
+
+        let err = Pin::new(&mut simd)
+            .deserialize(&BytesMut::from(&b"{"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "messagepack")]
+    #[test]
+    fn messagepack_impls() {
+        use impls::impls;
+        use std::fmt::Debug;
+
+        struct Nothing;
+        type T = crate::formats::MessagePack<Nothing, Nothing>;
+
+        assert!(impls!(T: Debug));
+        assert!(impls!(T: Default));
+    }
+
+    #[cfg(feature = "messagepack")]
+    #[test]
+    fn messagepack_compatible_flatten() {
+        use crate::{formats::SymmetricalMessagePack, Deserializer, Serializer};
+        use bytes::BytesMut;
+        use serde::{Deserialize, Serialize};
+        use std::net::IpAddr;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Peer {
+            addr: IpAddr,
         }
 
-        impl<Item, SinkItem, O> EncryptedBincode<Item, SinkItem, O>
-        where
-            O: Options
-        {
-            pub fn new(key: Vec<u8>, opts: Option<O>) -> Self {
-                let key = Secret::new(key);
-                    Self {
-                        options: opts.unwrap_or(bincode_crate::DefaultOptions::default()),
-                        ghost: PhantomData,
-                        key
-                    }
-            }
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Message {
+            id: u32,
+            #[serde(flatten)]
+            peer: Peer,
         }
 
-        impl<Item, SinkItem> Default for EncryptedBincode<Item, SinkItem> {
-            fn default() -> Self {
-                EncryptedBincode {
-                    options: Default::default(),
-                    ghost: PhantomData,
-                    key: gen_key(),
-                }
-            }
+        fn round_trip(
+            mut codec: SymmetricalMessagePack<Message>,
+            msg: &Message,
+        ) -> io::Result<Message> {
+            let buf = Pin::new(&mut codec).serialize(msg)?;
+            Pin::new(&mut codec).deserialize(&BytesMut::from(&buf[..]))
         }
 
-        impl<Item, SinkItem, O> From<O> for EncryptedBincode<Item, SinkItem, O>
-        where
-            O: Options,
-        {
-            fn from(options: O) -> Self {
-                Self {
-                    options,
-                    ghost: PhantomData,
-                    key: gen_key(),
-                }
-            }
+        let msg = Message {
+            id: 7,
+            peer: Peer {
+                addr: "127.0.0.1".parse().unwrap(),
+            },
+        };
+
+        assert!(round_trip(SymmetricalMessagePack::default(), &msg).is_err());
+        assert_eq!(
+            round_trip(SymmetricalMessagePack::compatible(), &msg).unwrap(),
+            msg
+        );
+    }
+
+    #[cfg(all(
+        feature = "json",
+        feature = "bincode",
+        feature = "messagepack",
+        feature = "cbor"
+    ))]
+    #[test]
+    fn any_codec_by_name() {
+        use crate::formats::{AnyCodec, Bincode, Json};
+        use std::convert::TryFrom;
+
+        let value = ("any".to_owned(), 7u32);
+
+        for name in ["json", "bincode", "messagepack", "cbor"] {
+            let mut codec: AnyCodec<(String, u32), (String, u32)> = name.parse().unwrap();
+            assert_eq!(codec.name(), name);
+
+            let buf = Pin::new(&mut codec).serialize(&value).unwrap();
+            let decoded = Pin::new(&mut codec)
+                .deserialize(&BytesMut::from(&buf[..]))
+                .unwrap();
+            assert_eq!(decoded, value);
+
+            let frame = Pin::new(&mut codec).serialize_mut(&value, 2).unwrap();
+            assert_eq!(&frame[..2], &[0, 0]);
+            assert_eq!(&frame[2..], &buf[..]);
+
+            let mut out = vec![0xff];
+            Pin::new(&mut codec)
+                .serialize_to_buf(&value, &mut out)
+                .unwrap();
+            assert_eq!(&out[1..], &buf[..]);
+
+            let err = Pin::new(&mut codec)
+                .serialize_to_buf(&value, &mut &mut [0; 4][..])
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WriteZero, "{}", name);
         }
 
-        #[cfg_attr(docsrs, doc(cfg(feature = "encrypted_bincode")))]
-        pub type SymmetricalEncryptedBincode<T, O = bincode_crate::DefaultOptions> =
-            EncryptedBincode<T, T, O>;
+        let err = "yaml".parse::<AnyCodec<(), ()>>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
 
-        impl<Item, SinkItem, O> Deserializer<Item> for EncryptedBincode<Item, SinkItem, O>
-        where
-            for<'a> Item: Deserialize<'a>,
-            O: Options + Clone,
-        {
-            type Error = io::Error;
+        let codec = AnyCodec::<(), ()>::from(Json::default());
+        let codec = Bincode::try_from(codec).unwrap_err();
+        assert!(Json::try_from(codec).is_ok());
+    }
 
-            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
-                let nonce = XNonce::from_slice(&src[..24]);
-                let chacha: XChaCha20Poly1305 = XChaCha20Poly1305::new(Key::from_slice(self.key.expose_secret()));
-                let data = chacha
-                    .decrypt(nonce, &src[24..])
-                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-                self.options
-                    .clone()
-                    .deserialize(&data)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            }
+    #[cfg(feature = "cbor")]
+    mod cbor_limits {
+        use crate::{formats::SymmetricalCbor, Deserializer, Serializer};
+        use bytes::BytesMut;
+        use serde_cbor::Value;
+        use std::{io, pin::Pin};
+
+        fn codec() -> SymmetricalCbor<Value> {
+            SymmetricalCbor::default().with_limits(16, 1024)
         }
 
-        impl<Item, SinkItem, O> Serializer<SinkItem> for EncryptedBincode<Item, SinkItem, O>
-        where
-            SinkItem: Serialize,
-            O: Options + Clone,
-        {
-            type Error = io::Error;
+        #[test]
+        fn within_limits() {
+            let value = Value::Array(vec![Value::Array(vec![Value::Integer(1); 1024]); 4]);
+            let buf = Pin::new(&mut codec()).serialize(&value).unwrap();
 
-            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
-                let mut nonce = XNonce::default();
-                let mut rng = OsRng::default();
-                rng.fill_bytes(&mut nonce);
-                let key = Key::from_slice(self.key.expose_secret());
-                let cipher = XChaCha20Poly1305::new(key);
-                let mut res = nonce.to_vec();
-                let ser = self
-                    .options
-                    .clone()
-                    .serialize(&item)
-                    .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
-                let mut other = cipher
-                    .encrypt(&nonce, ser.as_slice())
-                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-                res.append(&mut other);
-                Ok(Bytes::from(res))
+            let decoded = Pin::new(&mut codec())
+                .deserialize(&BytesMut::from(&buf[..]))
+                .unwrap();
+            assert_eq!(decoded, value);
+        }
+
+        #[test]
+        fn deeply_nested() {
+            // 32 nested single-element arrays, which serde_cbor alone accepts.
+            let mut frame = BytesMut::from(&[0x81; 32][..]);
+            frame.extend_from_slice(&[0x00]);
+            assert!(Pin::new(&mut SymmetricalCbor::<Value>::default())
+                .deserialize(&frame)
+                .is_ok());
+
+            let err = Pin::new(&mut codec()).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            // Indefinite-length arrays are nested just the same.
+            let mut frame = BytesMut::from(&[0x9f; 32][..]);
+            frame.extend_from_slice(&[0xff; 32]);
+            let err = Pin::new(&mut codec()).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn oversized_array() {
+            // An array declaring 2^20 elements, with none of them present.
+            let frame = BytesMut::from(&[0x9a, 0x00, 0x10, 0x00, 0x00][..]);
+            let err = Pin::new(&mut codec()).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            // An indefinite-length map with one entry too many.
+            let mut frame = BytesMut::from(&[0xbf][..]);
+            for i in 0..1025u16 {
+                frame.extend_from_slice(&[0x19]);
+                frame.extend_from_slice(&i.to_be_bytes());
+                frame.extend_from_slice(&[0xf6]);
             }
+            frame.extend_from_slice(&[0xff]);
+            let err = Pin::new(&mut codec()).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
         }
     }
-    #[cfg(feature = "bincode")]
-    mod bincode {
-        use super::*;
-        use bincode_crate::config::Options;
-        use serde::{Deserialize, Serialize};
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_impls() {
+        use impls::impls;
+        use std::fmt::Debug;
+
+        struct Nothing;
+        type T = crate::formats::Cbor<Nothing, Nothing>;
+
+        assert!(impls!(T: Debug));
+        assert!(impls!(T: Default));
+    }
+
+    #[cfg(all(
+        feature = "unified-error",
+        feature = "json",
+        feature = "bincode",
+        feature = "messagepack",
+        feature = "cbor"
+    ))]
+    #[test]
+    fn unified_error_types() {
+        use crate::{formats::*, Deserializer, Serializer};
+        use static_assertions::assert_type_eq_all;
         use std::io;
 
-        /// Bincode codec using [bincode](https://docs.rs/bincode) crate.
-        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
-        #[derive(Educe)]
-        #[educe(Debug)]
-        pub struct Bincode<Item, SinkItem, O = bincode_crate::DefaultOptions> {
-            #[educe(Debug(ignore))]
-            options: O,
-            #[educe(Debug(ignore))]
-            ghost: PhantomData<(Item, SinkItem)>,
+        type T = u32;
+
+        assert_type_eq_all!(
+            io::Error,
+            <Json<T, T> as Deserializer<T>>::Error,
+            <Json<T, T> as Serializer<T>>::Error,
+            <Bincode<T, T> as Deserializer<T>>::Error,
+            <MessagePack<T, T> as Deserializer<T>>::Error,
+            <Cbor<T, T> as Deserializer<T>>::Error,
+        );
+    }
+
+    #[cfg(all(feature = "unified-error", feature = "json"))]
+    #[test]
+    fn unified_error_json_position() {
+        use crate::formats::{JsonError, SymmetricalJson};
+        use std::error::Error;
+
+        let mut codec = SymmetricalJson::<Vec<u32>>::default();
+
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&b"[1,\n 2,\n x]"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let json = err.get_ref().unwrap().downcast_ref::<JsonError>().unwrap();
+        assert_eq!((json.line(), json.column()), (3, 2));
+        assert!(json.source().unwrap().is::<serde_json::Error>());
+    }
+
+    #[cfg(all(feature = "unified-error", feature = "json"))]
+    #[test]
+    fn unified_error_json_kind() {
+        use crate::{formats::SymmetricalJson, Deserializer};
+        use bytes::BytesMut;
+        use std::{io, pin::Pin};
+
+        let mut codec = SymmetricalJson::<u32>::default();
+
+        let res: crate::Result<u32> =
+            Pin::new(&mut codec).deserialize(&BytesMut::from(&b"\"nope\""[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[test]
+    fn enveloped_round_trip() {
+        use crate::{
+            combinators::{Envelope, EnvelopeMeta, Enveloped},
+            formats::{SymmetricalBincode, SymmetricalJson},
+            Deserializer, Serializer,
+        };
+        use bytes::BytesMut;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let mut codec = Enveloped::new(
+            SymmetricalJson::<Envelope<String>>::default(),
+            Duration::from_secs(30),
+        );
+
+        let buf = Pin::new(&mut codec).serialize(&"hello".to_owned()).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["ttl_ms"], 30_000);
+        assert_eq!(json["payload"], "hello");
+
+        let (meta, value) = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        assert!(meta.ts <= now && now - meta.ts < 5_000);
+        assert_eq!(meta.ts, json["ts"]);
+        assert_eq!(meta.ttl_ms, 30_000);
+        assert!(!meta.is_expired());
+        assert_eq!(value, "hello");
+
+        // Formats without field names read the envelope as a sequence.
+        let mut codec = Enveloped::new(
+            SymmetricalBincode::<Envelope<String>>::default(),
+            Duration::from_secs(30),
+        );
+        let buf = Pin::new(&mut codec).serialize(&"hello".to_owned()).unwrap();
+        let (meta, value) = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(meta.ttl_ms, 30_000);
+        assert_eq!(value, "hello");
+
+        let far_future = EnvelopeMeta {
+            ts: u64::MAX,
+            ttl_ms: u32::MAX,
+        };
+        assert!(!far_future.is_expired());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn enveloped_monotonic_rejects_stale_frames() {
+        use crate::{
+            combinators::{Envelope, Enveloped, StaleFrame},
+            formats::SymmetricalJson,
+        };
+        use std::time::Duration;
+
+        fn frame(ts: u64) -> BytesMut {
+            let frame = format!(r#"{{"ts":{},"ttl_ms":30000,"payload":"payload"}}"#, ts);
+            BytesMut::from(frame.as_bytes())
         }
 
-        impl<Item, SinkItem> Default for Bincode<Item, SinkItem> {
-            fn default() -> Self {
-                Bincode {
-                    options: Default::default(),
-                    ghost: PhantomData,
-                }
-            }
+        let mut codec = Enveloped::new(
+            SymmetricalJson::<Envelope<String>>::default(),
+            Duration::from_secs(30),
+        )
+        .monotonic(Duration::from_millis(50));
+
+        for &ts in &[1_000, 2_000] {
+            let (meta, _) = Pin::new(&mut codec).deserialize(&frame(ts)).unwrap();
+            assert_eq!(meta.ts, ts);
         }
 
-        impl<Item, SinkItem, O> From<O> for Bincode<Item, SinkItem, O>
-        where
-            O: Options,
-        {
-            fn from(options: O) -> Self {
-                Self {
-                    options,
-                    ghost: PhantomData,
-                }
+        let err = Pin::new(&mut codec).deserialize(&frame(1_500)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let stale = err.get_ref().unwrap().downcast_ref::<StaleFrame>().unwrap();
+        assert_eq!((stale.ts(), stale.last_ts()), (1_500, 2_000));
+
+        // Within the skew tolerance.
+        Pin::new(&mut codec).deserialize(&frame(1_960)).unwrap();
+        let (meta, _) = Pin::new(&mut codec).deserialize(&frame(3_000)).unwrap();
+        assert_eq!(meta.ts, 3_000);
+    }
+
+    #[test]
+    fn interleaved_lanes() {
+        use crate::combinators::{Interleaved, Lane};
+
+        const CONTROL: Lane = Lane(0);
+        const DATA: Lane = Lane(1);
+
+        let mut codec = Interleaved::new(Passthrough);
+
+        let control = Pin::new(&mut codec)
+            .serialize(&(CONTROL, Bytes::from_static(b"stop")))
+            .unwrap();
+        let data = Pin::new(&mut codec)
+            .serialize(&(DATA, Bytes::from_static(b"chunk")))
+            .unwrap();
+        assert_eq!(&control[..], b"\x00stop");
+        assert_eq!(&data[..], b"\x01chunk");
+
+        let decoded: (Lane, Bytes) = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&data[..]))
+            .unwrap();
+        assert_eq!(decoded, (DATA, Bytes::from_static(b"chunk")));
+
+        let decoded: (Lane, Bytes) = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&control[..]))
+            .unwrap();
+        assert_eq!(decoded, (CONTROL, Bytes::from_static(b"stop")));
+
+        let res: io::Result<(Lane, Bytes)> = Pin::new(&mut codec).deserialize(&BytesMut::new());
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_round_trip() {
+        use crate::combinators::{Alphabet, Base64};
+
+        let payload = Bytes::from_static(b"\xfb\xff binary");
+
+        let mut codec = Base64::new(Passthrough, Alphabet::Standard);
+        let buf = Pin::new(&mut codec).serialize(&payload).unwrap();
+        assert_eq!(&buf[..], b"+/8gYmluYXJ5");
+        let value = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(value, payload);
+
+        let mut codec = Base64::new(Passthrough, Alphabet::UrlSafe);
+        let buf = Pin::new(&mut codec).serialize(&payload).unwrap();
+        assert_eq!(&buf[..], b"-_8gYmluYXJ5");
+        let value = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(value, payload);
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_malformed() {
+        use crate::combinators::{Alphabet, Base64};
+
+        let mut codec = Base64::new(Passthrough, Alphabet::UrlSafe);
+
+        for frame in [&b"+/8gYmluYXJ5"[..], b"not base64!", b"YWJjZA="] {
+            let res: io::Result<Bytes> = Pin::new(&mut codec).deserialize(&BytesMut::from(frame));
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn named_round_trip() {
+        use crate::combinators::Named;
+
+        let mut codec = Named::new(Passthrough, "Ping");
+
+        let buf = Pin::new(&mut codec)
+            .serialize(&Bytes::from_static(b"payload"))
+            .unwrap();
+        assert_eq!(&buf[..], b"\x04Pingpayload");
+
+        let (name, value) = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(name, "Ping");
+        assert_eq!(value, "payload");
+
+        for frame in &[&b"\x09Ping"[..], &[0xff; 11][..], &b""[..]] {
+            let err = Pin::new(&mut codec)
+                .deserialize(&BytesMut::from(*frame))
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn open_enum_fallback() {
+        use crate::combinators::{OpenEnum, UnknownVariant};
+        use crate::formats::SymmetricalJson;
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "type")]
+        enum Event {
+            Ping {
+                seq: u32,
+            },
+            #[serde(skip)]
+            Unknown {
+                tag: String,
+                raw: Bytes,
+            },
+        }
+
+        impl UnknownVariant for Event {
+            fn unknown_variant(tag: String, raw: Bytes) -> Self {
+                Event::Unknown { tag, raw }
             }
         }
 
-        #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
-        pub type SymmetricalBincode<T, O = bincode_crate::DefaultOptions> = Bincode<T, T, O>;
+        let mut codec = OpenEnum::new(SymmetricalJson::<Event>::default());
 
-        impl<Item, SinkItem, O> Deserializer<Item> for Bincode<Item, SinkItem, O>
-        where
-            for<'a> Item: Deserialize<'a>,
-            O: Options + Clone,
-        {
-            type Error = io::Error;
+        let known = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&br#"{"type":"Ping","seq":1}"#[..]))
+            .unwrap();
+        assert_eq!(known, Event::Ping { seq: 1 });
 
-            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
-                self.options
-                    .clone()
-                    .deserialize(src)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        let frame = BytesMut::from(&br#"{"type":"Resize","w":80}"#[..]);
+        let unknown = Pin::new(&mut codec).deserialize(&frame).unwrap();
+        assert_eq!(
+            unknown,
+            Event::Unknown {
+                tag: "Resize".to_owned(),
+                raw: frame.freeze(),
             }
+        );
+
+        let res = Pin::new(&mut codec).deserialize(&BytesMut::from(&br#"{"type":"Ping"}"#[..]));
+        assert!(res.is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn open_enum_externally_tagged_cbor() {
+        use crate::combinators::{OpenEnum, UnknownVariant};
+        use crate::formats::SymmetricalCbor;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Shape {
+            Square(u32),
+            Circle { r: u32 },
         }
 
-        impl<Item, SinkItem, O> Serializer<SinkItem> for Bincode<Item, SinkItem, O>
-        where
-            SinkItem: Serialize,
-            O: Options + Clone,
-        {
-            type Error = io::Error;
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Event {
+            Ping,
+            Draw(Shape),
+            #[serde(skip)]
+            Unknown(String),
+        }
 
-            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
-                Ok(self
-                    .options
-                    .clone()
-                    .serialize(item)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-                    .into())
+        impl UnknownVariant for Event {
+            fn unknown_variant(tag: String, _: Bytes) -> Self {
+                Event::Unknown(tag)
             }
         }
+
+        #[derive(Serialize)]
+        enum Newer {
+            Resize { w: u32 },
+        }
+
+        let mut codec = OpenEnum::new(SymmetricalCbor::<Event>::default());
+        let mut decode =
+            |bytes: Vec<u8>| Pin::new(&mut codec).deserialize(&BytesMut::from(&bytes[..]));
+
+        let frame = serde_cbor::to_vec(&Event::Draw(Shape::Circle { r: 2 })).unwrap();
+        assert_eq!(decode(frame).unwrap(), Event::Draw(Shape::Circle { r: 2 }));
+        let frame = serde_cbor::to_vec(&Event::Ping).unwrap();
+        assert_eq!(decode(frame).unwrap(), Event::Ping);
+
+        let frame = serde_cbor::to_vec(&Newer::Resize { w: 80 }).unwrap();
+        assert_eq!(decode(frame).unwrap(), Event::Unknown("Resize".to_owned()));
+        let frame = serde_cbor::to_vec(&"Pong").unwrap();
+        assert_eq!(decode(frame).unwrap(), Event::Unknown("Pong".to_owned()));
+
+        // Only the top-level tag falls back; unknown nested variants are
+        // still errors.
+        let mut nested = serde_cbor::to_vec(&Event::Draw(Shape::Square(1))).unwrap();
+        let at = nested.windows(6).position(|w| w == b"Square").unwrap();
+        nested[at..at + 6].copy_from_slice(b"Hexagn");
+        let err = decode(nested).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 
     #[cfg(feature = "json")]
-    mod json {
-        use super::*;
-        use bytes::Buf;
+    #[test]
+    fn pointer_arc_and_box() {
+        use crate::combinators::Pointer;
+        use crate::formats::SymmetricalJson;
         use serde::{Deserialize, Serialize};
+        use std::sync::Arc;
 
-        /// JSON codec using [serde_json](https://docs.rs/serde_json) crate.
-        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
-        #[derive(Educe)]
-        #[educe(Debug, Default)]
-        pub struct Json<Item, SinkItem> {
-            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
-            ghost: PhantomData<(Item, SinkItem)>,
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Big {
+            id: u64,
+            data: Vec<u32>,
         }
 
-        #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
-        pub type SymmetricalJson<T> = Json<T, T>;
+        let big = Arc::new(Big {
+            id: 1,
+            data: (0..1024).collect(),
+        });
 
-        impl<Item, SinkItem> Deserializer<Item> for Json<Item, SinkItem>
-        where
-            for<'a> Item: Deserialize<'a>,
-        {
-            type Error = serde_json::Error;
+        let mut codec = Pointer::new(SymmetricalJson::<Big>::default());
+        let buf = Pin::new(&mut codec).serialize(&big).unwrap();
+        assert_eq!(Arc::strong_count(&big), 1);
 
-            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
-                serde_json::from_reader(std::io::Cursor::new(src).reader())
-            }
+        let decoded: Arc<Big> = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, big);
+
+        let boxed: Box<Big> = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(Pin::new(&mut codec).serialize(&boxed).unwrap(), buf);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn require_version_range() {
+        use crate::combinators::{RequireVersion, SchemaVersion};
+        use crate::formats::SymmetricalJson;
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Msg {
+            schema_version: u16,
+            body: String,
         }
 
-        impl<Item, SinkItem> Serializer<SinkItem> for Json<Item, SinkItem>
-        where
-            SinkItem: Serialize,
-        {
-            type Error = serde_json::Error;
+        let mut codec = RequireVersion::new(
+            SymmetricalJson::<Msg>::default(),
+            SymmetricalJson::<SchemaVersion>::default(),
+            2..=3,
+        );
 
-            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
-                serde_json::to_vec(item).map(Into::into)
+        let msg = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&br#"{"body":"hi","schema_version":3}"#[..]))
+            .unwrap();
+        assert_eq!(
+            msg,
+            Msg {
+                schema_version: 3,
+                body: "hi".to_owned(),
             }
-        }
+        );
+
+        // The version is rejected before the full decode, which would fail
+        // on the missing `body` field.
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&br#"{"schema_version":4}"#[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("schema version 4"));
     }
 
-    #[cfg(feature = "messagepack")]
-    mod messagepack {
-        use super::*;
-        use bytes::Buf;
-        use serde::{Deserialize, Serialize};
-        use std::io;
+    #[cfg(feature = "json")]
+    #[test]
+    fn result_codec_round_trip() {
+        use crate::combinators::ResultCodec;
+        use crate::formats::SymmetricalJson;
 
-        /// MessagePack codec using [rmp-serde](https://docs.rs/rmp-serde) crate.
-        #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
-        #[derive(Educe)]
-        #[educe(Debug, Default)]
-        pub struct MessagePack<Item, SinkItem> {
-            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
-            ghost: PhantomData<(Item, SinkItem)>,
-        }
+        let mut codec = ResultCodec::new(
+            SymmetricalJson::<u32>::default(),
+            SymmetricalJson::<String>::default(),
+        );
 
-        #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
-        pub type SymmetricalMessagePack<T> = MessagePack<T, T>;
+        let ok: Result<u32, String> = Ok(7);
+        let buf = Pin::new(&mut codec).serialize(&ok).unwrap();
+        assert_eq!(&buf[..], b"\x007");
+        let decoded: Result<u32, String> = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, ok);
 
-        impl<Item, SinkItem> Deserializer<Item> for MessagePack<Item, SinkItem>
-        where
-            for<'a> Item: Deserialize<'a>,
-        {
-            type Error = io::Error;
+        let err: Result<u32, String> = Err("denied".to_owned());
+        let buf = Pin::new(&mut codec).serialize(&err).unwrap();
+        assert_eq!(&buf[..], b"\x01\"denied\"");
+        let decoded: Result<u32, String> = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, err);
 
-            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
-                rmp_serde::from_read(std::io::Cursor::new(src).reader())
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            }
+        for frame in [&b""[..], b"\x027"] {
+            let res: io::Result<Result<u32, String>> =
+                Pin::new(&mut codec).deserialize(&BytesMut::from(frame));
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
         }
+    }
 
-        impl<Item, SinkItem> Serializer<SinkItem> for MessagePack<Item, SinkItem>
-        where
-            SinkItem: Serialize,
-        {
-            type Error = io::Error;
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[test]
+    fn tagged2_round_trip() {
+        use crate::combinators::{Tagged2, Variant2};
+        use crate::formats::{SymmetricalBincode, SymmetricalJson};
 
-            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
-                Ok(rmp_serde::to_vec(item)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-                    .into())
-            }
-        }
+        type Command = Variant2<u64, String>;
+
+        let mut codec = Tagged2::new(
+            SymmetricalBincode::<u64>::default(),
+            SymmetricalJson::<String>::default(),
+        );
+
+        let start: Command = Variant2::A(3);
+        let buf = Pin::new(&mut codec).serialize(&start).unwrap();
+        assert_eq!(&buf[..], b"\x00\x03");
+        let decoded: Command = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, start);
+
+        let stop: Command = Variant2::B("now".to_owned());
+        let buf = Pin::new(&mut codec).serialize(&stop).unwrap();
+        assert_eq!(&buf[..], b"\x01\"now\"");
+        let decoded: Command = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, stop);
+
+        let res: io::Result<Command> =
+            Pin::new(&mut codec).deserialize(&BytesMut::from(&b"\x02\x03"[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
 
-    #[cfg(feature = "cbor")]
-    mod cbor {
-        use super::*;
-        use serde::{Deserialize, Serialize};
-        use std::io;
+    #[cfg(feature = "json")]
+    #[test]
+    fn capture_and_replay_frames() {
+        use crate::{
+            combinators::{replay, Capture},
+            formats::SymmetricalJson,
+            SymmetricallyFramed,
+        };
+        use futures::{executor::block_on, stream, TryStreamExt};
 
-        /// CBOR codec using [serde_cbor](https://docs.rs/serde_cbor) crate.
-        #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
-        #[derive(Educe)]
-        #[educe(Debug, Default)]
-        pub struct Cbor<Item, SinkItem> {
-            #[educe(Debug(ignore), Default(expression = "PhantomData"))]
-            _mkr: PhantomData<(Item, SinkItem)>,
-        }
+        let frames: Vec<io::Result<BytesMut>> = vec![
+            Ok(BytesMut::from(&br#"{"id":1}"#[..])),
+            Ok(BytesMut::from(&br#"{"id":2}"#[..])),
+        ];
+        let mut capture = Vec::new();
+        let codec = Capture::new(
+            SymmetricalJson::<serde_json::Value>::default(),
+            &mut capture,
+        );
+        let framed = SymmetricallyFramed::new(stream::iter(frames), codec);
+        let decoded: Vec<_> = block_on(framed.try_collect()).unwrap();
+        assert_eq!(capture.len(), 2 * (4 + 8));
 
-        #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
-        pub type SymmetricalCbor<T> = Cbor<T, T>;
+        let codec = SymmetricalJson::<serde_json::Value>::default();
+        let framed = SymmetricallyFramed::new(replay(&capture[..]), codec);
+        let replayed: Vec<_> = block_on(framed.try_collect()).unwrap();
+        assert_eq!(replayed, decoded);
 
-        impl<Item, SinkItem> Deserializer<Item> for Cbor<Item, SinkItem>
-        where
-            for<'a> Item: Deserialize<'a>,
-        {
-            type Error = io::Error;
+        let truncated = replay(&capture[..6]);
+        let err = block_on(truncated.try_collect::<Vec<_>>()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
 
-            fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<Item, Self::Error> {
-                serde_cbor::from_slice(src.as_ref()).map_err(into_io_error)
-            }
-        }
+    #[cfg(all(feature = "length-delimited", feature = "json"))]
+    #[tokio::test]
+    async fn length_delimited_little_endian_prefix() {
+        use crate::{formats::SymmetricalJson, length_delimited, SymmetricallyFramed};
+        use futures::{SinkExt, TryStreamExt};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-        impl<Item, SinkItem> Serializer<SinkItem> for Cbor<Item, SinkItem>
-        where
-            SinkItem: Serialize,
-        {
-            type Error = io::Error;
+        let builder = length_delimited::Builder::new()
+            .with_length_field_length(2)
+            .with_big_endian(false);
 
-            fn serialize(self: Pin<&mut Self>, item: &SinkItem) -> Result<Bytes, Self::Error> {
-                serde_cbor::to_vec(item)
-                    .map_err(into_io_error)
-                    .map(Into::into)
-            }
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut framed: SymmetricallyFramed<_, String, _> =
+            builder.framed(client, SymmetricalJson::default());
+        framed.send("hi".to_owned()).await.unwrap();
+
+        let mut raw = [0; 6];
+        server.read_exact(&mut raw).await.unwrap();
+        assert_eq!(&raw, b"\x04\x00\"hi\"");
+
+        server.write_all(b"\x05\x00\"bye\"").await.unwrap();
+        let reply = framed.try_next().await.unwrap();
+        assert_eq!(reply.as_deref(), Some("bye"));
+    }
+
+    #[cfg(feature = "padded")]
+    #[test]
+    fn padded_frames_land_on_buckets() {
+        use crate::combinators::Padded;
+
+        let mut codec = Padded::new(Passthrough, vec![256, 64, 1024]);
+        assert_eq!(codec.sizes(), [64, 256, 1024]);
+
+        for (len, bucket) in [(0, 64), (60, 64), (61, 256), (500, 1024)] {
+            let payload = Bytes::from(vec![7; len]);
+            let buf = Pin::new(&mut codec).serialize(&payload).unwrap();
+            assert_eq!(buf.len(), bucket);
+
+            let decoded: Bytes = Pin::new(&mut codec)
+                .deserialize(&BytesMut::from(&buf[..]))
+                .unwrap();
+            assert_eq!(decoded, payload);
         }
 
-        fn into_io_error(cbor_err: serde_cbor::Error) -> io::Error {
-            use io::ErrorKind;
-            use serde_cbor::error::Category;
-            use std::error::Error;
+        let res = Pin::new(&mut codec).serialize(&Bytes::from(vec![0; 1021]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
 
-            match cbor_err.classify() {
-                Category::Eof => io::Error::new(ErrorKind::UnexpectedEof, cbor_err),
-                Category::Syntax => io::Error::new(ErrorKind::InvalidInput, cbor_err),
-                Category::Data => io::Error::new(ErrorKind::InvalidData, cbor_err),
-                Category::Io => {
-                    // Extract the underlying io error's type
-                    let kind = cbor_err
-                        .source()
-                        .and_then(|err| err.downcast_ref::<io::Error>())
-                        .map(|io_err| io_err.kind())
-                        .unwrap_or(ErrorKind::Other);
-                    io::Error::new(kind, cbor_err)
-                }
-            }
+        let res: io::Result<Bytes> =
+            Pin::new(&mut codec).deserialize(&BytesMut::from(&b"\x00\x00\x00\x09short"[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn smart_compress_with_dictionary() {
+        use crate::combinators::SmartCompress;
+
+        let dictionary: Vec<u8> = (0..20)
+            .flat_map(|i| {
+                format!(
+                    r#"{{"user_id":{},"event":"page_view","path":"/products/{}","ok":true}}"#,
+                    1000 + i,
+                    i
+                )
+                .into_bytes()
+            })
+            .collect();
+        let frames: Vec<Bytes> = (0..5)
+            .map(|i| {
+                let frame = format!(
+                    r#"{{"user_id":{},"event":"page_view","path":"/products/{}","ok":true}}"#,
+                    4242 + i,
+                    77 + i
+                );
+                Bytes::from(frame)
+            })
+            .collect();
+
+        let mut plain = SmartCompress::new(Passthrough).with_level(19);
+        let mut with_dict =
+            SmartCompress::with_dictionary(Passthrough, 3, dictionary.clone()).with_level(19);
+
+        for frame in &frames {
+            let plain_buf = Pin::new(&mut plain).serialize(frame).unwrap();
+            let dict_buf = Pin::new(&mut with_dict).serialize(frame).unwrap();
+            assert!(dict_buf.len() < plain_buf.len() / 2);
+            assert_eq!(dict_buf[0], 1);
+
+            let decoded: Bytes = Pin::new(&mut with_dict)
+                .deserialize(&BytesMut::from(&dict_buf[..]))
+                .unwrap();
+            assert_eq!(&decoded, frame);
+
+            let res: io::Result<Bytes> =
+                Pin::new(&mut plain).deserialize(&BytesMut::from(&dict_buf[..]));
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
         }
+
+        let mut capped = SmartCompress::with_dictionary(Passthrough, 3, dictionary)
+            .with_max_decompressed_len(frames[0].len() - 1);
+        let buf = Pin::new(&mut capped).serialize(&frames[0]).unwrap();
+        let res: io::Result<Bytes> = Pin::new(&mut capped).deserialize(&BytesMut::from(&buf[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    #[cfg(feature = "encrypted_bincode")]
+    #[cfg(feature = "zstd")]
     #[test]
-    fn enc_bincode_impls() {
-        use impls::impls;
-        use std::fmt::Debug;
+    fn smart_compress_picks_smaller_form() {
+        use crate::combinators::SmartCompress;
 
-        struct Nothing;
-        type T = crate::formats::EncryptedBincode<Nothing, Nothing>;
+        let mut codec = SmartCompress::new(Passthrough);
 
-        assert!(impls!(T: Debug));
-        assert!(impls!(T: Default));
+        // xorshift output doesn't compress.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let noise: Bytes = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let buf = Pin::new(&mut codec).serialize(&noise).unwrap();
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf.len(), noise.len() + 1);
+        let decoded: Bytes = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, noise);
+
+        let zeros = Bytes::from(vec![0; 4096]);
+        let buf = Pin::new(&mut codec).serialize(&zeros).unwrap();
+        assert_eq!(buf[0], 1);
+        assert!(buf.len() < zeros.len() / 10);
+        let decoded: Bytes = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, zeros);
+
+        let res: io::Result<Bytes> =
+            Pin::new(&mut codec).deserialize(&BytesMut::from(&b"\x02payload"[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut codec = SmartCompress::new(Passthrough).with_max_decompressed_len(1024);
+        let res: io::Result<Bytes> = Pin::new(&mut codec).deserialize(&BytesMut::from(&buf[..]));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
-    #[cfg(feature = "bincode")]
+
+    #[cfg(all(feature = "secure_stack", feature = "json"))]
     #[test]
-    fn bincode_impls() {
-        use impls::impls;
-        use std::fmt::Debug;
+    fn secure_stack_compresses_then_encrypts() {
+        use crate::{combinators::SecureStack, formats::SymmetricalJson};
+        use std::collections::BTreeMap;
 
-        struct Nothing;
-        type T = crate::formats::Bincode<Nothing, Nothing>;
+        type Event = BTreeMap<String, Vec<String>>;
 
-        assert!(impls!(T: Debug));
-        assert!(impls!(T: Default));
+        let mut sender = SecureStack::new(SymmetricalJson::<Event>::default(), [3; 32]);
+        let mut receiver = SecureStack::new(SymmetricalJson::<Event>::default(), [3; 32]);
+
+        let event: Event = (0..32)
+            .map(|i| {
+                let lines = (0..8)
+                    .map(|j| format!("worker {} finished job {} with status ok", i, j))
+                    .collect();
+                (format!("host-{:02}.example.com", i), lines)
+            })
+            .collect();
+        let plain = serde_json::to_vec(&event).unwrap();
+
+        let buf = Pin::new(&mut sender).serialize(&event).unwrap();
+        assert!(buf.len() < plain.len() / 4);
+        assert!(!buf.windows(6).any(|w| w == b"worker"));
+
+        let recompressed = zstd::bulk::compress(&buf, 19).unwrap();
+        assert!(recompressed.len() >= buf.len());
+
+        let decoded = Pin::new(&mut receiver)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, event);
+
+        let mut tampered = BytesMut::from(&buf[..]);
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        let err = Pin::new(&mut receiver).deserialize(&tampered).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut capped = SecureStack::new(SymmetricalJson::<Event>::default(), [3; 32])
+            .with_max_decompressed_len(plain.len() - 1);
+        let err = Pin::new(&mut capped)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 
-    #[cfg(feature = "json")]
+    #[cfg(all(feature = "transcode", feature = "json", feature = "cbor"))]
     #[test]
-    fn json_impls() {
-        use impls::impls;
-        use std::fmt::Debug;
+    fn transcode_json_cbor() {
+        use crate::formats::{CborFormat, JsonFormat, Transcode};
+        use serde::{Deserialize, Serialize};
 
-        struct Nothing;
-        type T = crate::formats::Json<Nothing, Nothing>;
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Reading {
+            sensor: String,
+            values: Vec<i32>,
+            ok: bool,
+        }
 
-        assert!(impls!(T: Debug));
-        assert!(impls!(T: Default));
+        let reading = Reading {
+            sensor: "t-1".to_owned(),
+            values: vec![-3, 0, 1 << 20],
+            ok: true,
+        };
+        let json = serde_json::to_vec(&reading).unwrap();
+
+        let mut codec = Transcode::<JsonFormat, CborFormat>::default();
+        let cbor = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&json[..]))
+            .unwrap();
+        assert_eq!(serde_cbor::from_slice::<Reading>(&cbor).unwrap(), reading);
+
+        let back = Pin::new(&mut codec).serialize(&cbor).unwrap();
+        assert_eq!(serde_json::from_slice::<Reading>(&back).unwrap(), reading);
+
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&b"{} {}"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 
-    #[cfg(feature = "messagepack")]
+    #[cfg(all(feature = "gzip", feature = "json"))]
     #[test]
-    fn messagepack_impls() {
-        use impls::impls;
-        use std::fmt::Debug;
+    fn maybe_gzip_sniffs_frames() {
+        use crate::{combinators::MaybeGzip, formats::SymmetricalJson};
 
-        struct Nothing;
-        type T = crate::formats::MessagePack<Nothing, Nothing>;
+        let value: Vec<String> = (0..64).map(|i| format!("record {}", i % 4)).collect();
+        let plain = serde_json::to_vec(&value).unwrap();
 
-        assert!(impls!(T: Debug));
-        assert!(impls!(T: Default));
+        let mut codec = MaybeGzip::new(SymmetricalJson::<Vec<String>>::default());
+        let buf = Pin::new(&mut codec).serialize(&value).unwrap();
+        assert_eq!(&buf[..2], [0x1f, 0x8b]);
+        assert!(buf.len() < plain.len());
+
+        let compressed = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(compressed, value);
+
+        let decoded = Pin::new(&mut codec)
+            .deserialize_owned(BytesMut::from(&plain[..]))
+            .unwrap();
+        assert_eq!(decoded, value);
+
+        let mut codec = codec.with_compression(false);
+        let buf = Pin::new(&mut codec).serialize(&value).unwrap();
+        assert_eq!(buf, plain);
+
+        let err = Pin::new(&mut codec)
+            .deserialize(&BytesMut::from(&b"\x1f\x8bgarbage"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let codec = MaybeGzip::new(SymmetricalJson::<Vec<String>>::default());
+        let mut exact = codec.with_max_decompressed_len(plain.len());
+        let buf = Pin::new(&mut exact).serialize(&value).unwrap();
+        let decoded = Pin::new(&mut exact)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, value);
+
+        let mut capped = exact.with_max_decompressed_len(plain.len() - 1);
+        let err = Pin::new(&mut capped)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 
-    #[cfg(feature = "cbor")]
+    #[cfg(all(feature = "partially_encrypted", feature = "json"))]
     #[test]
-    fn cbor_impls() {
-        use impls::impls;
-        use std::fmt::Debug;
+    fn partially_encrypted_round_trip() {
+        use crate::{combinators::PartiallyEncrypted, formats::SymmetricalJson};
 
-        struct Nothing;
-        type T = crate::formats::Cbor<Nothing, Nothing>;
+        type Codec = PartiallyEncrypted<SymmetricalJson<String>, SymmetricalJson<Vec<u32>>>;
 
-        assert!(impls!(T: Debug));
-        assert!(impls!(T: Default));
+        let codec = || -> Codec {
+            PartiallyEncrypted::new(
+                SymmetricalJson::default(),
+                SymmetricalJson::default(),
+                [5; 32],
+            )
+        };
+        let mut sender = codec();
+        let mut receiver = codec();
+
+        let item = ("eu-west".to_owned(), vec![4, 8, 15, 16, 23, 42]);
+        let buf = Pin::new(&mut sender).serialize(&item).unwrap();
+        assert_eq!(Codec::header_bytes(&buf).unwrap(), br#""eu-west""#);
+        assert!(!buf.windows(2).any(|w| w == b"42"));
+
+        let decoded = Pin::new(&mut receiver)
+            .deserialize(&BytesMut::from(&buf[..]))
+            .unwrap();
+        assert_eq!(decoded, item);
+
+        let err = Pin::new(&mut receiver)
+            .deserialize(&BytesMut::from(&buf[..10]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(all(feature = "partially_encrypted", feature = "json"))]
+    #[test]
+    fn partially_encrypted_binds_header() {
+        use crate::{combinators::PartiallyEncrypted, formats::SymmetricalJson};
+
+        let mut codec = PartiallyEncrypted::new(
+            SymmetricalJson::<String>::default(),
+            SymmetricalJson::<u32>::default(),
+            [5; 32],
+        );
+
+        let buf = Pin::new(&mut codec)
+            .serialize(&("eu-west".to_owned(), 7))
+            .unwrap();
+
+        // Reroute the frame by swapping the header for one of the same length.
+        let mut tampered = BytesMut::from(&buf[..]);
+        let start = tampered.iter().position(|&b| b == b'w').unwrap();
+        tampered[start..start + 4].copy_from_slice(b"east");
+        assert_eq!(
+            PartiallyEncrypted::<SymmetricalJson<String>, SymmetricalJson<u32>>::header_bytes(
+                &tampered
+            )
+            .unwrap(),
+            br#""eu-east""#
+        );
+
+        let err = Pin::new(&mut codec).deserialize(&tampered).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(all(feature = "hmac", feature = "json"))]
+    mod signed {
+        use crate::{combinators::Signed, formats::SymmetricalJson, Deserializer, Serializer};
+        use bytes::BytesMut;
+        use std::{io, pin::Pin};
+
+        type Codec = Signed<SymmetricalJson<String>>;
+
+        fn codec() -> Codec {
+            Signed::new(SymmetricalJson::default(), b"shared secret")
+        }
+
+        fn signed_frame() -> BytesMut {
+            let frame = Pin::new(&mut codec())
+                .serialize(&"hello".to_owned())
+                .unwrap();
+            BytesMut::from(&frame[..])
+        }
+
+        #[test]
+        fn valid_signature() {
+            let frame = signed_frame();
+            assert_eq!(&frame[..frame.len() - 32], b"\"hello\"");
+
+            let value: String = Pin::new(&mut codec()).deserialize(&frame).unwrap();
+            assert_eq!(value, "hello");
+        }
+
+        #[test]
+        fn tampered_payload() {
+            let mut frame = signed_frame();
+            frame[1] = b'j';
+
+            let err = Pin::new(&mut codec()).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn tampered_tag() {
+            let mut frame = signed_frame();
+            let last = frame.len() - 1;
+            frame[last] ^= 1;
+
+            let err = Pin::new(&mut codec()).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn wrong_key() {
+            let frame = signed_frame();
+            let mut other = Signed::new(SymmetricalJson::<String>::default(), b"other secret");
+
+            let err = Pin::new(&mut other).deserialize(&frame).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
     }
 }
@@ -0,0 +1,92 @@
+//! Checks that small frames are serialized with a single allocation.
+//!
+//! This installs a counting global allocator, so it runs as its own test
+//! binary rather than alongside the unit tests.
+
+#![cfg(any(feature = "json", feature = "messagepack", feature = "cbor"))]
+
+use bytes::Bytes;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    pin::Pin,
+};
+use tokio_serde::Serializer;
+
+/// Counts the allocations made by the current thread, so tests
+/// running in parallel don't disturb each other.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn serialize_counting<C, T>(mut codec: C, item: &T) -> (Bytes, usize)
+where
+    C: Serializer<T> + Unpin,
+    C::Error: std::fmt::Debug,
+{
+    let before = ALLOCATIONS.with(Cell::get);
+    let buf = Pin::new(&mut codec).serialize(item).unwrap();
+    (buf, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_small_frame_allocates_once() {
+    use tokio_serde::formats::SymmetricalJson;
+
+    let (buf, allocations) =
+        serialize_counting(SymmetricalJson::<(u8, bool)>::default(), &(1, true));
+    assert_eq!(&buf[..], b"[1,true]");
+    assert_eq!(allocations, 1);
+
+    let large: Vec<u32> = (0..1000).collect();
+    let (buf, _) = serialize_counting(SymmetricalJson::<Vec<u32>>::default(), &large);
+    assert_eq!(serde_json::from_slice::<Vec<u32>>(&buf).unwrap(), large);
+}
+
+#[cfg(feature = "messagepack")]
+#[test]
+fn messagepack_small_frame_allocates_once() {
+    use tokio_serde::formats::SymmetricalMessagePack;
+
+    let codec = SymmetricalMessagePack::<(u8, bool)>::default();
+    let (buf, allocations) = serialize_counting(codec, &(1, true));
+    assert_eq!(&buf[..], b"\x92\x01\xc3");
+    assert_eq!(allocations, 1);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_small_frame_allocates_once() {
+    use tokio_serde::formats::SymmetricalCbor;
+
+    let codec = SymmetricalCbor::<(u8, bool)>::default();
+    let (buf, allocations) = serialize_counting(codec, &(1, true));
+    assert_eq!(&buf[..], b"\x82\x01\xf5");
+    assert_eq!(allocations, 1);
+}